@@ -0,0 +1,200 @@
+use crate::{
+    config::{CleanConfig, Opts},
+    info, success,
+    types::PkgVersion,
+};
+
+use anyhow::Result;
+use indicatif::HumanBytes;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+pub fn clean(opts: &Opts, cleanconfig: &CleanConfig) -> Result<()> {
+    let pkg_cache_path = opts.root.join(crate::PKG_CACHE_PATH);
+    let older_than = cleanconfig.older_than;
+    if older_than.is_some() || cleanconfig.keep_latest {
+        info!("Pruning local package cache...");
+        let (files, bytes) = prune_pkg_cache(&pkg_cache_path, older_than, cleanconfig.keep_latest)?;
+        success!("Removed {} file(s), freeing {}.", files, HumanBytes(bytes));
+    } else {
+        info!("Purging local package cache...");
+        if pkg_cache_path.is_dir() {
+            fs::remove_dir_all(&pkg_cache_path)?;
+            fs::create_dir_all(&pkg_cache_path)?;
+        }
+    }
+
+    if cleanconfig.all {
+        info!("Purging local metadata cache...");
+        let db_cache_path = opts.root.join(crate::DB_CACHE_PATH);
+        if db_cache_path.is_dir() {
+            fs::remove_dir_all(&db_cache_path)?;
+            fs::create_dir_all(&db_cache_path)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Delete cached package files that are older than `older_than` and/or superseded by a newer
+/// cached version of the same package. A file must match every given criterion to be deleted.
+/// Returns (number of files, bytes) freed
+fn prune_pkg_cache(
+    dir: &Path,
+    older_than: Option<Duration>,
+    keep_latest: bool,
+) -> Result<(usize, u64)> {
+    if !dir.is_dir() {
+        return Ok((0, 0));
+    }
+
+    // (path, size, last modified, parsed filename)
+    let mut files: Vec<(PathBuf, u64, SystemTime, Option<(String, PkgVersion)>)> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let parsed = entry.file_name().to_str().and_then(parse_pkg_filename);
+        files.push((entry.path(), metadata.len(), metadata.modified()?, parsed));
+    }
+
+    let mut latest: HashMap<&str, &PkgVersion> = HashMap::new();
+    for (_, _, _, parsed) in &files {
+        if let Some((name, version)) = parsed {
+            let entry = latest.entry(name.as_str()).or_insert(version);
+            if version > *entry {
+                *entry = version;
+            }
+        }
+    }
+
+    let now = SystemTime::now();
+    let mut freed = (0, 0);
+    for (path, size, modified, parsed) in &files {
+        if let Some(older_than) = older_than {
+            // Files from the future are not old
+            if now.duration_since(*modified).unwrap_or_default() <= older_than {
+                continue;
+            }
+        }
+        if keep_latest {
+            match parsed {
+                Some((name, version)) if version < latest[name.as_str()] => (),
+                _ => continue,
+            }
+        }
+        fs::remove_file(path)?;
+        freed.0 += 1;
+        freed.1 += size;
+    }
+
+    Ok(freed)
+}
+
+/// Get name and version from a package file name like `name-ver-rel-arch.pkg.tar.zst`
+/// Signatures (`*.pkg.tar.zst.sig`) belong to the same package
+fn parse_pkg_filename(filename: &str) -> Option<(String, PkgVersion)> {
+    let stem = &filename[..filename.find(".pkg.tar")?];
+    let mut parts = stem.rsplitn(4, '-');
+    let _arch = parts.next()?;
+    let rel = parts.next()?;
+    let ver = parts.next()?;
+    let name = parts.next()?;
+    let version = PkgVersion::try_from(format!("{ver}-{rel}").as_str()).ok()?;
+    Some((name.to_owned(), version))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test::TempDir;
+
+    #[test]
+    fn parse_filename() {
+        let (name, version) =
+            parse_pkg_filename("lib32-glibc-1:2.38-7-x86_64.pkg.tar.zst").unwrap();
+        assert_eq!(name, "lib32-glibc");
+        assert_eq!(version, PkgVersion::try_from("1:2.38-7").unwrap());
+        assert!(parse_pkg_filename("foo-1-1-any.pkg.tar.zst.sig").is_some());
+        assert!(parse_pkg_filename("core.db").is_none());
+    }
+
+    #[test]
+    fn prune() {
+        let dir = TempDir::new();
+        let populate = || {
+            fs::create_dir_all(&dir).unwrap();
+            let day = Duration::from_secs(24 * 3600);
+            for (name, days_old) in [
+                ("foo-1.0-1-x86_64.pkg.tar.zst", 10),
+                ("foo-1.1-1-x86_64.pkg.tar.zst", 10),
+                ("foo-1.2-1-x86_64.pkg.tar.zst", 1),
+                ("bar-2.0-1-any.pkg.tar.zst", 10),
+                ("bar-1.0-1-any.pkg.tar.zst", 1),
+                ("unknown", 10),
+            ] {
+                let path = dir.join(name);
+                fs::write(&path, name).unwrap();
+                let f = fs::File::options().write(true).open(&path).unwrap();
+                f.set_modified(SystemTime::now() - day * days_old).unwrap();
+            }
+        };
+        let remaining = || {
+            let mut names: Vec<String> = fs::read_dir(&dir)
+                .unwrap()
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect();
+            names.sort();
+            names
+        };
+
+        populate();
+        let (files, bytes) =
+            prune_pkg_cache(&dir, Some(Duration::from_secs(5 * 24 * 3600)), false).unwrap();
+        assert_eq!(files, 4);
+        assert_eq!(
+            bytes,
+            [
+                "foo-1.0-1-x86_64.pkg.tar.zst",
+                "foo-1.1-1-x86_64.pkg.tar.zst",
+                "bar-2.0-1-any.pkg.tar.zst",
+                "unknown"
+            ]
+            .iter()
+            .map(|name| name.len() as u64)
+            .sum::<u64>()
+        );
+        assert_eq!(remaining(), vec!["bar-1.0-1-any.pkg.tar.zst", "foo-1.2-1-x86_64.pkg.tar.zst"]);
+        fs::remove_dir_all(&dir).unwrap();
+
+        populate();
+        let (files, _) = prune_pkg_cache(&dir, None, true).unwrap();
+        assert_eq!(files, 3);
+        assert_eq!(
+            remaining(),
+            vec!["bar-2.0-1-any.pkg.tar.zst", "foo-1.2-1-x86_64.pkg.tar.zst", "unknown"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Both: only old and superseded files
+        populate();
+        let (files, _) =
+            prune_pkg_cache(&dir, Some(Duration::from_secs(5 * 24 * 3600)), true).unwrap();
+        assert_eq!(files, 2);
+        assert_eq!(
+            remaining(),
+            vec![
+                "bar-1.0-1-any.pkg.tar.zst",
+                "bar-2.0-1-any.pkg.tar.zst",
+                "foo-1.2-1-x86_64.pkg.tar.zst",
+                "unknown"
+            ]
+        );
+    }
+}
@@ -1,50 +1,30 @@
 use crate::{
-    db::LocalDb,
-    info, pool,
-    types::{Checksum, PkgSource},
+    config::CachedRepoDb,
+    info,
+    solver::pool::BasicPkgPool,
+    success,
+    types::{Checksum, PkgMeta, PkgSource},
     utils::downloader::{Compression, DownloadJob, Downloader},
 };
 
 use anyhow::{bail, Context, Result};
 use console::style;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
+/// Download a package file to `output`, or copy it from `cache_dir` if a valid copy is there
 pub async fn download(
     pkgname: &str,
-    local_db: &LocalDb,
+    repo_db: &CachedRepoDb,
     downloader: &Downloader,
     latest: bool,
+    output: &Path,
+    cache_dir: &Path,
 ) -> Result<()> {
-    let dbs = local_db.get_all_package_db().context("Invalid local package database")?;
-    let pool = pool::source::create_pool(&dbs, &[])?;
-
-    // Get all versions
-    // Choices: Vec<(DisplayString, URL)>
-    let mut choices: Vec<(String, String, u64, Checksum)> = Vec::new();
-    if let Some(ids) = pool.get_pkgs_by_name(pkgname) {
-        let mut first = true;
-        for id in ids {
-            let meta = pool.get_pkg_by_id(id).unwrap();
-            let (url, size, checksum) = match &meta.source {
-                PkgSource::Http((url, size, checksum)) => (url, size, checksum),
-                // This should never happen
-                PkgSource::Local(_) => panic!("Local source from http repo"),
-            };
-            // Form version str for display
-            let mut version_str = meta.version.to_string();
-            let mut info_segments = Vec::new();
-            if first {
-                info_segments.push(style("latest").green().to_string());
-            }
-            if !info_segments.is_empty() {
-                version_str.push_str(&format!(" ({})", info_segments.join(", ")));
-            }
-            choices.push((version_str, url.to_owned(), *size, checksum.to_owned()));
-            // Not the first anymore
-            first = false;
-        }
-    } else {
-        bail!("Package {} not found", style(pkgname).bold());
-    }
+    let pool = super::load_pool(repo_db)?;
+    let choices = get_choices(&pool, pkgname)?;
 
     // Display them
     let choices_str: Vec<&str> = choices.iter().map(|ver| ver.0.as_str()).collect();
@@ -52,27 +32,141 @@ pub async fn download(
         0
     } else {
         info!("Please choose a version for {}:", style(pkgname).bold());
-        dialoguer::Select::with_theme(&crate::cli::OmaTheme::default())
+        dialoguer::Select::with_theme(&crate::utils::cli::SasmTheme::default())
             .items(&choices_str)
             .default(0)
             .interact()?
     };
 
-    let (_, url, size, checksum) = &choices[i];
+    let meta = choices[i].1;
+    let (url, size, checksum) = match &meta.source {
+        PkgSource::Http((url, size, checksum)) => (url, size, checksum),
+        PkgSource::Local(path) => bail!(
+            "Package {} {} is a local package at {}, nothing to download.",
+            style(pkgname).bold(),
+            meta.version,
+            path.display()
+        ),
+    };
+    let filename = match url.rsplit('/').next() {
+        Some(filename) if !filename.is_empty() => filename.to_owned(),
+        _ => bail!("{} doesn't contain filename.", url),
+    };
+    if let Some(path) = reuse_cached(cache_dir, output, &filename, checksum)? {
+        success!("Package copied from local cache to {}.", style(path.display()).bold());
+        return Ok(());
+    }
+
     let job = DownloadJob {
         url: url.to_owned(),
+        fallback_urls: Vec::new(),
+        current_url: None,
         description: None,
-        filename: None,
+        filename: Some(filename),
         size: Some(*size),
         compression: Compression::None(Some(checksum.clone())),
+        conditional: false,
     };
-
-    // Download package to current directory
-    let current_dir = std::env::current_dir().context("Failed to get current directory.")?;
-    downloader
-        .fetch([job].to_vec(), &current_dir, true)
+    let res = downloader
+        .fetch(vec![job], output, false)
         .await
         .context("Failed to fetch request package from repository.")?;
+    for path in res.values() {
+        success!("Package downloaded to {}.", style(path.display()).bold());
+    }
 
     Ok(())
 }
+
+/// Get all versions of a package, most preferred first
+/// Returns Vec<(DisplayString, PkgMeta)>
+fn get_choices<'a, P: BasicPkgPool + ?Sized>(
+    pool: &'a P,
+    pkgname: &str,
+) -> Result<Vec<(String, &'a PkgMeta)>> {
+    let ids = match pool.get_pkgs_by_name(pkgname) {
+        Some(ids) => ids,
+        None => bail!("Package {} not found.", style(pkgname).bold()),
+    };
+    let mut choices = Vec::with_capacity(ids.len());
+    for (i, id) in ids.into_iter().enumerate() {
+        let meta = pool.get_pkg_by_id(id).unwrap();
+        // Form version str for display
+        let mut version_str = meta.version.to_string();
+        if i == 0 {
+            version_str.push_str(&format!(" ({})", style("latest").green()));
+        }
+        choices.push((version_str, meta));
+    }
+    Ok(choices)
+}
+
+/// Put the package file in `cache_dir` into `output`, if it matches the checksum
+/// Hard link it if possible, copy otherwise. Returns the resulting path
+fn reuse_cached(
+    cache_dir: &Path,
+    output: &Path,
+    filename: &str,
+    checksum: &Checksum,
+) -> Result<Option<PathBuf>> {
+    let cached = cache_dir.join(filename);
+    if !cached.is_file() || !checksum.cmp_file(&cached)? {
+        return Ok(None);
+    }
+    let dest = output.join(filename);
+    if dest.is_file() && checksum.cmp_file(&dest)? {
+        return Ok(Some(dest));
+    }
+    fs::create_dir_all(output)?;
+    if dest.exists() {
+        fs::remove_file(&dest)?;
+    }
+    if fs::hard_link(&cached, &dest).is_err() {
+        fs::copy(&cached, &dest)
+            .context(format!("Failed to copy {} from local cache.", filename))?;
+    }
+    Ok(Some(dest))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{solver::pool::InMemoryPool, utils::test::TempDir};
+
+    #[test]
+    fn cached() {
+        let dir = TempDir::new();
+        let cache_dir = dir.join("cache");
+        let output = dir.join("out/custom");
+        fs::create_dir_all(&cache_dir).unwrap();
+        let filename = "foo-1.0-1-x86_64.pkg.tar.zst";
+        fs::write(cache_dir.join(filename), "foo").unwrap();
+        let checksum = Checksum::from_file_sha256(&cache_dir.join(filename)).unwrap();
+
+        // Written to the custom directory, which is created if missing
+        let path = reuse_cached(&cache_dir, &output, filename, &checksum).unwrap().unwrap();
+        assert_eq!(path, output.join(filename));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "foo");
+        // Reusing again is fine
+        assert_eq!(reuse_cached(&cache_dir, &output, filename, &checksum).unwrap(), Some(path));
+
+        // Corrupt or missing cached files need downloading
+        fs::write(cache_dir.join(filename), "bar").unwrap();
+        assert_eq!(reuse_cached(&cache_dir, &dir, filename, &checksum).unwrap(), None);
+        assert_eq!(reuse_cached(&cache_dir, &dir, "missing", &checksum).unwrap(), None);
+    }
+
+    #[test]
+    fn local_versions() {
+        let mut pool = InMemoryPool::new();
+        pool.add(PkgMeta::test("foo", "2"));
+        pool.add(PkgMeta::test_remote("foo", "1"));
+        pool.finalize();
+
+        // A local version doesn't keep other versions from being downloaded
+        let choices = get_choices(&pool, "foo").unwrap();
+        assert_eq!(choices.len(), 2);
+        assert!(choices.iter().any(|(_, meta)| matches!(meta.source, PkgSource::Http(_))));
+        assert!(get_choices(&pool, "bar").is_err());
+    }
+}
@@ -1,22 +1,20 @@
-use super::UserRequest;
+use super::{RemoveRequest, UserRequest};
 use crate::{
-    config::{Blueprints, CachedRepoDb, Config, Opts},
-    debug,
+    config::{Blueprints, CachedRepoDb, Config, Opts, ResolveOpts},
+    debug, error,
     executor::MachineStatus,
     info,
-    solver::{
-        pool::{self, PkgPool},
-        Solver,
-    },
+    solver::{pool::PkgPool, Solver},
     success,
-    types::PkgActionModifier,
+    types::{PinPolicy, PkgActionModifier, PkgVersion, VersionRequirement},
     utils::cli::{self, ask_confirm},
     utils::downloader::Downloader,
     warn,
 };
 
-use anyhow::{anyhow, bail, Context, Result};
-use console::style;
+use anyhow::{anyhow, bail, Result};
+use console::{style, Term};
+use std::collections::HashMap;
 
 // -> Result<UserCancelled?>
 pub async fn execute(
@@ -24,63 +22,153 @@ pub async fn execute(
     downloader: &Downloader,
     blueprint: &mut Blueprints,
     opts: &Opts,
+    resolve: &ResolveOpts,
     config: &Config,
     request: UserRequest,
 ) -> Result<bool> {
-    let dbs = repo_db.get_all_package_db().context("Invalid local package database!")?;
     let local_repo = opts.root.join(crate::LOCAL_REPO_PATH);
     if !local_repo.is_dir() {
         std::fs::create_dir_all(&local_repo)?;
     }
-    let mut pool = pool::InMemoryPool::new();
-    for (db_base_url, db_path) in dbs {
-        crate::alpm::db::import(&db_path, &mut pool, &db_base_url)?;
-    }
+    let pool = super::load_pool(repo_db)?;
 
     debug!("Processing user request...");
-    let root = &opts.root;
-    let machine_status = MachineStatus::new(root).await?;
-    process_user_request(request, &pool, blueprint, &machine_status)?;
+    let mut machine_status = MachineStatus::from_opts(opts).await?;
+    // One-shot holds from the command line are not written anywhere
+    machine_status.hold(&resolve.ignore)?;
+    for pattern in &resolve.exclude {
+        let regex = crate::alpm::conf::glob_to_regex(pattern)?;
+        if machine_status.hold_matching(&[regex]) == 0 {
+            warn!("No installed package matches {}.", style(pattern).bold());
+        }
+    }
+    if let UserRequest::Upgrade(only) = &request {
+        if !only.is_empty() {
+            machine_status.hold_except(only)?;
+        }
+    }
+    let (purge, forget): (Vec<String>, Vec<String>) = match &request {
+        UserRequest::Remove(list) => (
+            list.iter().filter(|req| req.purge).map(|req| req.pkgname.clone()).collect(),
+            list.iter()
+                .filter(|req| req.forget && machine_status.pkgs.contains_key(&req.pkgname))
+                .map(|req| req.pkgname.clone())
+                .collect(),
+        ),
+        _ => (Vec::new(), Vec::new()),
+    };
+    if !process_user_request(opts, config, request, &pool, blueprint, &machine_status)? {
+        // User cancelled
+        return Ok(true);
+    }
+    // Forgotten packages are no longer requested, but stay at their installed version
+    machine_status.hold(&forget)?;
 
     debug!("Applying replaces according to package catalog...");
     apply_replaces(opts, &pool, blueprint)?;
 
     info!("Resolving dependencies...");
-    let solver = Solver::from(pool);
-    let res = solver.install(blueprint)?;
+    let mut solver = Solver::from(pool);
+    let mut assumed = Vec::new();
+    for arg in &resolve.assume_installed {
+        let (name, version) = match arg.split_once('=') {
+            Some((name, version)) => (name, Some(PkgVersion::try_from(version)?)),
+            None => (arg.as_str(), None),
+        };
+        solver.assume_installed(name, version)?;
+        assumed.push(name);
+    }
+    let from_repo: HashMap<String, String> = resolve.from_repo.iter().cloned().collect();
+    let res = match solver.install(blueprint, &machine_status.held_pkgs(), &from_repo) {
+        Ok(res) => res,
+        Err(e) => {
+            if resolve.explain {
+                explain_requests(&solver, blueprint, &from_repo)?;
+            }
+            return Err(e);
+        }
+    };
     // Translating result to list of actions
-    let actions = machine_status.gen_actions(res.as_slice());
+    for name in &resolve.reinstall {
+        if !machine_status.pkgs.contains_key(name) {
+            bail!("Cannot reinstall {}: package is not installed.", style(name).bold());
+        }
+        if !res.iter().any(|pkg| &pkg.name == name) {
+            bail!("Cannot reinstall {}: package is going to be removed.", style(name).bold());
+        }
+    }
+    let mut actions = machine_status.gen_actions(res.as_slice(), &resolve.reinstall, &purge);
+    // Assumed packages are left out of the solution, but that doesn't mean they should go
+    actions.remove.retain(|(name, _)| !assumed.contains(&name.as_str()));
+    actions.purge.retain(|(name, _)| !assumed.contains(&name.as_str()));
+    machine_status.check_held(&actions)?;
+    // Installs are already sorted by the solver, dependencies first
+    machine_status.sort_removals(solver.pool.as_ref(), &mut actions)?;
+    // Packages with explicit version requirements in blueprint
+    let pinned: Vec<String> = blueprint
+        .get_pkg_requests()?
+        .into_iter()
+        .filter(|req| !req.version.is_arbitary())
+        .map(|req| req.name)
+        .collect();
+    actions.check_downgrades(resolve.no_downgrade, resolve.allow_downgrade, &pinned)?;
     if actions.is_empty() {
+        if opts.json {
+            actions.show_json()?;
+        }
         success!("There is nothing to do.");
         return Ok(false);
     }
 
     // There is something to do. Show it.
-    info!("sasm will perform the following actions:");
-    if opts.yes && opts.no_pager {
-        actions.show();
+    if opts.json {
+        actions.show_json()?;
     } else {
-        actions.show_tables(opts.no_pager)?;
+        info!("sasm will perform the following actions:");
+        if opts.yes && opts.no_pager {
+            actions.show();
+        } else {
+            actions.show_tables(opts.no_pager)?;
+        }
+        crate::WRITER.writeln("", "")?;
+        actions.show_size_change();
     }
-    crate::WRITER.writeln("", "")?;
-    actions.show_size_change();
 
-    if ask_confirm(opts, "Proceed?")? {
-        // Run it!
-        Ok(false)
-    } else {
-        Ok(true)
+    if opts.dry_run {
+        info!("Dry run requested, no changes have been made.");
+        // Treat as cancelled so blueprint changes are not written back
+        return Ok(true);
+    }
+    if opts.machine_state.is_some() {
+        info!("Planned against a machine state snapshot, no changes have been made.");
+        return Ok(true);
+    }
+
+    let essential = machine_status.essential_removals(&actions);
+    if !confirm_essential_removal(resolve.allow_essential_removal, &essential, cli::ask_input)? {
+        return Ok(true);
+    }
+    if !ask_confirm(opts, "Proceed?")? {
+        return Ok(true);
     }
+
+    // Run it!
+    Ok(false)
 }
 
+/// Returns false if user cancelled
 fn process_user_request(
+    opts: &Opts,
+    config: &Config,
     req: UserRequest,
     pool: &dyn PkgPool,
     blueprint: &mut Blueprints,
     ms: &MachineStatus,
-) -> Result<()> {
+) -> Result<bool> {
     match req {
         UserRequest::Install(list) => {
+            // Newly added packages whose optional dependencies are offered to the user
+            let mut suggest_for = Vec::new();
             for install in list {
                 // Check if this package actually exists
                 if pool.get_pkgs_by_name(&install.pkgname).is_none() {
@@ -96,34 +184,261 @@ fn process_user_request(
                         bail!("Failed to add new package: {}", install.pkgname);
                     }
                 }
+                let ver_req = match (install.ver_req, config.default_pin) {
+                    (Some(ver_req), _) => {
+                        check_version_available(pool, &install.pkgname, &ver_req)?;
+                        Some(ver_req)
+                    }
+                    // Pin to the version that is going to be installed
+                    (None, Some(policy)) if !install.local => {
+                        pin_requirement(pool, &install.pkgname, policy)
+                    }
+                    (None, _) => None,
+                };
 
                 // Add pkg to blueprint
-                let add_res = blueprint.add(
-                    &install.pkgname,
-                    install.modify,
-                    None,
-                    install.ver_req,
-                    install.local,
-                );
-                if let Err(e) = add_res {
-                    warn!("Cannot add package {}: {e}", style(&install.pkgname).bold());
+                let add_res =
+                    blueprint.add(&install.pkgname, install.modify, None, ver_req, install.local);
+                match add_res {
+                    Ok(()) => {
+                        if install.install_recomm {
+                            blueprint.set_recommends(&install.pkgname)?;
+                        }
+                        if install.install_suggests {
+                            suggest_for.push(install.pkgname);
+                        }
+                    }
+                    Err(e) => warn!("Cannot add package {}: {e}", style(&install.pkgname).bold()),
                 }
             }
+            let suggestions = find_suggestions(pool, blueprint, ms, &suggest_for)?;
+            if !suggestions.is_empty() {
+                let chosen = choose_suggestions(opts, &suggestions)?;
+                add_suggestions(blueprint, &suggestions, &chosen)?;
+            }
         }
         UserRequest::Remove(list) => {
-            for (name, remove_recomm) in list {
-                blueprint.remove(&name, remove_recomm)?;
+            for req in list {
+                // Nothing is uninstalled when forgetting a package
+                if !req.forget && !confirm_removal(opts, pool, ms, &req)? {
+                    return Ok(false);
+                }
+                blueprint.remove(&req.pkgname, req.remove_recomm)?;
+            }
+        }
+        UserRequest::Upgrade(_) => (),
+        UserRequest::Autoremove => {
+            for req in blueprint.remove_orphans() {
+                info!(
+                    "Removing {} from blueprint: it was added by {}, which is no longer requested.",
+                    style(&req.name).bold(),
+                    style(req.added_by.unwrap_or_default()).bold()
+                );
             }
         }
-        UserRequest::Upgrade => (),
     };
 
+    Ok(true)
+}
+
+/// Requirement derived by `policy` from the preferred version of a package
+/// None if that accepts any version
+fn pin_requirement(
+    pool: &dyn PkgPool,
+    pkgname: &str,
+    policy: PinPolicy,
+) -> Option<VersionRequirement> {
+    let id = pool.get_pkgs_by_name(pkgname)?[0];
+    let ver_req = policy.requirement(&pool.get_pkg_by_id(id)?.version);
+    if ver_req.is_arbitary() {
+        None
+    } else {
+        Some(ver_req)
+    }
+}
+
+/// An optional dependency of a newly requested package
+#[derive(Debug, PartialEq, Eq)]
+struct Suggestion {
+    name: String,
+    suggested_by: String,
+    description: Option<String>,
+}
+
+/// Optional dependencies of `pkgnames` that are available, but neither requested nor installed
+fn find_suggestions(
+    pool: &dyn PkgPool,
+    blueprint: &Blueprints,
+    ms: &MachineStatus,
+    pkgnames: &[String],
+) -> Result<Vec<Suggestion>> {
+    let requested: Vec<String> =
+        blueprint.get_pkg_requests()?.into_iter().map(|req| req.name).collect();
+    let mut res: Vec<Suggestion> = Vec::new();
+    for pkgname in pkgnames {
+        let id = match pool.get_pkgs_by_name(pkgname) {
+            Some(ids) => ids[0],
+            None => continue,
+        };
+        for (name, _, description) in &pool.get_pkg_by_id(id).unwrap().optional {
+            if requested.contains(name)
+                || ms.pkgs.contains_key(name)
+                || pool.get_pkgs_by_name(name).is_none()
+                || res.iter().any(|s| &s.name == name)
+            {
+                continue;
+            }
+            res.push(Suggestion {
+                name: name.clone(),
+                suggested_by: pkgname.clone(),
+                description: description.clone(),
+            });
+        }
+    }
+    Ok(res)
+}
+
+/// Let the user pick suggested packages to install. Returns indices of chosen ones
+fn choose_suggestions(opts: &Opts, suggestions: &[Suggestion]) -> Result<Vec<usize>> {
+    let items: Vec<String> = suggestions
+        .iter()
+        .map(|s| {
+            let mut item = format!("{} (suggested by {})", style(&s.name).bold(), s.suggested_by);
+            if let Some(description) = &s.description {
+                item.push_str(&format!(": {description}"));
+            }
+            item
+        })
+        .collect();
+    // Nobody is there to pick any
+    if opts.yes {
+        info!("Skipping {} suggested package(s):", suggestions.len());
+        for item in &items {
+            crate::WRITER.writeln("", item)?;
+        }
+        return Ok(Vec::new());
+    }
+
+    info!("Select suggested packages to install:");
+    let chosen =
+        dialoguer::MultiSelect::with_theme(&cli::SasmTheme::default()).items(&items).interact()?;
+    Ok(chosen)
+}
+
+/// Add chosen suggestions to user blueprint, as added by the package suggesting them
+fn add_suggestions(
+    blueprint: &mut Blueprints,
+    suggestions: &[Suggestion],
+    chosen: &[usize],
+) -> Result<()> {
+    for i in chosen {
+        let suggestion = &suggestions[*i];
+        blueprint.add(&suggestion.name, false, Some(&suggestion.suggested_by), None, false)?;
+    }
+    Ok(())
+}
+
+/// Make sure some version of a package in the repositories satisfies the requirement
+fn check_version_available(
+    pool: &dyn PkgPool,
+    pkgname: &str,
+    ver_req: &VersionRequirement,
+) -> Result<()> {
+    let ids = pool.get_pkgs_by_name(pkgname).unwrap_or_default();
+    if !ids.iter().any(|id| ver_req.contains(&pool.get_pkg_by_id(*id).unwrap().version)) {
+        bail!(
+            "No version of {} in the repositories satisfies {}.",
+            style(pkgname).bold(),
+            style(ver_req).bold()
+        );
+    }
+    Ok(())
+}
+
+/// Removing packages with this many installed reverse dependencies needs explicit confirmation
+const MANY_RDEPENDS: usize = 10;
+
+/// Show installed packages that depend on the package to be removed, and ask for confirmation
+fn confirm_removal(
+    opts: &Opts,
+    pool: &dyn PkgPool,
+    ms: &MachineStatus,
+    req: &RemoveRequest,
+) -> Result<bool> {
+    let name = req.pkgname.as_str();
+    let rdepends = ms.installed_rdepends(pool, name)?;
+    if rdepends.is_empty() {
+        return Ok(true);
+    }
+
+    warn!(
+        "Removing {} will also remove or break the following installed package(s): {}.",
+        style(name).bold(),
+        rdepends.iter().map(|pkg| style(pkg).bold().to_string()).collect::<Vec<_>>().join(", ")
+    );
+    // Nothing is removed in a dry run, the warning is enough
+    if opts.dry_run {
+        return Ok(true);
+    }
+    let msg = format!("Remove {} anyway?", style(name).bold());
+    if rdepends.len() < MANY_RDEPENDS || req.allow_rdepends {
+        return cli::ask_confirm(opts, &msg);
+    }
+    // --yes doesn't cover this, and there is no one to ask without a terminal
+    if opts.yes || !Term::stderr().is_term() {
+        bail!(
+            "{} installed package(s) depend on {}, refusing to remove it without confirmation. Use {} to remove it anyway.",
+            rdepends.len(),
+            style(name).bold(),
+            style("--allow-rdepends").bold()
+        );
+    }
+    cli::ask_confirm_explicit(&msg)
+}
+
+/// Removing essential packages needs typing their names, even with --yes
+/// `ask` prompts for a line of input
+fn confirm_essential_removal(
+    allow_essential_removal: bool,
+    names: &[&str],
+    mut ask: impl FnMut(&str) -> Result<String>,
+) -> Result<bool> {
+    if names.is_empty() {
+        return Ok(true);
+    }
+    // gen_actions has already warned about these
+    let styled: Vec<String> = names.iter().map(|name| style(name).bold().to_string()).collect();
+    if allow_essential_removal {
+        return Ok(true);
+    }
+    for (name, styled) in names.iter().zip(&styled) {
+        let input = ask(&format!("Type {styled} to confirm its removal"))?;
+        if input.trim() != *name {
+            error!("Input does not match {styled}, aborting.");
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Check every requested package on its own and report those that cannot be satisfied
+fn explain_requests(
+    solver: &Solver,
+    blueprint: &Blueprints,
+    from_repo: &HashMap<String, String>,
+) -> Result<()> {
+    info!("Checking requested packages individually...");
+    for req in blueprint.get_pkg_requests()? {
+        if let Err(e) = solver.explain(&req, from_repo) {
+            error!("Cannot install {}: {:#}", style(&req.name).bold(), e);
+        }
+    }
     Ok(())
 }
 
 fn apply_replaces(opts: &Opts, pool: &dyn PkgPool, blueprint: &mut Blueprints) -> Result<()> {
     // For every package in blueprint, check if they are replaced
-    for pkg in blueprint.get_pkg_requests() {
+    for pkg in blueprint.get_pkg_requests()? {
         if let Some(replacement) = pool.find_replacement(&pkg.name, &pkg.version) {
             // Found a replacement!
             // If in user blueprint, ask if to replace it
@@ -142,3 +457,175 @@ fn apply_replaces(opts: &Opts, pool: &dyn PkgPool, blueprint: &mut Blueprints) -
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        actions::InstallRequest,
+        solver::pool::{BasicPkgPool, InMemoryPool},
+        types::{PkgMeta, PkgStatus},
+        utils::test::TempDir,
+    };
+    use clap::Parser;
+
+    #[test]
+    fn essential_removal() {
+        let never = |_: &str| -> Result<String> { panic!("should not ask") };
+        assert!(confirm_essential_removal(false, &[], never).unwrap());
+        // The explicit flag skips typing
+        assert!(confirm_essential_removal(true, &["glibc"], never).unwrap());
+
+        let mut asked = Vec::new();
+        let answer = |answer: &'static str| {
+            move |msg: &str| -> Result<String> {
+                assert!(msg.contains("glibc") || msg.contains("pacman"));
+                Ok(answer.to_string())
+            }
+        };
+        assert!(confirm_essential_removal(false, &["glibc"], answer("glibc\n")).unwrap());
+        assert!(!confirm_essential_removal(false, &["glibc"], answer("y")).unwrap());
+        // Every package is asked for
+        let ask_all = |msg: &str| -> Result<String> {
+            asked.push(msg.to_owned());
+            Ok(if msg.contains("glibc") { "glibc" } else { "pacman" }.to_string())
+        };
+        assert!(confirm_essential_removal(false, &["glibc", "pacman"], ask_all).unwrap());
+        assert_eq!(asked.len(), 2);
+    }
+
+    #[test]
+    fn many_rdepends_removal() {
+        let mut pool = InMemoryPool::new();
+        let mut pkgs = HashMap::new();
+        let names = (0..MANY_RDEPENDS).map(|i| format!("d{i}")).chain(["c".to_string()]);
+        for name in names {
+            let mut meta = PkgMeta::test(&name, "1");
+            if name != "c" {
+                meta.depends.push(("c".to_string(), VersionRequirement::default(), None));
+            }
+            pool.add(meta);
+            let status = PkgStatus {
+                name: name.clone(),
+                version: PkgVersion::try_from("1").unwrap(),
+                install_size: 0,
+                held: false,
+                essential: false,
+            };
+            pkgs.insert(name, status);
+        }
+        pool.finalize();
+        let ms = MachineStatus { pkgs };
+        let req = |allow_rdepends: bool| RemoveRequest {
+            pkgname: "c".to_string(),
+            remove_recomm: false,
+            purge: false,
+            forget: false,
+            allow_rdepends,
+        };
+
+        // --yes alone is not enough
+        let opts = Opts::parse_from(["sasm", "--yes", "remove", "c"]);
+        let e = confirm_removal(&opts, &pool, &ms, &req(false)).unwrap_err();
+        assert!(e.to_string().contains("--allow-rdepends"));
+        assert!(confirm_removal(&opts, &pool, &ms, &req(true)).unwrap());
+        // Dry runs don't ask
+        let opts = Opts::parse_from(["sasm", "--dry-run", "remove", "c"]);
+        assert!(confirm_removal(&opts, &pool, &ms, &req(false)).unwrap());
+    }
+
+    #[test]
+    fn version_available() {
+        let mut pool = InMemoryPool::new();
+        for version in ["1.0-1", "1.2-1"] {
+            pool.add(PkgMeta::test("foo", version));
+        }
+        pool.finalize();
+
+        let req = |s: &str| VersionRequirement::try_from(s).unwrap();
+        assert!(check_version_available(&pool, "foo", &req("=1.0-1")).is_ok());
+        assert!(check_version_available(&pool, "foo", &req(">=1.1")).is_ok());
+        assert!(check_version_available(&pool, "foo", &req("=1.1-1")).is_err());
+        assert!(check_version_available(&pool, "foo", &req(">=2")).is_err());
+        assert!(check_version_available(&pool, "bar", &req("=1.0-1")).is_err());
+    }
+
+    #[test]
+    fn suggestions() {
+        let mut pool = InMemoryPool::new();
+        let mut a = PkgMeta::test_remote("a", "1");
+        for name in ["b", "c", "d", "missing"] {
+            a.optional.push((name.to_string(), VersionRequirement::default(), None));
+        }
+        pool.add(a);
+        for name in ["b", "c", "d"] {
+            pool.add(PkgMeta::test_remote(name, "1"));
+        }
+        pool.finalize();
+        let solver = Solver::from(pool);
+
+        let dir = TempDir::new();
+        let path = dir.join("user.blueprint");
+        std::fs::write(&path, "a\nd\n").unwrap();
+        let mut blueprints = Blueprints::from_files(path, &[], "x86_64").unwrap();
+        let ms = MachineStatus { pkgs: HashMap::new() };
+
+        // d is already requested, and missing is not available
+        let pool = solver.pool.as_ref();
+        let suggestions = find_suggestions(pool, &blueprints, &ms, &["a".to_string()]).unwrap();
+        let names: Vec<&str> = suggestions.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["b", "c"]);
+
+        add_suggestions(&mut blueprints, &suggestions, &[1]).unwrap();
+        let requests = blueprints.get_pkg_requests().unwrap();
+        let c = requests.iter().find(|req| req.name == "c").unwrap();
+        assert_eq!(c.added_by.as_deref(), Some("a"));
+        assert!(!requests.iter().any(|req| req.name == "b"));
+
+        // Optional dependencies are not installed unless chosen
+        let res = solver.install(&blueprints, &[], &HashMap::new()).unwrap();
+        let mut names: Vec<&str> = res.iter().map(|pkg| pkg.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["a", "c", "d"]);
+    }
+
+    #[test]
+    fn install_recommends() {
+        let mut pool = InMemoryPool::new();
+        let mut a = PkgMeta::test_remote("a", "1");
+        a.optional.push(("b".to_string(), VersionRequirement::default(), None));
+        pool.add(a);
+        pool.add(PkgMeta::test_remote("b", "1"));
+        pool.finalize();
+        let solver = Solver::from(pool);
+        let opts = Opts::parse_from(["sasm", "--yes", "install", "a"]);
+        let config = Config::from_toml("arch = \"x86_64\"\n[repo]\n").unwrap();
+        let ms = MachineStatus { pkgs: HashMap::new() };
+
+        let dir = TempDir::new();
+        let path = dir.join("user.blueprint");
+        let install = |install_recomm: bool| -> Vec<String> {
+            std::fs::write(&path, "").unwrap();
+            let mut blueprints = Blueprints::from_files(path.clone(), &[], "x86_64").unwrap();
+            let req = UserRequest::Install(vec![InstallRequest {
+                pkgname: "a".to_string(),
+                install_recomm,
+                install_suggests: false,
+                ver_req: None,
+                local: false,
+                modify: false,
+            }]);
+            let pool = solver.pool.as_ref();
+            assert!(process_user_request(&opts, &config, req, pool, &mut blueprints, &ms).unwrap());
+            let requests = blueprints.get_pkg_requests().unwrap();
+            assert_eq!(requests[0].recommends, install_recomm);
+            let res = solver.install(&blueprints, &[], &HashMap::new()).unwrap();
+            let mut names: Vec<String> = res.into_iter().map(|pkg| pkg.name.clone()).collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(install(true), vec!["a", "b"]);
+        assert_eq!(install(false), vec!["a"]);
+    }
+}
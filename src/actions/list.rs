@@ -0,0 +1,121 @@
+use super::search::format_pkg_line;
+use crate::{
+    config::{CachedRepoDb, ListConfig, Opts},
+    executor::MachineStatus,
+    solver::pool::BasicPkgPool,
+    types::{PkgMeta, PkgVersion},
+    utils::{cli::gen_prefix, pager::Pager},
+};
+
+use anyhow::Result;
+use std::{collections::BTreeSet, io::Write};
+
+/// A package to list
+struct ListEntry<'a> {
+    name: &'a str,
+    installed: Option<&'a PkgVersion>,
+    // Most preferred version in repositories, if any
+    latest: Option<&'a PkgMeta>,
+}
+
+pub async fn list(repo_db: &CachedRepoDb, opts: &Opts, list_config: &ListConfig) -> Result<()> {
+    let pool = super::load_pool(repo_db)?;
+    let machine_status = MachineStatus::from_opts(opts).await?;
+
+    let mut pager = Pager::new(opts.no_pager)?;
+    let mut out = pager.get_writer()?;
+    for entry in list_entries(&pool, &machine_status, list_config) {
+        let (version_str, repo) = match (entry.installed, entry.latest) {
+            (Some(installed), Some(latest)) if installed < &latest.version => {
+                (format!("{} -> {}", installed, latest.version), latest.repo.as_deref())
+            }
+            (Some(installed), _) => (installed.to_string(), None),
+            (None, Some(latest)) => (latest.version.to_string(), latest.repo.as_deref()),
+            (None, None) => continue,
+        };
+        let (prefix, line) =
+            format_pkg_line(entry.name, &version_str, repo, entry.installed.is_some());
+        writeln!(out, "{}{}", gen_prefix(&prefix), line)?;
+    }
+
+    // Finish writing
+    drop(out);
+    // Wait until pager exits
+    pager.wait_for_exit()?;
+
+    Ok(())
+}
+
+/// Collect packages to list, sorted by name
+/// Installed packages are listed even if no repository has them
+fn list_entries<'a, P: BasicPkgPool + ?Sized>(
+    pool: &'a P,
+    machine_status: &'a MachineStatus,
+    list_config: &ListConfig,
+) -> Vec<ListEntry<'a>> {
+    let only_installed = list_config.installed || list_config.upgradable;
+    let mut names: BTreeSet<&str> = machine_status.pkgs.keys().map(|name| name.as_str()).collect();
+    if !only_installed {
+        names.extend(pool.pkgname_iter().map(|(name, _)| name));
+    }
+
+    let mut res = Vec::with_capacity(names.len());
+    for name in names {
+        let installed = machine_status.pkgs.get(name).map(|pkg| &pkg.version);
+        let latest = pool
+            .get_pkgs_by_name(name)
+            .and_then(|ids| ids.first().copied())
+            .and_then(|id| pool.get_pkg_by_id(id));
+        let upgradable = match (installed, latest) {
+            (Some(installed), Some(latest)) => installed < &latest.version,
+            _ => false,
+        };
+        if list_config.upgradable && !upgradable {
+            continue;
+        }
+        res.push(ListEntry { name, installed, latest });
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{solver::pool::InMemoryPool, types::PkgStatus};
+    use std::collections::HashMap;
+
+    #[test]
+    fn entries() {
+        let mut pool = InMemoryPool::new();
+        pool.add(PkgMeta::test("a", "1"));
+        pool.add(PkgMeta::test("a", "2"));
+        pool.add(PkgMeta::test("b", "1"));
+        pool.add(PkgMeta::test("c", "1"));
+        pool.finalize();
+        let mut pkgs = HashMap::new();
+        // d is installed, but in no repository
+        for (name, version) in [("a", "1"), ("b", "1"), ("d", "1")] {
+            let status = PkgStatus {
+                name: name.to_string(),
+                version: PkgVersion::try_from(version).unwrap(),
+                install_size: 0,
+                held: false,
+                essential: false,
+            };
+            pkgs.insert(name.to_string(), status);
+        }
+        let ms = MachineStatus { pkgs };
+
+        let names = |installed, upgradable| {
+            let list_config = ListConfig { installed, upgradable };
+            list_entries(&pool, &ms, &list_config)
+                .iter()
+                .map(|entry| entry.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(false, false), vec!["a", "b", "c", "d"]);
+        assert_eq!(names(true, false), vec!["a", "b", "d"]);
+        assert_eq!(names(false, true), vec!["a"]);
+    }
+}
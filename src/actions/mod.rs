@@ -1,90 +1,384 @@
+mod bench;
+mod clean;
+mod download;
 mod execute;
+mod list;
+mod pick;
+mod provide;
+mod search;
+mod show;
+mod verify;
+mod why;
+use bench::bench;
+use clean::clean;
+use download::download;
 use execute::execute;
+use list::list;
+use pick::pick;
+use provide::provide;
+use search::search;
+use show::show;
+use verify::verify;
+use why::why;
 
 use crate::{
     config::CachedRepoDb,
-    config::{Blueprints, Config, Opts, SubCmd},
+    config::{bench::BenchCache, Blueprints, Config, Opts, ProgressFormat, SubCmd},
+    debug,
     executor::MachineStatus,
-    info, success,
-    types::VersionRequirement,
-    utils::lock,
+    info,
+    solver::pool::{BasicPkgPool, InMemoryPool},
+    success,
+    types::{sha256_file, PkgMeta, VersionRequirement},
+    utils::{
+        downloader::{Downloader, ProgressEvents},
+        lock,
+    },
+    warn,
 };
 
-use anyhow::{Context, Result};
-use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use console::style;
+use rayon::prelude::*;
+use std::{path::PathBuf, time::Duration};
 
 #[derive(Debug)]
 pub enum UserRequest {
     // Vec<(PkgName, ver_req, install_recomm, added_by, local)>
     Install(Vec<InstallRequest>),
-    // Vec<(PkgName, remove_recomm)>
-    Remove(Vec<(String, bool)>),
-    Upgrade,
+    Remove(Vec<RemoveRequest>),
+    // Upgrade only these packages if not empty, everything otherwise
+    Upgrade(Vec<String>),
+    // Remove orphaned requests and packages
+    Autoremove,
 }
 
 #[derive(Debug)]
 pub struct InstallRequest {
     pkgname: String,
     install_recomm: bool,
+    // Offer optional dependencies to add as well
+    install_suggests: bool,
     ver_req: Option<VersionRequirement>,
     local: bool,
     /// Whether modify existing entry
     modify: bool,
 }
 
+#[derive(Debug)]
+pub struct RemoveRequest {
+    pkgname: String,
+    remove_recomm: bool,
+    purge: bool,
+    /// Only drop the blueprint entry, keep the package installed
+    forget: bool,
+    /// Skip the explicit confirmation for packages with many reverse dependencies
+    allow_rdepends: bool,
+}
+
 /// bool in return type indicated whether user cancelled operation
 pub async fn fullfill_command(
     config: &Config,
     opts: &Opts,
     blueprints: &mut Blueprints,
 ) -> Result<bool> {
-    let downloader = crate::utils::downloader::Downloader::new();
+    let download_opts = opts.subcmd.download_opts();
+    let mut downloader = Downloader::new()
+        .with_limits(config.max_concurrent_downloads, config.max_downloads_per_host)
+        .with_rate_limit(download_opts.and_then(|o| o.limit_rate).or(config.max_bytes_per_sec))
+        .with_retry_backoff(config.retry_backoff_ms.map(Duration::from_millis));
+    if download_opts.is_some_and(|o| o.progress == ProgressFormat::Json) {
+        downloader = downloader.with_progress_events(ProgressEvents::stderr());
+    }
     // Directory that stores trusted public keys for repos
     let _key_root = opts.root.join(crate::DB_KEY_PATH);
-    let localdb =
-        CachedRepoDb::new(opts.root.join(crate::DB_CACHE_PATH), config.repo.clone(), &config.arch);
+    for name in &opts.enable_repo {
+        if !config.repo.contains_key(name) {
+            bail!("Repository {} is not configured.", style(name).bold());
+        }
+    }
+    // Report conflicting version pins before the solver gets a chance to fail on them
+    if matches!(
+        opts.subcmd,
+        SubCmd::Install(_) | SubCmd::Execute(_) | SubCmd::Remove(_) | SubCmd::Autoremove(_)
+    ) {
+        blueprints.validate()?;
+    }
+    let localdb = CachedRepoDb::new(
+        opts.root.join(crate::DB_CACHE_PATH),
+        config.repo.clone(),
+        config.url_variables(),
+        &opts.enable_repo,
+    );
+    // Missing or broken benchmark results only mean mirrors are used in config order
+    let mut localdb = match BenchCache::load(&opts.root.join(crate::BENCH_CACHE_PATH)) {
+        Ok(cache) => localdb.with_bench_results(cache),
+        Err(e) => {
+            warn!("Failed to load mirror benchmark results: {e}");
+            localdb
+        }
+    };
 
     match &opts.subcmd {
-        SubCmd::Execute => {
-            // This operation has side effects
-            lock::ensure_unlocked(&opts.root)?;
-            lock::lock(&opts.root)?;
+        SubCmd::Install(installpkg) => {
+            // This operation has side effects, unless in dry run mode
+            if opts.dry_run {
+                lock::ensure_unlocked(&opts.root)?;
+            } else {
+                lock::lock(&opts.root, opts.wait).await?;
+            }
 
-            let req = UserRequest::Upgrade;
+            let mut list = Vec::new();
+            for arg in &installpkg.names {
+                let (pkgname, ver_req) = parse_install_arg(arg)?;
+                list.push(InstallRequest {
+                    pkgname,
+                    install_recomm: installpkg.recommends,
+                    install_suggests: installpkg.install_suggests,
+                    // Pinning a version of a requested package updates its entry
+                    modify: ver_req.is_some(),
+                    ver_req,
+                    local: installpkg.local,
+                });
+            }
+            let req = UserRequest::Install(list);
             localdb
-                .update(&downloader)
+                .update(&downloader, opts.strict)
                 .await
                 .context("Failed to refresh local package metadata!")?;
 
-            let exit = execute(&localdb, &downloader, blueprints, opts, config, req).await?;
+            let resolve = &installpkg.resolve;
+            let exit =
+                execute(&localdb, &downloader, blueprints, opts, resolve, config, req).await?;
+
+            Ok(exit)
+        }
+        SubCmd::Execute(executeconfig) => {
+            // This operation has side effects, unless in dry run mode
+            if opts.dry_run {
+                lock::ensure_unlocked(&opts.root)?;
+            } else {
+                lock::lock(&opts.root, opts.wait).await?;
+            }
+
+            let req = UserRequest::Upgrade(executeconfig.only.clone());
+            localdb
+                .update(&downloader, opts.strict)
+                .await
+                .context("Failed to refresh local package metadata!")?;
+
+            let resolve = &executeconfig.resolve;
+            let exit =
+                execute(&localdb, &downloader, blueprints, opts, resolve, config, req).await?;
+
+            Ok(exit)
+        }
+        SubCmd::Remove(removepkg) => {
+            // This operation has side effects, unless in dry run mode
+            if opts.dry_run {
+                lock::ensure_unlocked(&opts.root)?;
+            } else {
+                lock::lock(&opts.root, opts.wait).await?;
+            }
+
+            let req = UserRequest::Remove(
+                removepkg
+                    .names
+                    .iter()
+                    .map(|name| RemoveRequest {
+                        pkgname: name.clone(),
+                        remove_recomm: removepkg.remove_recommends,
+                        purge: removepkg.purge,
+                        forget: removepkg.forget,
+                        allow_rdepends: removepkg.allow_rdepends,
+                    })
+                    .collect(),
+            );
+            let resolve = &removepkg.resolve;
+            let exit =
+                execute(&localdb, &downloader, blueprints, opts, resolve, config, req).await?;
+
+            Ok(exit)
+        }
+        SubCmd::Autoremove(autoremoveconfig) => {
+            // This operation has side effects, unless in dry run mode
+            if opts.dry_run {
+                lock::ensure_unlocked(&opts.root)?;
+            } else {
+                lock::lock(&opts.root, opts.wait).await?;
+            }
+
+            // Installed packages that are not required by blueprints will be removed by execute
+            let req = UserRequest::Autoremove;
+            let resolve = &autoremoveconfig.resolve;
+            let exit =
+                execute(&localdb, &downloader, blueprints, opts, resolve, config, req).await?;
 
             Ok(exit)
         }
         SubCmd::Clean(cleanconfig) => {
             // This operation has side effects
-            lock::ensure_unlocked(&opts.root)?;
-            lock::lock(&opts.root)?;
-
-            info!("Purging local package metadata cache...");
-            let pkg_cache_path = opts.root.join(crate::PKG_CACHE_PATH);
-            if pkg_cache_path.is_dir() {
-                std::fs::remove_dir_all(&pkg_cache_path)?;
-                std::fs::create_dir_all(&pkg_cache_path)?;
+            lock::lock(&opts.root, opts.wait).await?;
+
+            clean(opts, cleanconfig)?;
+            Ok(false)
+        }
+        SubCmd::Bench(benchconfig) => {
+            let max_age = benchconfig.max_age.map(|hours| Duration::from_secs(hours * 3600));
+            bench(opts, &mut localdb, &downloader, max_age).await?;
+            Ok(false)
+        }
+        SubCmd::Download(downloadpkg) => {
+            let output = match &downloadpkg.output {
+                Some(dir) => dir.clone(),
+                None => std::env::current_dir().context("Failed to get current directory.")?,
+            };
+            download(
+                &downloadpkg.pkgname,
+                &localdb,
+                &downloader,
+                downloadpkg.latest,
+                &output,
+                &opts.root.join(crate::PKG_CACHE_PATH),
+            )
+            .await?;
+            Ok(false)
+        }
+        SubCmd::Pick(pickpkg) => {
+            // This operation modifies blueprint
+            lock::lock(&opts.root, opts.wait).await?;
+
+            pick(&pickpkg.name, &localdb, blueprints, opts).await?;
+            Ok(false)
+        }
+        SubCmd::Search(search_config) => {
+            let ms = MachineStatus::from_opts(opts).await?;
+            search(&localdb, search_config, &ms)?;
+            Ok(false)
+        }
+        SubCmd::Provide(provide_file) => {
+            let ms = MachineStatus::from_opts(opts).await?;
+            provide(&localdb, &downloader, &ms, &provide_file.file, provide_file.bin).await?;
+            Ok(false)
+        }
+        SubCmd::Show(showpkg) => {
+            show(&localdb, opts, &showpkg.name).await?;
+            Ok(false)
+        }
+        SubCmd::List(list_config) => {
+            list(&localdb, opts, list_config).await?;
+            Ok(false)
+        }
+        // Handled before loading configurations
+        SubCmd::Completions { .. } => Ok(false),
+        SubCmd::Why(whypkg) => {
+            why(&localdb, blueprints, opts, &whypkg.name).await?;
+            Ok(false)
+        }
+        SubCmd::Verify(verifyconfig) => {
+            verify(opts, verifyconfig).await?;
+            Ok(false)
+        }
+        SubCmd::Snapshot(snapshot) => {
+            let ms = MachineStatus::from_opts(opts).await?;
+            ms.save(&snapshot.path)?;
+            success!(
+                "Saved {} installed package(s) to {}.",
+                ms.pkgs.len(),
+                style(snapshot.path.display()).bold()
+            );
+            Ok(false)
+        }
+    }
+}
+
+/// Split an install argument like `foo`, `foo=1.0-1` or `foo>=1.0` into name and version
+fn parse_install_arg(arg: &str) -> Result<(String, Option<VersionRequirement>)> {
+    match arg.find(|c| matches!(c, '<' | '>' | '=' | '!')) {
+        Some(pos) => {
+            let (name, req) = arg.split_at(pos);
+            if name.is_empty() {
+                bail!("Invalid package request {}: missing package name.", style(arg).bold());
             }
+            let ver_req = VersionRequirement::try_from(req)
+                .context(format!("Invalid version requirement in {}.", style(arg).bold()))?;
+            Ok((name.to_owned(), Some(ver_req)))
+        }
+        None => Ok((arg.to_owned(), None)),
+    }
+}
 
-            info!("Purging local package cache...");
-            let ms = MachineStatus::new(&opts.root).await?;
+/// Load all cached package databases into a finalized package pool
+/// Reuse the pool cache if it is built from the same databases
+fn load_pool(repo_db: &CachedRepoDb) -> Result<InMemoryPool> {
+    let mut dbs = repo_db.get_all_package_db().context("Invalid local package database!")?;
+    // Keep PkgIDs stable across invocations
+    dbs.sort_by(|a, b| a.2.cmp(&b.2));
+
+    // Key the cache by the name, base url and checksum of every database
+    let mut key = Vec::with_capacity(dbs.len());
+    for (repo, db_base_url, db_path) in &dbs {
+        key.push(repo.as_bytes().to_vec());
+        key.push(db_base_url.as_bytes().to_vec());
+        key.push(sha256_file(db_path)?);
+    }
 
-            if cleanconfig.all {
-                info!("Purging local metadata cache...");
-                let db_cache_path = opts.root.join(crate::DB_CACHE_PATH);
-                if db_cache_path.is_dir() {
-                    std::fs::remove_dir_all(&db_cache_path)?;
-                    std::fs::create_dir_all(&db_cache_path)?;
-                }
+    let cache_path = repo_db.get_pool_cache_path();
+    let mut pool = match InMemoryPool::load_cache(&cache_path, &key)? {
+        Some(pool) => {
+            debug!("Loaded package pool from cache.");
+            pool
+        }
+        None => {
+            // Parse databases in parallel, then add them sequentially so PkgIDs stay stable
+            let pkgs: Vec<Vec<PkgMeta>> = dbs
+                .par_iter()
+                .map(|(repo, db_base_url, db_path)| {
+                    crate::alpm::db::read(db_path, repo, db_base_url)
+                })
+                .collect::<Result<_>>()?;
+            let mut pool = InMemoryPool::new();
+            for pkg in pkgs.into_iter().flatten() {
+                pool.add(pkg);
             }
 
-            Ok(false)
+            // Failing to write cache only makes the next run slower
+            if let Err(e) = pool.save_cache(&cache_path, &key) {
+                warn!("Failed to save package pool cache: {e}");
+            }
+            pool
         }
+    };
+
+    // Priorities are not part of the cache, so they can be changed without re-parsing
+    pool.set_repo_priorities(repo_db.get_repo_priorities());
+    debug!("Finalizing package pool...");
+    pool.finalize();
+    Ok(pool)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn install_arg() {
+        assert_eq!(parse_install_arg("foo").unwrap(), ("foo".to_owned(), None));
+        let (name, ver_req) = parse_install_arg("foo=1.2.3-1").unwrap();
+        assert_eq!(name, "foo");
+        assert_eq!(ver_req, Some(VersionRequirement::try_from("=1.2.3-1").unwrap()));
+        let ver_req = ver_req.unwrap();
+        assert!(ver_req.contains(&"1.2.3-1".try_into().unwrap()));
+        assert!(!ver_req.contains(&"1.2.3-2".try_into().unwrap()));
+
+        let (name, ver_req) = parse_install_arg("lib32-foo>=1.2").unwrap();
+        assert_eq!(name, "lib32-foo");
+        let ver_req = ver_req.unwrap();
+        assert!(ver_req.contains(&"1.2".try_into().unwrap()));
+        assert!(ver_req.contains(&"2.0-1".try_into().unwrap()));
+        assert!(!ver_req.contains(&"1.1".try_into().unwrap()));
+
+        assert!(parse_install_arg("=1.0").is_err());
+        assert!(parse_install_arg("foo=").is_err());
     }
 }
@@ -0,0 +1,67 @@
+use crate::{
+    config::{Blueprints, CachedRepoDb, Opts},
+    executor::MachineStatus,
+    info, msg,
+    solver::pool::BasicPkgPool,
+    success,
+    types::VersionRequirement,
+};
+
+use anyhow::{bail, Result};
+use console::style;
+
+/// Pin a package in user blueprint to a version chosen by the user
+pub async fn pick(
+    pkgname: &str,
+    repo_db: &CachedRepoDb,
+    blueprint: &mut Blueprints,
+    opts: &Opts,
+) -> Result<()> {
+    let pool = super::load_pool(repo_db)?;
+    let machine_status = MachineStatus::from_opts(opts).await?;
+    let installed = machine_status.pkgs.get(pkgname).map(|pkg| &pkg.version);
+
+    let ids = match pool.get_pkgs_by_name(pkgname) {
+        Some(ids) => ids,
+        None => bail!("Package {} not found.", style(pkgname).bold()),
+    };
+
+    // Form version strings for display
+    let mut default = 0;
+    let mut versions = Vec::with_capacity(ids.len());
+    let mut choices = Vec::with_capacity(ids.len());
+    for (i, id) in ids.into_iter().enumerate() {
+        let meta = pool.get_pkg_by_id(id).unwrap();
+        let mut info_segments = Vec::new();
+        if i == 0 {
+            info_segments.push(style("latest").green().to_string());
+        }
+        if installed == Some(&meta.version) {
+            info_segments.push(style("installed").blue().to_string());
+            default = i;
+        } else {
+            info_segments.push(style("available").dim().to_string());
+        }
+        choices.push(format!("{} ({})", meta.version, info_segments.join(", ")));
+        versions.push(meta.version.clone());
+    }
+
+    info!("Please choose a version for {}:", style(pkgname).bold());
+    let i = dialoguer::Select::with_theme(&crate::utils::cli::SasmTheme::default())
+        .items(&choices)
+        .default(default)
+        .interact()?;
+
+    let version = versions[i].clone();
+    let ver_req = VersionRequirement {
+        lower_bond: Some((version.clone(), true)),
+        upper_bond: Some((version.clone(), true)),
+        exclusions: Vec::new(),
+    };
+    // Modify the existing entry, if there is one
+    blueprint.add(pkgname, true, None, Some(ver_req), false)?;
+    success!("Pinned {} to version {} in user blueprint.", style(pkgname).bold(), version);
+    msg!("Run {} to apply the change.", style("sasm execute").bold());
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use super::search::PkgInfo;
+use crate::{
+    config::CachedRepoDb, debug, executor::MachineStatus, solver::pool::BasicPkgPool,
+    utils::downloader::Downloader, warn,
+};
+
+use anyhow::{Context, Result};
+use console::style;
+use std::{cmp::Reverse, collections::HashMap};
+
+/// Show packages that contain files matching `keyword`
+pub async fn provide(
+    repo_db: &CachedRepoDb,
+    downloader: &Downloader,
+    machine_status: &MachineStatus,
+    keyword: &str,
+    bin: bool,
+) -> Result<()> {
+    repo_db.update_contents(downloader).await.context("Failed to refresh file databases!")?;
+
+    debug!("Searching file databases...");
+    let mut matches: HashMap<String, Vec<String>> = HashMap::new();
+    for (_, db_path) in repo_db.get_all_contents_db()? {
+        for (pkgname, paths) in crate::alpm::files::search(&db_path, keyword, bin)
+            .context(format!("Failed to search file database {}.", db_path.display()))?
+        {
+            matches.entry(pkgname).or_default().extend(paths);
+        }
+    }
+    // Packages with the most matched paths first
+    let mut matches: Vec<(String, Vec<String>)> = matches.into_iter().collect();
+    matches.sort_by_key(|(_, paths)| Reverse(paths.len()));
+
+    let pool = super::load_pool(repo_db)?;
+    for (pkgname, paths) in matches {
+        let latest_pkg = match pool.get_pkgs_by_name(&pkgname) {
+            // This is safe unless the pool is broken
+            Some(ids) => pool.get_pkg_by_id(ids[0]).unwrap(),
+            None => {
+                warn!(
+                    "Package {} is in file database but not in package database.",
+                    style(&pkgname).bold()
+                );
+                continue;
+            }
+        };
+        let additional_info =
+            paths.into_iter().map(|path| format!("Provides: {}", style(path).bold())).collect();
+        let pkginfo = PkgInfo { pkg: latest_pkg, additional_info };
+        pkginfo.show(machine_status)?;
+    }
+
+    Ok(())
+}
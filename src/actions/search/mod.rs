@@ -1,67 +1,77 @@
 mod pkg;
-mod provide;
-pub use pkg::search_deb_db;
-pub use provide::show_provide_file;
+pub use pkg::search;
 
-use crate::{
-    executor::{MachineStatus, PkgState},
-    types::PkgMeta,
-};
+use crate::{executor::MachineStatus, types::PkgMeta};
 
 use anyhow::Result;
 use console::style;
+use serde::Serialize;
 
 pub struct PkgInfo<'a> {
     pub pkg: &'a PkgMeta,
     // Additional info
-    pub has_dbg_pkg: bool,
     pub additional_info: Vec<String>,
 }
 
-impl<'a> PkgInfo<'a> {
-    pub fn show(&self, machine_status: &MachineStatus) -> Result<()> {
-        // Construct prefix
-        let prefix = match machine_status.pkgs.get(&self.pkg.name) {
-            Some(pkg) => match pkg.state {
-                PkgState::Installed => style("INSTALLED").green(),
-                PkgState::Unpacked => style("UNPACKED").yellow(),
-                _ => style("AVAIL").dim(),
-            },
-            None => style("AVAIL").dim(),
-        }
-        .to_string();
-        // Construct pkg info line
-        let mut pkg_info_line = style(&self.pkg.name).bold().to_string();
+/// A search result in --json output
+#[derive(Serialize)]
+pub struct JsonPkgInfo {
+    name: String,
+    version: String,
+    repo: Option<String>,
+    description: String,
+    installed: bool,
+}
+
+/// Format the prefix and the first line of a package entry
+pub fn format_pkg_line(
+    name: &str,
+    version: &str,
+    repo: Option<&str>,
+    installed: bool,
+) -> (String, String) {
+    // Construct prefix
+    let prefix =
+        if installed { style("INSTALLED").green() } else { style("AVAIL").dim() }.to_string();
+    // Construct pkg info line
+    let mut pkg_info_line = style(name).bold().to_string();
+    pkg_info_line.push(' ');
+    pkg_info_line.push_str(&style(version).green().to_string());
+    if let Some(repo) = repo {
         pkg_info_line.push(' ');
-        pkg_info_line.push_str(&style(&self.pkg.version).green().to_string());
-        if self.has_dbg_pkg {
-            pkg_info_line.push(' ');
-            pkg_info_line.push_str(&style("(debug symbols available)").dim().to_string());
+        pkg_info_line.push_str(&style(format!("[{repo}]")).dim().to_string());
+    }
+    (prefix, pkg_info_line)
+}
+
+impl<'a> PkgInfo<'a> {
+    pub fn to_json(&self, machine_status: &MachineStatus) -> JsonPkgInfo {
+        JsonPkgInfo {
+            name: self.pkg.name.clone(),
+            version: self.pkg.version.to_string(),
+            repo: self.pkg.repo.clone(),
+            description: self.pkg.description.clone(),
+            installed: machine_status.pkgs.contains_key(&self.pkg.name),
         }
+    }
+
+    pub fn show(&self, machine_status: &MachineStatus) -> Result<()> {
+        let (prefix, pkg_info_line) = format_pkg_line(
+            &self.pkg.name,
+            &self.pkg.version.to_string(),
+            self.pkg.repo.as_deref(),
+            machine_status.pkgs.contains_key(&self.pkg.name),
+        );
         crate::WRITER.writeln(&prefix, &pkg_info_line)?;
 
         // Write package description
         crate::WRITER.writeln("", &self.pkg.description)?;
 
         // Write provided packages
-        if let Some(provides) = &self.pkg.provides {
+        if !self.pkg.provides.is_empty() {
             let prefix = style("Provides:").dim().to_string();
             let mut chunks = vec![prefix];
-            for (name, ver_req) in provides {
-                let mut chunk = name.clone();
-                if !ver_req.is_arbitary() {
-                    chunk.push_str(&format!(" ({ver_req})"));
-                }
-                chunks.push(chunk);
-            }
-            crate::WRITER.write_chunks("", &chunks)?;
-        }
-
-        // Write recommended packages
-        if let Some(recommends) = &self.pkg.recommends {
-            let prefix = style("Recommends:").dim().to_string();
-            let mut chunks = vec![prefix];
-            for (name, ver_req) in recommends {
+            for (name, ver_req, _) in &self.pkg.provides {
                 let mut chunk = name.clone();
                 if !ver_req.is_arbitary() {
                     chunk.push_str(&format!(" ({ver_req})"));
@@ -71,11 +81,11 @@ impl<'a> PkgInfo<'a> {
             crate::WRITER.write_chunks("", &chunks)?;
         }
 
-        // Write suggested packages
-        if let Some(suggests) = &self.pkg.suggests {
-            let prefix = style("Suggests:").dim().to_string();
+        // Write optional dependencies
+        if !self.pkg.optional.is_empty() {
+            let prefix = style("Optional:").dim().to_string();
             let mut chunks = vec![prefix];
-            for (name, ver_req) in suggests {
+            for (name, ver_req, _) in &self.pkg.optional {
                 let mut chunk = name.clone();
                 if !ver_req.is_arbitary() {
                     chunk.push_str(&format!(" ({ver_req})"));
@@ -1,25 +1,27 @@
 use super::PkgInfo;
-use crate::{db::LocalDb, executor::MachineStatus, pool, pool::PkgPool};
+use crate::{
+    config::{CachedRepoDb, SearchPkg},
+    executor::MachineStatus,
+    solver::pool::{BasicPkgPool, PkgPool},
+    utils::cli::print_json,
+};
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 use std::{cmp::Reverse, collections::HashMap};
 
-pub fn search_deb_db(
-    local_db: &LocalDb,
-    keyword: &str,
+pub fn search(
+    repo_db: &CachedRepoDb,
+    search_config: &SearchPkg,
     machine_status: &MachineStatus,
 ) -> Result<()> {
-    let dbs = local_db
-        .get_all_package_db()
-        .context("Failed to initialize local database for searching!")?;
-    let pool = pool::source::create_pool(&dbs, &[])?;
-
-    let mut pkgs = search_pkg_helper(pool.as_ref(), keyword);
-
-    // Sort pkg in descending order based on relevance to keyword
-    pkgs.sort_by_cached_key(|pkg| Reverse(pkg_score(pkg, keyword)));
+    let pool = crate::actions::load_pool(repo_db)?;
+    let pkgs = search_results(&pool, search_config);
 
     // Display result
+    if crate::json() {
+        let res: Vec<_> = pkgs.iter().map(|pkg| pkg.to_json(machine_status)).collect();
+        return print_json(&res);
+    }
     for pkg in pkgs {
         pkg.show(machine_status)?;
     }
@@ -27,52 +29,66 @@ pub fn search_deb_db(
     Ok(())
 }
 
-pub fn search_pkg_helper<'a, P: ?Sized>(pool: &'a P, keyword: &str) -> Vec<PkgInfo<'a>>
+/// Search packages by name and, unless --names-only, description
+/// Results are sorted in descending order of relevance to keyword
+fn search_results<'a, P: ?Sized>(pool: &'a P, search_config: &SearchPkg) -> Vec<PkgInfo<'a>>
+where
+    P: PkgPool,
+{
+    let keyword = &search_config.keyword;
+    let mut pkgs =
+        search_pkg_helper(pool, keyword, search_config.ignore_case, !search_config.names_only);
+
+    // Sort pkg in descending order based on relevance to keyword
+    pkgs.sort_by_cached_key(|pkg| Reverse(pkg_score(pkg, keyword)));
+    pkgs
+}
+
+pub fn search_pkg_helper<'a, P: ?Sized>(
+    pool: &'a P,
+    keyword: &str,
+    ignore_case: bool,
+    description: bool,
+) -> Vec<PkgInfo<'a>>
 where
     P: PkgPool,
 {
+    let matches = |haystack: &str| {
+        if ignore_case {
+            contains_ignore_case(haystack, keyword)
+        } else {
+            haystack.contains(keyword)
+        }
+    };
+
     // Iterate through package names
     let mut res = HashMap::new();
     for (name, versions) in pool.pkgname_iter() {
-        if name.contains(keyword) {
+        if matches(name) {
             let id = versions[0].0;
             let pkg = pool.get_pkg_by_id(id).unwrap();
-            let has_dbg_pkg = pool.has_dbg_pkg(id).unwrap();
-
-            // Construct PkgInfo, don't include debug packages
-            if !pkg.name.ends_with("-dbg") && pkg.section != "debug" {
-                let pkginfo = PkgInfo {
-                    pkg,
-                    has_dbg_pkg,
-                    additional_info: Vec::new(),
-                };
-                res.insert(name, pkginfo);
-            }
+            let pkginfo = PkgInfo { pkg, additional_info: Vec::new() };
+            res.insert(name, pkginfo);
         }
     }
 
-    // Search package description
     for (id, meta) in pool.pkgid_iter() {
-        if meta.description.contains(keyword) && !res.contains_key(meta.name.as_str()) {
-            let pkginfo = PkgInfo {
-                pkg: meta,
-                has_dbg_pkg: pool.has_dbg_pkg(id).unwrap(),
-                additional_info: Vec::new(),
-            };
+        // Only consider the latest version of each package
+        if pool.get_pkgs_by_name(&meta.name).map(|ids| ids[0]) != Some(id) {
+            continue;
+        }
+
+        // Search package description
+        if description && matches(&meta.description) && !res.contains_key(meta.name.as_str()) {
+            let pkginfo = PkgInfo { pkg: meta, additional_info: Vec::new() };
             res.insert(&meta.name, pkginfo);
         }
 
         // Search if provides
-        if let Some(provides) = &meta.provides {
-            for provide in provides {
-                if keyword == provide.0 {
-                    let pkginfo = PkgInfo {
-                        pkg: meta,
-                        has_dbg_pkg: false,
-                        additional_info: Vec::new(),
-                    };
-                    res.insert(&meta.name, pkginfo);
-                }
+        for provide in &meta.provides {
+            if keyword == provide.0 {
+                let pkginfo = PkgInfo { pkg: meta, additional_info: Vec::new() };
+                res.insert(&meta.name, pkginfo);
             }
         }
     }
@@ -80,14 +96,96 @@ where
     res.into_values().collect()
 }
 
+/// Substring search that ignores case
+/// Pure ASCII input is compared in place, other input is lowercased first
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    if haystack.is_ascii() && needle.is_ascii() {
+        let (haystack, needle) = (haystack.as_bytes(), needle.as_bytes());
+        if needle.is_empty() {
+            return true;
+        }
+        haystack.windows(needle.len()).any(|window| window.eq_ignore_ascii_case(needle))
+    } else {
+        haystack.to_lowercase().contains(&needle.to_lowercase())
+    }
+}
+
 fn pkg_score(pkg: &PkgInfo, keyword: &str) -> u8 {
-    if let Some(provides) = &pkg.pkg.provides {
-        for provide in provides {
-            if provide.0 == keyword {
-                return u8::MAX;
-            }
+    for provide in &pkg.pkg.provides {
+        if provide.0 == keyword {
+            return u8::MAX;
         }
     }
 
     (255.0 * strsim::jaro_winkler(&pkg.pkg.name, keyword)) as u8
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        solver::pool::InMemoryPool,
+        types::{PkgMeta, PkgVersion},
+    };
+    use clap::Parser;
+
+    fn pkgmeta(name: &str, description: &str) -> PkgMeta {
+        PkgMeta { description: description.to_string(), ..PkgMeta::test(name, "1") }
+    }
+
+    #[test]
+    fn names_and_descriptions() {
+        let mut pool = InMemoryPool::new();
+        pool.add(pkgmeta("firefox", "Standalone web browser from mozilla.org"));
+        pool.add(pkgmeta("thunderbird", "Standalone mail and news reader from Mozilla"));
+        pool.add(pkgmeta("mozilla-common", "Files shared by Ångström builds"));
+        pool.finalize();
+
+        let search = |args: &[&str]| {
+            let search_config = SearchPkg::parse_from(std::iter::once(&"search").chain(args));
+            let mut names: Vec<String> =
+                search_results(&pool, &search_config).iter().map(|p| p.pkg.name.clone()).collect();
+            names.sort();
+            names
+        };
+
+        // Descriptions are searched by default
+        assert_eq!(search(&["mozilla"]), vec!["firefox", "mozilla-common"]);
+        assert_eq!(search(&["-i", "mozilla"]), vec!["firefox", "mozilla-common", "thunderbird"]);
+        assert_eq!(search(&["--names-only", "mozilla"]), vec!["mozilla-common"]);
+        assert_eq!(search(&["--names-only", "-i", "Mozilla"]), vec!["mozilla-common"]);
+        assert!(search(&["Firefox"]).is_empty());
+        assert_eq!(search(&["-i", "ångSTRÖM"]), vec!["mozilla-common"]);
+        assert!(search(&["chrome"]).is_empty());
+    }
+
+    #[test]
+    fn json() {
+        let mut pool = InMemoryPool::new();
+        pool.add(pkgmeta("firefox", "Standalone web browser from mozilla.org"));
+        pool.finalize();
+        let mut ms = MachineStatus { pkgs: HashMap::new() };
+        let res = search_pkg_helper(&pool, "firefox", false, false);
+        let json = serde_json::to_value(res[0].to_json(&ms)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "name": "firefox",
+                "version": "1",
+                "repo": null,
+                "description": "Standalone web browser from mozilla.org",
+                "installed": false,
+            })
+        );
+
+        let status = crate::types::PkgStatus {
+            name: "firefox".to_string(),
+            version: PkgVersion::try_from("1").unwrap(),
+            install_size: 0,
+            held: false,
+            essential: false,
+        };
+        ms.pkgs.insert("firefox".to_string(), status);
+        assert!(res[0].to_json(&ms).installed);
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{
+    config::{CachedRepoDb, Opts},
+    executor::MachineStatus,
+    info,
+    solver::pool::BasicPkgPool,
+    types::{PkgMeta, PkgSource, VersionRequirement},
+};
+
+use anyhow::{bail, Result};
+use console::style;
+use indicatif::HumanBytes;
+
+/// Print all metadata of every available version of a package
+pub async fn show(repo_db: &CachedRepoDb, opts: &Opts, pkgname: &str) -> Result<()> {
+    let pool = super::load_pool(repo_db)?;
+    let machine_status = MachineStatus::from_opts(opts).await?;
+
+    let ids = match pool.get_pkgs_by_name(pkgname) {
+        Some(ids) => ids,
+        None => bail!("Package {} not found.", style(pkgname).bold()),
+    };
+
+    let installed = machine_status.pkgs.get(pkgname);
+    for (i, id) in ids.iter().enumerate() {
+        let pkg = pool.get_pkg_by_id(*id).unwrap();
+        if i != 0 {
+            crate::WRITER.writeln("", "")?;
+        }
+        let prefix = match installed {
+            Some(installed) if installed.version == pkg.version => style("INSTALLED").green(),
+            _ => style("AVAIL").dim(),
+        }
+        .to_string();
+        show_pkg(&prefix, pkg)?;
+    }
+
+    if let Some(installed) = installed {
+        // The first one is the preferred version
+        let best = &pool.get_pkg_by_id(ids[0]).unwrap().version;
+        if best > &installed.version {
+            crate::WRITER.writeln("", "")?;
+            info!(
+                "A newer version of {} is available: {} -> {}.",
+                style(pkgname).bold(),
+                installed.version,
+                style(best).green()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn show_pkg(prefix: &str, pkg: &PkgMeta) -> Result<()> {
+    let mut pkg_info_line = style(&pkg.name).bold().to_string();
+    pkg_info_line.push(' ');
+    pkg_info_line.push_str(&style(&pkg.version).green().to_string());
+    crate::WRITER.writeln(prefix, &pkg_info_line)?;
+    crate::WRITER.writeln("", &pkg.description)?;
+
+    let field = |name: &str| style(format!("{name}:")).dim().to_string();
+    if let Some(repo) = &pkg.repo {
+        crate::WRITER.writeln("", &format!("{} {}", field("Repository"), repo))?;
+    }
+    if let Some(maintainer) = &pkg.maintainer {
+        crate::WRITER.writeln("", &format!("{} {}", field("Maintainer"), maintainer))?;
+    }
+    if let Some(homepage) = &pkg.homepage {
+        crate::WRITER.writeln("", &format!("{} {}", field("Homepage"), homepage))?;
+    }
+    crate::WRITER
+        .writeln("", &format!("{} {}", field("Installed Size"), HumanBytes(pkg.install_size)))?;
+    match &pkg.source {
+        PkgSource::Http((_, size, _)) => {
+            crate::WRITER
+                .writeln("", &format!("{} {}", field("Download Size"), HumanBytes(*size)))?;
+        }
+        PkgSource::Local(path) => {
+            crate::WRITER.writeln("", &format!("{} {}", field("Local File"), path.display()))?;
+        }
+    }
+
+    show_pkg_list("Depends", &pkg.depends)?;
+    show_pkg_list("Optional", &pkg.optional)?;
+    show_pkg_list("Conflicts", &pkg.conflicts)?;
+    show_pkg_list("Provides", &pkg.provides)?;
+    show_pkg_list("Replaces", &pkg.replaces)?;
+
+    Ok(())
+}
+
+fn show_pkg_list(name: &str, list: &[(String, VersionRequirement, Option<String>)]) -> Result<()> {
+    if list.is_empty() {
+        return Ok(());
+    }
+
+    let mut chunks = vec![style(format!("{name}:")).dim().to_string()];
+    for (name, ver_req, _) in list {
+        let mut chunk = name.clone();
+        if !ver_req.is_arbitary() {
+            chunk.push_str(&format!(" ({ver_req})"));
+        }
+        chunks.push(chunk);
+    }
+    crate::WRITER.write_chunks("", &chunks)?;
+
+    Ok(())
+}
@@ -0,0 +1,210 @@
+use crate::{
+    alpm::mtree,
+    config::{Opts, VerifyConfig},
+    error,
+    executor::MachineStatus,
+    info, success,
+    types::PkgStatus,
+    utils::pacparse,
+    warn,
+};
+
+use anyhow::{bail, Context, Result};
+use console::style;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use rayon::prelude::*;
+use std::{fs, path::Path};
+
+#[derive(Debug, PartialEq, Eq)]
+enum Problem {
+    Missing(String),
+    Modified(String),
+}
+
+/// Check files of installed packages against the checksums recorded when they were installed
+pub async fn verify(opts: &Opts, verifyconfig: &VerifyConfig) -> Result<()> {
+    // Files are checked on the real root, so a saved machine state is no use here
+    let machine_status = MachineStatus::new(&opts.root).await?;
+    let mut pkgs: Vec<&PkgStatus> = if verifyconfig.names.is_empty() {
+        machine_status.pkgs.values().collect()
+    } else {
+        let mut pkgs = Vec::new();
+        for name in &verifyconfig.names {
+            match machine_status.pkgs.get(name) {
+                Some(pkg) => pkgs.push(pkg),
+                None => bail!("Package {} is not installed.", style(name).bold()),
+            }
+        }
+        pkgs
+    };
+    pkgs.sort_by(|a, b| a.name.cmp(&b.name));
+
+    info!("Verifying files of {} package(s)...", pkgs.len());
+    let bar = if crate::json() {
+        ProgressBar::with_draw_target(Some(pkgs.len() as u64), ProgressDrawTarget::hidden())
+    } else {
+        ProgressBar::new(pkgs.len() as u64)
+    };
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template(" {pos:>5}/{len:<5} [{wide_bar:.white/black}] {percent:>3}%")?
+            .progress_chars("=>-"),
+    );
+    let results: Vec<(&PkgStatus, Result<Vec<Problem>>)> = pkgs
+        .par_iter()
+        .map(|pkg| {
+            let res = verify_pkg(&opts.root, pkg);
+            bar.inc(1);
+            (*pkg, res)
+        })
+        .collect();
+    bar.finish_and_clear();
+
+    let mut problems = 0;
+    let mut broken_pkgs = 0;
+    for (pkg, res) in results {
+        match res {
+            Ok(found) if found.is_empty() => (),
+            Ok(found) => {
+                broken_pkgs += 1;
+                problems += found.len();
+                for problem in found {
+                    match problem {
+                        Problem::Missing(path) => {
+                            error!("{}: /{} is missing.", style(&pkg.name).bold(), path)
+                        }
+                        Problem::Modified(path) => {
+                            error!("{}: /{} has been modified.", style(&pkg.name).bold(), path)
+                        }
+                    }
+                }
+            }
+            // No file records only means this package can't be checked
+            Err(e) => warn!("Cannot verify {}: {:#}", style(&pkg.name).bold(), e),
+        }
+    }
+
+    if problems > 0 {
+        bail!("Found {} problem(s) in {} package(s).", problems, broken_pkgs);
+    }
+    success!("Files of all checked packages are intact.");
+    Ok(())
+}
+
+/// Find missing and modified files of an installed package
+fn verify_pkg(root: &Path, pkg: &PkgStatus) -> Result<Vec<Problem>> {
+    let db_dir = root.join("var/lib/pacman/local").join(format!("{}-{}", pkg.name, pkg.version));
+    let files = mtree::read(&db_dir.join("mtree"))?;
+    let backup = read_backup_files(&db_dir.join("files"))?;
+
+    let mut res = Vec::new();
+    for file in files {
+        let path = root.join(&file.path);
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(_) => {
+                res.push(Problem::Missing(file.path));
+                continue;
+            }
+        };
+        // Configuration files are expected to be changed by the user
+        if backup.contains(&file.path) {
+            continue;
+        }
+        // Compare size first, since it is much cheaper than hashing
+        if file.size.map(|size| size != metadata.len()).unwrap_or(false) {
+            res.push(Problem::Modified(file.path));
+            continue;
+        }
+        if let Some(checksum) = &file.checksum {
+            if !checksum.cmp_file(&path)? {
+                res.push(Problem::Modified(file.path));
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+/// Get paths listed in %BACKUP% of the local db files list
+fn read_backup_files(path: &Path) -> Result<Vec<String>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        // Not every package has a files list
+        Err(_) => return Ok(Vec::new()),
+    };
+    let fields =
+        pacparse::parse_str(&content).context(format!("Failed to parse {}", path.display()))?;
+    // Each line is path and md5 of the original file, separated by a tab
+    Ok(fields
+        .get("BACKUP")
+        .map(|backup| {
+            backup.lines().filter_map(|line| line.split('\t').next()).map(str::to_owned).collect()
+        })
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        types::{sha256_file, PkgVersion},
+        utils::test::TempDir,
+    };
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    #[test]
+    fn tampered_file() {
+        let root = TempDir::new();
+        let db_dir = root.join("var/lib/pacman/local/foo-1.0-1");
+        fs::create_dir_all(&db_dir).unwrap();
+        fs::create_dir_all(root.join("usr/bin")).unwrap();
+        fs::create_dir_all(root.join("etc")).unwrap();
+
+        let mut mtree = String::from("#mtree\n/set type=file uid=0 gid=0 mode=644\n");
+        for (path, content) in [
+            ("usr/bin/intact", "intact"),
+            ("usr/bin/tampered", "tampered"),
+            ("usr/bin/missing", "missing"),
+            ("etc/foo.conf", "default"),
+        ] {
+            let path_on_disk = root.join(path);
+            fs::write(&path_on_disk, content).unwrap();
+            let hash = sha256_file(&path_on_disk).unwrap();
+            mtree.push_str(&format!(
+                "./{} size={} sha256digest={}\n",
+                path,
+                content.len(),
+                hex::encode(hash)
+            ));
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(mtree.as_bytes()).unwrap();
+        fs::write(db_dir.join("mtree"), encoder.finish().unwrap()).unwrap();
+        fs::write(db_dir.join("files"), "%FILES%\netc/foo.conf\n\n%BACKUP%\netc/foo.conf\tabc\n")
+            .unwrap();
+
+        // Same size, different content
+        fs::write(root.join("usr/bin/tampered"), "TAMPERED").unwrap();
+        fs::remove_file(root.join("usr/bin/missing")).unwrap();
+        // Changed configuration files are fine
+        fs::write(root.join("etc/foo.conf"), "customized").unwrap();
+
+        let pkg = PkgStatus {
+            name: "foo".to_owned(),
+            version: PkgVersion::try_from("1.0-1").unwrap(),
+            install_size: 0,
+            held: false,
+            essential: false,
+        };
+        let problems = verify_pkg(&root, &pkg).unwrap();
+        assert_eq!(
+            problems,
+            vec![
+                Problem::Modified("usr/bin/tampered".to_owned()),
+                Problem::Missing("usr/bin/missing".to_owned())
+            ]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{
+    config::{Blueprints, CachedRepoDb, Opts},
+    executor::MachineStatus,
+    info, msg,
+    solver::pool::{BasicPkgPool, PkgPool},
+};
+
+use anyhow::{bail, Result};
+use console::style;
+use std::collections::{HashMap, VecDeque};
+
+/// Explain why a package is installed by finding a dependency chain to the blueprint
+pub async fn why(
+    repo_db: &CachedRepoDb,
+    blueprint: &Blueprints,
+    opts: &Opts,
+    pkgname: &str,
+) -> Result<()> {
+    let machine_status = MachineStatus::from_opts(opts).await?;
+    if !machine_status.pkgs.contains_key(pkgname) {
+        bail!("Package {} is not installed.", style(pkgname).bold());
+    }
+
+    let requests: HashMap<String, Option<String>> =
+        blueprint.get_pkg_requests()?.into_iter().map(|req| (req.name, req.added_by)).collect();
+    if let Some(added_by) = requests.get(pkgname) {
+        info!("Package {} is requested in the blueprint.", style(pkgname).bold());
+        if let Some(parent) = added_by {
+            msg!("It was added to the blueprint by {}.", style(parent).bold());
+        }
+        return Ok(());
+    }
+
+    let pool = super::load_pool(repo_db)?;
+    // Only consider the versions that are actually installed
+    let installed_id = |name: &str| -> Option<usize> {
+        let status = machine_status.pkgs.get(name)?;
+        let ids = pool.get_pkgs_by_name(name)?;
+        ids.iter()
+            .find(|id| pool.get_pkg_by_id(**id).unwrap().version == status.version)
+            .or_else(|| ids.first())
+            .copied()
+    };
+
+    let target = match installed_id(pkgname) {
+        Some(id) => id,
+        None => bail!("Package {} is not available in any repository.", style(pkgname).bold()),
+    };
+
+    // Breadth-first search upward, so we find the shortest chain
+    let mut parents: HashMap<usize, usize> = HashMap::new();
+    let mut queue = VecDeque::from([target]);
+    let mut root = None;
+    while let Some(id) = queue.pop_front() {
+        let name = &pool.get_pkg_by_id(id).unwrap().name;
+        if id != target && requests.contains_key(name) {
+            root = Some(id);
+            break;
+        }
+        for rdep in pool.rdepends(id)? {
+            let rdep_name = &pool.get_pkg_by_id(rdep).unwrap().name;
+            if installed_id(rdep_name) == Some(rdep)
+                && rdep != target
+                && !parents.contains_key(&rdep)
+            {
+                parents.insert(rdep, id);
+                queue.push_back(rdep);
+            }
+        }
+    }
+
+    match root {
+        Some(root) => {
+            // Walk back down from the blueprint package to the target
+            let mut chain = vec![root];
+            let mut cur = root;
+            while let Some(child) = parents.get(&cur) {
+                chain.push(*child);
+                cur = *child;
+            }
+            let chain: Vec<String> = chain
+                .into_iter()
+                .map(|id| style(&pool.get_pkg_by_id(id).unwrap().name).bold().to_string())
+                .collect();
+            info!("Package {} is installed as a dependency:", style(pkgname).bold());
+            msg!("{}", chain.join(" -> "));
+            let root_name = &pool.get_pkg_by_id(root).unwrap().name;
+            if let Some(Some(parent)) = requests.get(root_name) {
+                msg!("{} was added to the blueprint by {}.", root_name, style(parent).bold());
+            }
+        }
+        None => {
+            info!(
+                "Package {} is not required by any package in the blueprint.",
+                style(pkgname).bold()
+            );
+            msg!("It may be removed the next time sasm executes the blueprint.");
+        }
+    }
+
+    Ok(())
+}
@@ -1 +1,60 @@
+/// Reader of compressed database files
+/// repo-add can compress databases with gzip, zstd or xz, or leave them uncompressed
+use anyhow::{Context, Result};
+use flate2::bufread::GzDecoder;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
 
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Open a file, decompressing it according to its magic bytes
+pub fn open(path: &Path) -> Result<Box<dyn Read>> {
+    let f = File::open(path).context(format!("Failed to open {}", path.display()))?;
+    decompress(BufReader::new(f)).context(format!("Failed to read {}", path.display()))
+}
+
+/// Content without a known magic is read as is
+fn decompress<'a>(mut reader: impl BufRead + 'a) -> Result<Box<dyn Read + 'a>> {
+    let magic: Vec<u8> = reader.fill_buf()?.iter().take(XZ_MAGIC.len()).copied().collect();
+    let res: Box<dyn Read + 'a> = if magic.starts_with(GZIP_MAGIC) {
+        Box::new(GzDecoder::new(reader))
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        Box::new(zstd::Decoder::with_buffer(reader)?)
+    } else if magic.starts_with(XZ_MAGIC) {
+        Box::new(xz2::bufread::XzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    #[test]
+    fn detect_compression() {
+        let content = b"sasm database decompression test".to_vec();
+
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&content).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let zstd = zstd::encode_all(content.as_slice(), 0).unwrap();
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(&content).unwrap();
+        let xz = xz.finish().unwrap();
+
+        for data in [gzip, zstd, xz, content.clone()] {
+            let mut res = Vec::new();
+            decompress(data.as_slice()).unwrap().read_to_end(&mut res).unwrap();
+            assert_eq!(res, content);
+        }
+    }
+}
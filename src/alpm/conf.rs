@@ -0,0 +1,95 @@
+/// Reader for the parts of pacman.conf sasm cares about
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::path::Path;
+
+/// Get patterns of packages listed in `IgnorePkg` directives
+/// These packages are held at their installed version
+pub fn read_ignored_pkgs(path: &Path) -> Result<Vec<Regex>> {
+    read_pkg_patterns(path, "IgnorePkg")
+}
+
+/// Get patterns of packages listed in `HoldPkg` directives
+/// Removing these packages needs explicit confirmation
+pub fn read_essential_pkgs(path: &Path) -> Result<Vec<Regex>> {
+    read_pkg_patterns(path, "HoldPkg")
+}
+
+fn read_pkg_patterns(path: &Path, directive: &str) -> Result<Vec<Regex>> {
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read_to_string(path)
+        .context(format!("Failed to read pacman configuration {}.", path.display()))?;
+    parse_list_directive(&content, directive).iter().map(|pattern| glob_to_regex(pattern)).collect()
+}
+
+/// Translate a shell style glob (`*` and `?`) to an anchored regex
+/// Every other character is matched literally
+pub fn glob_to_regex(pattern: &str) -> Result<Regex> {
+    let mut res = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => res.push_str(".*"),
+            '?' => res.push('.'),
+            c => res.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    res.push('$');
+    Regex::new(&res).context(format!("Invalid package name pattern {pattern}."))
+}
+
+/// Get values of a directive that takes a space separated list, like `IgnorePkg`
+fn parse_list_directive(content: &str, directive: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    for line in content.lines() {
+        // Strip comments
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim() == directive {
+                res.extend(value.split_whitespace().map(|name| name.to_owned()));
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ignored_pkgs() {
+        let content = "[options]
+# IgnorePkg = commented
+IgnorePkg   = linux linux-headers # keep kernel
+IgnoreGroup = gnome
+IgnorePkg=firefox
+
+HoldPkg     = pacman glibc
+
+[core]
+Include = /etc/pacman.d/mirrorlist
+";
+        assert_eq!(
+            parse_list_directive(content, "IgnorePkg"),
+            vec!["linux", "linux-headers", "firefox"]
+        );
+        assert_eq!(parse_list_directive(content, "HoldPkg"), vec!["pacman", "glibc"]);
+    }
+
+    #[test]
+    fn glob() {
+        let names = ["linux", "linux-lts", "linux-zen", "linux-zen-headers", "linux+git", "gcc"];
+        let matches = |pattern: &str| -> Vec<&str> {
+            let re = glob_to_regex(pattern).unwrap();
+            names.iter().copied().filter(|name| re.is_match(name)).collect()
+        };
+        assert_eq!(matches("linux"), vec!["linux"]);
+        assert_eq!(matches("linux-*"), vec!["linux-lts", "linux-zen", "linux-zen-headers"]);
+        assert_eq!(matches("linux-z?n"), vec!["linux-zen"]);
+        // Regex special characters are literal
+        assert_eq!(matches("linux+git"), vec!["linux+git"]);
+        assert_eq!(matches("*"), names.to_vec());
+    }
+}
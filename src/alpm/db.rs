@@ -1,7 +1,6 @@
 /// The pacman db reader
 use crate::{
     debug,
-    solver::pool::PkgPool,
     types::{Checksum, PkgMeta, PkgSource, PkgVersion, VersionRequirement},
     utils::{downloader, pacparse},
     warn,
@@ -10,19 +9,17 @@ use anyhow::{anyhow, bail, Context, Result};
 use rayon::prelude::*;
 use std::{
     collections::HashMap,
-    fs::File,
     io::{BufRead, BufReader, Read},
     path::Path,
 };
 
-use flate2::read::GzDecoder;
 use tar::Archive;
 
-pub fn import(db: &Path, pool: &mut dyn PkgPool, baseurl: &str) -> Result<()> {
-    debug!("Importing package database from {}", db.display());
-    let f = File::open(db)?;
-    let gzipdecoder = GzDecoder::new(f);
-    let mut tar = Archive::new(gzipdecoder);
+/// Read all packages in a pacman db, in the order they appear in the db
+pub fn read(db: &Path, repo: &str, baseurl: &str) -> Result<Vec<PkgMeta>> {
+    debug!("Reading package database from {}", db.display());
+    let mut res = Vec::new();
+    let mut tar = Archive::new(super::archive::open(db)?);
 
     for file in tar.entries()? {
         let file = file.context("error reading file from db")?;
@@ -30,9 +27,10 @@ pub fn import(db: &Path, pool: &mut dyn PkgPool, baseurl: &str) -> Result<()> {
         let path_str = path.display().to_string();
         if path.ends_with("desc") {
             // Now we are talking!
-            match parse_desc(file, &path_str) {
-                Ok(pkgmeta) => {
-                    pool.add(pkgmeta);
+            match parse_desc(file, &path_str, baseurl) {
+                Ok(mut pkgmeta) => {
+                    pkgmeta.repo = Some(repo.to_owned());
+                    res.push(pkgmeta);
                 }
                 Err(e) => {
                     warn!("Failed to add {path_str} from {0}: {e}", db.display());
@@ -40,23 +38,25 @@ pub fn import(db: &Path, pool: &mut dyn PkgPool, baseurl: &str) -> Result<()> {
             };
         }
     }
-    Ok(())
+    Ok(res)
 }
 
-fn parse_desc(mut f: impl Read, from: &str) -> Result<PkgMeta> {
+fn parse_desc(mut f: impl Read, from: &str, baseurl: &str) -> Result<PkgMeta> {
     let mut content = String::new();
     f.read_to_string(&mut content).context("error reading desc file from db")?;
     let fields =
         pacparse::parse_str(&content).context(format!("error parsing desc from {from}"))?;
-    let pkgmeta = fields_to_pkgmeta(fields).context(format!("error reading fields from {from}"))?;
+    let pkgmeta =
+        fields_to_pkgmeta(fields, baseurl).context(format!("error reading fields from {from}"))?;
     Ok(pkgmeta)
 }
 
-fn fields_to_pkgmeta(mut f: HashMap<String, String>) -> Result<PkgMeta> {
+fn fields_to_pkgmeta(mut f: HashMap<String, String>, baseurl: &str) -> Result<PkgMeta> {
     // Get name first, for error reporting
     let name = f.remove("NAME").ok_or_else(|| anyhow!("bad metadata: missing NAME"))?;
     // Generate real url
     let path = f.remove("FILENAME").ok_or_else(|| anyhow!("bad metadata: missing FILENAME"))?;
+    let url = format!("{baseurl}/{path}");
 
     // Needed for source, so parse this first
     let download_size =
@@ -77,11 +77,14 @@ fn fields_to_pkgmeta(mut f: HashMap<String, String>) -> Result<PkgMeta> {
             .parse()?,
         provides: get_pkg_list(&name, "PROVIDES", &mut f)?,
         replaces: get_pkg_list(&name, "REPLACES", &mut f)?,
-        source: PkgSource::Http((path, download_size, {
-            if let Some(hex) = f.get("SHA256SUM") {
-                Checksum::from_sha256_str(&hex)?
-            } else if let Some(hex) = f.get("SHA512SUM") {
+        maintainer: f.remove("PACKAGER"),
+        homepage: f.remove("URL"),
+        source: PkgSource::Http((url, download_size, {
+            // Prefer the stronger hash when both are present
+            if let Some(hex) = f.get("SHA512SUM") {
                 Checksum::from_sha512_str(&hex)?
+            } else if let Some(hex) = f.get("SHA256SUM") {
+                Checksum::from_sha256_str(&hex)?
             } else {
                 bail!(
                         "Metadata for package {} does not contain the checksum field (SHA256 or SHA512).",
@@ -89,6 +92,8 @@ fn fields_to_pkgmeta(mut f: HashMap<String, String>) -> Result<PkgMeta> {
                     )
             }
         })),
+        // Filled by the caller
+        repo: None,
     })
 }
 
@@ -113,3 +118,73 @@ fn get_pkg_list(
     // It's fine to have nothing
     Ok(out)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prefer_sha512() {
+        let fields = HashMap::from([
+            ("NAME".to_string(), "foo".to_string()),
+            ("FILENAME".to_string(), "foo-1.0-1-x86_64.pkg.tar.zst".to_string()),
+            ("CSIZE".to_string(), "1024".to_string()),
+            ("ISIZE".to_string(), "4096".to_string()),
+            ("DESC".to_string(), "Foo".to_string()),
+            ("VERSION".to_string(), "1.0-1".to_string()),
+            ("SHA256SUM".to_string(), "ab".repeat(32)),
+            ("SHA512SUM".to_string(), "cd".repeat(64)),
+        ]);
+        let meta = fields_to_pkgmeta(fields, "https://repo.example.org/core").unwrap();
+        match meta.source {
+            PkgSource::Http((url, size, checksum)) => {
+                assert_eq!(url, "https://repo.example.org/core/foo-1.0-1-x86_64.pkg.tar.zst");
+                assert_eq!(size, 1024);
+                assert_eq!(checksum, Checksum::from_sha512_str(&"cd".repeat(64)).unwrap());
+            }
+            PkgSource::Local(_) => panic!("Local source from http repo"),
+        }
+    }
+
+    #[test]
+    fn maintainer_and_homepage() {
+        let desc = "%FILENAME%
+foo-1.0-1-x86_64.pkg.tar.zst
+
+%NAME%
+foo
+
+%VERSION%
+1.0-1
+
+%DESC%
+Foo
+
+%CSIZE%
+1024
+
+%ISIZE%
+4096
+
+%SHA256SUM%
+abababababababababababababababababababababababababababababababab
+
+%URL%
+https://foo.example.org
+
+%PACKAGER%
+Foo Bar <foo@example.org>
+";
+        let meta =
+            parse_desc(desc.as_bytes(), "foo-1.0-1/desc", "https://repo.example.org/core").unwrap();
+        assert_eq!(meta.homepage.as_deref(), Some("https://foo.example.org"));
+        assert_eq!(meta.maintainer.as_deref(), Some("Foo Bar <foo@example.org>"));
+
+        // Both are optional
+        let desc = desc.split("%URL%").next().unwrap();
+        let meta =
+            parse_desc(desc.as_bytes(), "foo-1.0-1/desc", "https://repo.example.org/core").unwrap();
+        assert_eq!(meta.homepage, None);
+        assert_eq!(meta.maintainer, None);
+    }
+}
@@ -0,0 +1,106 @@
+/// The pacman files db reader
+use crate::{debug, utils::pacparse};
+use anyhow::{Context, Result};
+use std::{io::Read, path::Path};
+use tar::Archive;
+
+/// Find packages with files whose path contains `keyword`
+/// Returns (PkgName, Paths) in the order they appear in the db
+pub fn search(db: &Path, keyword: &str, bin: bool) -> Result<Vec<(String, Vec<String>)>> {
+    debug!("Searching file database {}", db.display());
+    search_reader(super::archive::open(db)?, keyword, bin)
+}
+
+fn search_reader(
+    reader: impl Read,
+    keyword: &str,
+    bin: bool,
+) -> Result<Vec<(String, Vec<String>)>> {
+    let mut res = Vec::new();
+    let mut tar = Archive::new(reader);
+    for file in tar.entries()? {
+        let mut file = file.context("error reading file from files db")?;
+        let path = file.path()?.to_path_buf();
+        if !path.ends_with("files") {
+            continue;
+        }
+        // File lists are stored as NAME-VERSION-RELEASE/files
+        let pkgname = match path
+            .parent()
+            .and_then(|dir| dir.to_str())
+            .and_then(|dir| dir.rsplitn(3, '-').nth(2))
+        {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+
+        let mut content = String::new();
+        file.read_to_string(&mut content).context("error reading files from files db")?;
+        let fields = pacparse::parse_str(&content)
+            .context(format!("error parsing files of {}", path.display()))?;
+        let paths: Vec<String> = match fields.get("FILES") {
+            Some(files) => files
+                .lines()
+                // Skip directories
+                .filter(|p| !p.ends_with('/'))
+                .filter(|p| !bin || is_bin(p))
+                // Paths in files db are relative to root
+                .map(|p| format!("/{p}"))
+                .filter(|p| p.contains(keyword))
+                .collect(),
+            None => continue,
+        };
+        if !paths.is_empty() {
+            res.push((pkgname, paths));
+        }
+    }
+
+    Ok(res)
+}
+
+/// Whether the file is directly under a `bin` or `sbin` directory
+fn is_bin(path: &str) -> bool {
+    matches!(
+        Path::new(path).parent().and_then(|dir| dir.file_name()).and_then(|dir| dir.to_str()),
+        Some("bin") | Some("sbin")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn files_db(pkgs: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (dir, files) in pkgs {
+            let content = format!("%FILES%\n{files}\n");
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, format!("{dir}/files"), content.as_bytes()).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn search_files() {
+        let db = files_db(&[
+            ("foo-1.0-1", "usr/\nusr/bin/\nusr/bin/foo\nusr/share/foo/README"),
+            ("lib-bar-2.0-3", "usr/\nusr/lib/\nusr/lib/libbar.so"),
+        ]);
+
+        let res = search_reader(db.as_slice(), "/usr/bin/foo", false).unwrap();
+        assert_eq!(res, vec![("foo".to_string(), vec!["/usr/bin/foo".to_string()])]);
+
+        let res = search_reader(db.as_slice(), "libbar", false).unwrap();
+        assert_eq!(res, vec![("lib-bar".to_string(), vec!["/usr/lib/libbar.so".to_string()])]);
+
+        // Only files under bin directories
+        let res = search_reader(db.as_slice(), "foo", false).unwrap();
+        assert_eq!(res[0].1.len(), 2);
+        let res = search_reader(db.as_slice(), "foo", true).unwrap();
+        assert_eq!(res, vec![("foo".to_string(), vec!["/usr/bin/foo".to_string()])]);
+        assert!(search_reader(db.as_slice(), "libbar", true).unwrap().is_empty());
+    }
+}
@@ -52,7 +52,10 @@ pub async fn read_alpm_local_db(root: &Path) -> Result<HashMap<String, PkgStatus
             } else {
                 0
             };
-            state.insert(name.clone(), PkgStatus { name, version, install_size });
+            state.insert(
+                name.clone(),
+                PkgStatus { name, version, install_size, held: false, essential: false },
+            );
         }
     }
     Ok(state)
@@ -1,3 +1,6 @@
 pub mod archive;
+pub mod conf;
 pub mod db;
+pub mod files;
 pub mod local;
+pub mod mtree;
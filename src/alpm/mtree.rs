@@ -0,0 +1,131 @@
+/// Reader of the mtree file pacman keeps for every installed package
+use crate::types::Checksum;
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::{collections::HashMap, fs::File, io::Read, path::Path};
+
+/// A regular file recorded in mtree
+#[derive(Debug, PartialEq, Eq)]
+pub struct MtreeFile {
+    /// Path relative to root
+    pub path: String,
+    pub size: Option<u64>,
+    pub checksum: Option<Checksum>,
+}
+
+/// Read regular files from a gzipped mtree file
+pub fn read(path: &Path) -> Result<Vec<MtreeFile>> {
+    let f = File::open(path).context(format!("Failed to open {}", path.display()))?;
+    let mut content = String::new();
+    GzDecoder::new(f)
+        .read_to_string(&mut content)
+        .context(format!("Failed to read {}", path.display()))?;
+    parse(&content).context(format!("Failed to parse {}", path.display()))
+}
+
+fn parse(content: &str) -> Result<Vec<MtreeFile>> {
+    let mut res = Vec::new();
+    // Keywords set by /set, used when an entry doesn't have them
+    let mut defaults: HashMap<&str, &str> = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let first = fields.next().unwrap();
+        match first {
+            "/set" => {
+                for (k, v) in fields.filter_map(|kv| kv.split_once('=')) {
+                    defaults.insert(k, v);
+                }
+                continue;
+            }
+            "/unset" => {
+                for k in fields {
+                    if k == "all" {
+                        defaults.clear();
+                    } else {
+                        defaults.remove(k);
+                    }
+                }
+                continue;
+            }
+            _ => (),
+        }
+
+        let mut keywords = defaults.clone();
+        for (k, v) in fields.filter_map(|kv| kv.split_once('=')) {
+            keywords.insert(k, v);
+        }
+        if keywords.get("type").copied().unwrap_or("file") != "file" {
+            continue;
+        }
+        let path = unescape(first.trim_start_matches("./"))?;
+        // Package metadata like .PKGINFO is never installed
+        if path.starts_with('.') {
+            continue;
+        }
+        let size = match keywords.get("size") {
+            Some(size) => Some(size.parse().context(format!("Bad size for {}", path))?),
+            None => None,
+        };
+        let checksum = match keywords.get("sha256digest") {
+            Some(hash) => Some(Checksum::from_sha256_str(hash)?),
+            None => None,
+        };
+        res.push(MtreeFile { path, size, checksum });
+    }
+
+    Ok(res)
+}
+
+/// Paths in mtree escape special characters as backslash and three octal digits
+fn unescape(s: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&c, tail)) = rest.split_first() {
+        if c == b'\\' && tail.len() >= 3 {
+            let oct = std::str::from_utf8(&tail[..3])?;
+            bytes.push(u8::from_str_radix(oct, 8).context(format!("Bad escape in {}", s))?);
+            rest = &tail[3..];
+        } else {
+            bytes.push(c);
+            rest = tail;
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_mtree() {
+        let content = "#mtree
+/set type=file uid=0 gid=0 mode=644
+./.BUILDINFO time=1.0 size=5 sha256digest=2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824
+./usr time=1.0 mode=755 type=dir
+./usr/bin/foo time=1.0 mode=755 size=5 sha256digest=2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824
+./usr/lib/libfoo.so time=1.0 mode=777 type=link link=libfoo.so.1
+/unset type
+./usr/share/foo\\040bar time=1.0 type=file size=0
+";
+        let files = parse(content).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, "usr/bin/foo");
+        assert_eq!(files[0].size, Some(5));
+        assert_eq!(
+            files[0].checksum,
+            Some(
+                Checksum::from_sha256_str(
+                    "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+                )
+                .unwrap()
+            )
+        );
+        assert_eq!(files[1].path, "usr/share/foo bar");
+        assert_eq!(files[1].checksum, None);
+    }
+}
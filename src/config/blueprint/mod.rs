@@ -2,11 +2,11 @@ mod parse;
 mod variables;
 use parse::{read_blueprint_from_file, BlueprintLine};
 
-use crate::{error, info, msg, types::VersionRequirement};
+use crate::{error, info, msg, types::VersionRequirement, utils::atomic::atomic_write};
 
 use anyhow::{bail, Context, Result};
 use console::style;
-use std::{fs::OpenOptions, os::unix::fs::FileExt, path::PathBuf};
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, PartialEq, Eq, Default, Clone)]
 pub struct PkgRequest {
@@ -14,6 +14,10 @@ pub struct PkgRequest {
     pub version: VersionRequirement,
     pub added_by: Option<String>,
     pub local: bool,
+    // Pull in optional dependencies of this package as well
+    pub recommends: bool,
+    // Comment after the request on the same line, without the leading `#`
+    pub trailing_comment: Option<String>,
 }
 
 impl std::fmt::Display for PkgRequest {
@@ -30,11 +34,17 @@ impl std::fmt::Display for PkgRequest {
         if self.local {
             sections.push("local".to_owned());
         }
+        if self.recommends {
+            sections.push("recommends".to_owned());
+        }
         // Write it
         if !sections.is_empty() {
             let joined = sections.join(", ");
             write!(f, " ({})", joined)?;
         }
+        if let Some(comment) = &self.trailing_comment {
+            write!(f, " #{}", comment)?;
+        }
         Ok(())
     }
 }
@@ -46,14 +56,16 @@ pub struct Blueprints {
     user_blueprint_modified: bool,
     user: Vec<BlueprintLine>,
     vendor: Vec<(PathBuf, Vec<BlueprintLine>)>,
+    // System architecture, for filling variables
+    arch: String,
 }
 
 impl Blueprints {
-    pub fn from_files(user: PathBuf, vendor: &[PathBuf]) -> Result<Self> {
-        let user_blueprint = read_blueprint_from_file(&user)?;
+    pub fn from_files(user: PathBuf, vendor: &[PathBuf], arch: &str) -> Result<Self> {
+        let user_blueprint = read_blueprint_from_file(&user, arch)?;
         let mut vendor_blueprints = Vec::with_capacity(vendor.len());
         for path in vendor {
-            vendor_blueprints.push((path.clone(), read_blueprint_from_file(path)?));
+            vendor_blueprints.push((path.clone(), read_blueprint_from_file(path, arch)?));
         }
 
         Ok(Blueprints {
@@ -61,37 +73,84 @@ impl Blueprints {
             user_blueprint_modified: false,
             user: user_blueprint,
             vendor: vendor_blueprints,
+            arch: arch.to_owned(),
         })
     }
 
-    pub fn get_pkg_requests(&self) -> Vec<PkgRequest> {
+    /// Get all package requests, with requests for the same package merged into one
+    pub fn get_pkg_requests(&self) -> Result<Vec<PkgRequest>> {
         // Add user blueprint first
-        let mut res: Vec<PkgRequest> = self
-            .user
-            .iter()
-            .filter_map(|mut line| match &mut line {
-                BlueprintLine::PkgRequest(req) => {
-                    // Fill variables
-                    let mut req = req.clone();
-                    req.name = variables::fill_variables(&req.name).unwrap();
-                    Some(req)
-                }
-                _ => None,
-            })
-            .collect();
+        let mut all = Vec::new();
+        collect_requests(&self.user, &mut all);
 
         // Then add vendor blueprint
         for (_, vendor) in &self.vendor {
-            for line in vendor {
-                if let BlueprintLine::PkgRequest(req) = line {
-                    res.push(req.clone());
+            collect_requests(vendor, &mut all);
+        }
+
+        // Fill variables
+        for req in all.iter_mut() {
+            req.name = variables::fill_variables(&req.name, &self.arch)?;
+        }
+
+        // Duplicates are allowed, so we shall merge them here
+        let mut res: Vec<PkgRequest> = Vec::with_capacity(all.len());
+        for req in all {
+            match res.iter_mut().find(|existing| existing.name == req.name) {
+                Some(existing) => {
+                    existing.version = existing.version.combine(&req.version).context(format!(
+                        "Conflicting version requirements for {}: {} and {}.",
+                        style(&req.name).bold(),
+                        existing.version,
+                        req.version
+                    ))?;
+                    existing.local |= req.local;
+                    existing.recommends |= req.recommends;
+                    if existing.added_by.is_none() {
+                        existing.added_by = req.added_by;
+                    }
                 }
+                None => res.push(req),
             }
         }
+        Ok(res)
+    }
+
+    /// Check that requests for the same package across all blueprints can be satisfied together
+    pub fn validate(&self) -> Result<()> {
+        let mut all = Vec::new();
+        collect_requests_with_source(&self.user, &self.user_blueprint_path, &mut all);
+        for (path, vendor) in &self.vendor {
+            collect_requests_with_source(vendor, path, &mut all);
+        }
+        for (_, req) in all.iter_mut() {
+            req.name = variables::fill_variables(&req.name, &self.arch)?;
+        }
 
-        // Duplicates are allowed, so we shall dedup here
-        res.dedup();
-        res
+        for (i, (path, req)) in all.iter().enumerate() {
+            let previous = &all[..i];
+            let mut merged = req.version.clone();
+            for entry in previous.iter().filter(|(_, other)| other.name == req.name) {
+                if let Ok(combined) = merged.combine(&entry.1.version) {
+                    merged = combined;
+                    continue;
+                }
+                // Blame the earliest request that conflicts with this one on its own, if any
+                let (other_path, other) = previous
+                    .iter()
+                    .find(|(_, o)| o.name == req.name && req.version.combine(&o.version).is_err())
+                    .unwrap_or(entry);
+                bail!(
+                    "Conflicting version requirements for {}: {} in {} and {} in {}.",
+                    style(&req.name).bold(),
+                    other.version,
+                    other_path.display(),
+                    req.version,
+                    path.display()
+                );
+            }
+        }
+        Ok(())
     }
 
     pub fn add(
@@ -120,12 +179,19 @@ impl Blueprints {
             );
         }
 
+        // Keep the comment and recommends of the entry we are modifying
+        let existing = self.user.iter().find_map(|line| match line {
+            BlueprintLine::PkgRequest(req) if modify && req.name == pkgname => Some(req.clone()),
+            _ => None,
+        });
         let version = ver_req.unwrap_or_default();
         let pkgreq = PkgRequest {
             name: pkgname.to_string(),
             version,
             added_by: added_by.map(|pkgname| pkgname.to_owned()),
             local,
+            recommends: existing.as_ref().map(|req| req.recommends).unwrap_or(false),
+            trailing_comment: existing.and_then(|req| req.trailing_comment),
         };
         if modify && self.user_list_contains(pkgname) {
             // Remove old entry first
@@ -136,6 +202,26 @@ impl Blueprints {
         Ok(())
     }
 
+    /// Pull in optional dependencies of a package in user blueprint as well
+    pub fn set_recommends(&mut self, pkgname: &str) -> Result<()> {
+        for line in self.user.iter_mut() {
+            if let BlueprintLine::PkgRequest(req) = line {
+                if req.name == pkgname {
+                    if !req.recommends {
+                        req.recommends = true;
+                        self.user_blueprint_modified = true;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        bail!("Package {} not found in user blueprint.", style(pkgname).bold())
+    }
+
+    /// Remove a request from user blueprint
+    /// With `remove_recomms`, requests added by this package are removed too, see
+    /// `remove_affiliated`. This also holds when the package is only forgotten and stays
+    /// installed: requests it added are kept otherwise, until `remove_orphans` drops them.
     pub fn remove(&mut self, pkgname: &str, remove_recomms: bool) -> Result<()> {
         if !self.user_list_contains(pkgname) {
             if let Some(path) = self.vendor_list_contains(pkgname) {
@@ -172,6 +258,42 @@ impl Blueprints {
         }
     }
 
+    /// Remove requests in user blueprint that were added by packages no longer requested
+    /// Returns the removed requests
+    pub fn remove_orphans(&mut self) -> Vec<PkgRequest> {
+        let mut removed = Vec::new();
+        // Removing a request may orphan the requests it added, so repeat until nothing changes
+        loop {
+            let mut requested = Vec::new();
+            collect_requests(&self.user, &mut requested);
+            for (_, vendor) in &self.vendor {
+                collect_requests(vendor, &mut requested);
+            }
+
+            let orphans: Vec<PkgRequest> = self
+                .user
+                .iter()
+                .filter_map(|line| match line {
+                    BlueprintLine::PkgRequest(req) => match &req.added_by {
+                        Some(parent) if !requested.iter().any(|r| &r.name == parent) => {
+                            Some(req.clone())
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect();
+            if orphans.is_empty() {
+                break;
+            }
+            for orphan in &orphans {
+                self.remove_affiliated(orphan.added_by.as_ref().unwrap());
+            }
+            removed.extend(orphans);
+        }
+        removed
+    }
+
     // Write back user blueprint
     pub fn export(&self) -> Result<bool> {
         if !self.user_blueprint_modified {
@@ -185,14 +307,15 @@ impl Blueprints {
                 BlueprintLine::Comment(content) => res.push_str(&format!("#{}\n", content)),
                 BlueprintLine::EmptyLine => res.push('\n'),
                 BlueprintLine::PkgRequest(req) => res.push_str(&format!("{}\n", req)),
+                // Included blueprints are never modified
+                BlueprintLine::Include(path, _, _) => {
+                    res.push_str(&format!("@include {}\n", path.display()))
+                }
             }
         }
 
-        // Open user blueprint
-        let blueprint_file =
-            OpenOptions::new().write(true).truncate(true).open(&self.user_blueprint_path)?;
-        blueprint_file.set_len(0)?;
-        blueprint_file.write_all_at(&res.into_bytes(), 0).context(format!(
+        // Replace user blueprint atomically, so an interruption won't corrupt it
+        atomic_write(&self.user_blueprint_path, res.as_bytes()).context(format!(
             "Failed to write to blueprint file {}.",
             self.user_blueprint_path.display()
         ))?;
@@ -211,7 +334,11 @@ impl Blueprints {
         false
     }
 
+    /// Find the vendor blueprint (or blueprint included by others) that contains a package
     pub fn vendor_list_contains(&self, pkgname: &str) -> Option<PathBuf> {
+        if let Some(path) = find_included(&self.user, pkgname) {
+            return Some(path);
+        }
         for (path, vendor) in &self.vendor {
             for line in vendor {
                 if let BlueprintLine::PkgRequest(req) = line {
@@ -220,7 +347,137 @@ impl Blueprints {
                     }
                 }
             }
+            if let Some(path) = find_included(vendor, pkgname) {
+                return Some(path);
+            }
         }
         None
     }
 }
+
+/// Collect package requests from blueprint lines, including the included blueprints
+fn collect_requests(lines: &[BlueprintLine], res: &mut Vec<PkgRequest>) {
+    for line in lines {
+        match line {
+            BlueprintLine::PkgRequest(req) => res.push(req.clone()),
+            BlueprintLine::Include(_, _, included) => collect_requests(included, res),
+            _ => (),
+        }
+    }
+}
+
+/// Like `collect_requests`, but also record the blueprint file each request comes from
+fn collect_requests_with_source(
+    lines: &[BlueprintLine],
+    path: &Path,
+    res: &mut Vec<(PathBuf, PkgRequest)>,
+) {
+    for line in lines {
+        match line {
+            BlueprintLine::PkgRequest(req) => res.push((path.to_owned(), req.clone())),
+            BlueprintLine::Include(_, included_path, included) => {
+                collect_requests_with_source(included, included_path, res)
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Find the included blueprint that contains a package
+fn find_included(lines: &[BlueprintLine], pkgname: &str) -> Option<PathBuf> {
+    for line in lines {
+        if let BlueprintLine::Include(_, path, included) = line {
+            let contains = included
+                .iter()
+                .any(|l| matches!(l, BlueprintLine::PkgRequest(req) if req.name == pkgname));
+            if contains {
+                return Some(path.clone());
+            }
+            if let Some(path) = find_included(included, pkgname) {
+                return Some(path);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn blueprints(user: &[(&str, &str)], vendor: &[(&str, &str)]) -> Blueprints {
+        let to_lines = |reqs: &[(&str, &str)]| {
+            reqs.iter()
+                .map(|(name, ver_req)| {
+                    BlueprintLine::PkgRequest(PkgRequest {
+                        name: name.to_string(),
+                        version: VersionRequirement::try_from(*ver_req).unwrap(),
+                        ..Default::default()
+                    })
+                })
+                .collect()
+        };
+        Blueprints {
+            user_blueprint_path: PathBuf::from("user.blueprint"),
+            user_blueprint_modified: false,
+            user: to_lines(user),
+            vendor: vec![(PathBuf::from("vendor.blueprint"), to_lines(vendor))],
+            arch: "x86_64".to_string(),
+        }
+    }
+
+    #[test]
+    fn merge_requests() {
+        let b = blueprints(&[("foo", ">=2.0"), ("bar", "")], &[("baz", ""), ("foo", "<3.0")]);
+        let reqs = b.get_pkg_requests().unwrap();
+        assert_eq!(reqs.len(), 3);
+        assert_eq!(reqs[0].name, "foo");
+        assert_eq!(reqs[0].version, VersionRequirement::try_from(">=2.0, <3.0").unwrap());
+        assert_eq!(reqs[1].name, "bar");
+        assert_eq!(reqs[2].name, "baz");
+    }
+
+    #[test]
+    fn remove_orphans() {
+        let mut b = blueprints(&[("a", ""), ("b", ""), ("c", ""), ("d", "")], &[("e", "")]);
+        let set_added_by = |b: &mut Blueprints, name: &str, parent: &str| {
+            for line in b.user.iter_mut() {
+                if let BlueprintLine::PkgRequest(req) = line {
+                    if req.name == name {
+                        req.added_by = Some(parent.to_string());
+                    }
+                }
+            }
+        };
+        // b <- c <- d chain with b's parent gone, e is in vendor blueprint
+        set_added_by(&mut b, "b", "gone");
+        set_added_by(&mut b, "c", "b");
+        set_added_by(&mut b, "d", "c");
+        set_added_by(&mut b, "a", "e");
+
+        let mut removed: Vec<String> = b.remove_orphans().into_iter().map(|r| r.name).collect();
+        removed.sort();
+        assert_eq!(removed, vec!["b", "c", "d"]);
+        assert!(b.user_list_contains("a"));
+        assert!(b.user_blueprint_modified);
+    }
+
+    #[test]
+    fn merge_requests_conflict() {
+        let b = blueprints(&[("foo", ">=3.0")], &[("foo", "<2.0")]);
+        assert!(b.get_pkg_requests().is_err());
+    }
+
+    #[test]
+    fn validate() {
+        let b = blueprints(&[("foo", ">=2.0"), ("bar", "")], &[("foo", "<3.0"), ("bar", ">=1")]);
+        assert!(b.validate().is_ok());
+
+        let b = blueprints(&[("foo", ">=3.0"), ("bar", "")], &[("bar", ""), ("foo", "<2.0")]);
+        let msg = b.validate().unwrap_err().to_string();
+        assert!(msg.contains("user.blueprint"), "{msg}");
+        assert!(msg.contains("vendor.blueprint"), "{msg}");
+        assert!(msg.contains(">=3.0"), "{msg}");
+        assert!(msg.contains("<2.0"), "{msg}");
+    }
+}
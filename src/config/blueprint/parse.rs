@@ -17,7 +17,7 @@ use nom::{
 use std::{
     fs::File,
     io::{BufRead, BufReader},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -25,19 +25,50 @@ pub enum BlueprintLine {
     PkgRequest(PkgRequest),
     Comment(String),
     EmptyLine,
+    // (Path as written, resolved path, lines of the included blueprint)
+    Include(PathBuf, PathBuf, Vec<BlueprintLine>),
 }
 
-pub fn read_blueprint_from_file(path: &Path) -> Result<Vec<BlueprintLine>> {
+pub fn read_blueprint_from_file(path: &Path, arch: &str) -> Result<Vec<BlueprintLine>> {
+    read_blueprint_recursive(path, arch, &mut Vec::new())
+}
+
+/// Read a blueprint file and all files it includes
+/// `stack` contains the files that are currently being read, for cycle detection
+fn read_blueprint_recursive(
+    path: &Path,
+    arch: &str,
+    stack: &mut Vec<PathBuf>,
+) -> Result<Vec<BlueprintLine>> {
+    let canonical = path
+        .canonicalize()
+        .context(format!("Failed to open blueprint file at {}.", style(path.display()).bold()))?;
+    if stack.contains(&canonical) {
+        bail!("Blueprint {} includes itself.", style(path.display()).bold());
+    }
+
     // Read lines from blueprint file
     let f = File::open(path)
         .context(format!("Failed to open blueprint file at {}.", style(path.display()).bold()))?;
     let reader = BufReader::new(f);
-    let lines = parse_blueprint_lines(reader)
+    let mut lines = parse_blueprint_lines(reader)
         .context(format!("Failed to parse blueprint {}.", style(path.display()).bold()))?;
+
+    // Resolve includes relative to the current file
+    stack.push(canonical);
+    for line in lines.iter_mut() {
+        if let BlueprintLine::Include(include_path, resolved, included) = line {
+            *resolved = path.parent().unwrap_or_else(|| Path::new("")).join(&include_path);
+            *included = read_blueprint_recursive(resolved, arch, stack)
+                .context(format!("Failed to include blueprint {}.", include_path.display()))?;
+        }
+    }
+    stack.pop();
+
     for (no, line) in lines.iter().enumerate() {
         // Try fill variables to sanitize
         if let BlueprintLine::PkgRequest(req) = &line {
-            let new_pkgname = fill_variables(&req.name)?;
+            let new_pkgname = fill_variables(&req.name, arch)?;
             if !new_pkgname.chars().all(is_pkgname_char) {
                 bail!(
                     "Fail to parse blueprint {}: invalid package name at line {}.",
@@ -56,7 +87,7 @@ fn parse_blueprint_lines(reader: impl BufRead) -> Result<Vec<BlueprintLine>> {
     let mut errors = 0;
     for (no, line) in reader.lines().enumerate() {
         let i = line?;
-        match alt((empty_line, comment_line, package_line_wrapper))(&i) {
+        match alt((empty_line, comment_line, include_line, package_line_wrapper))(&i) {
             Ok((_, content)) => {
                 res.push(content);
             }
@@ -88,6 +119,18 @@ fn comment_line(i: &str) -> IResult<&str, BlueprintLine> {
     }
 }
 
+fn include_line(i: &str) -> IResult<&str, BlueprintLine> {
+    let (i, _) = tag("@include")(i)?;
+    let (i, _) = space1(i)?;
+    let (i, path) = not_line_ending(i)?;
+    let path = path.trim_end();
+    if path.is_empty() {
+        return Err(nom::Err::Error(nom::error::Error::from_error_kind(i, ErrorKind::Eof)));
+    }
+    // Files are read later, in read_blueprint_from_file
+    Ok(("", BlueprintLine::Include(PathBuf::from(path), PathBuf::new(), Vec::new())))
+}
+
 fn is_pkgname_char(c: char) -> bool {
     c.is_alphanumeric() || c == '-' || c == '.' || c == '+'
 }
@@ -104,6 +147,7 @@ enum PkgOption {
     VersionRequirement(VersionRequirement),
     AddedBy(String),
     Local,
+    Recommends,
 }
 
 fn pkg_option(i: &str) -> IResult<&str, PkgOption> {
@@ -123,6 +167,10 @@ fn pkg_option(i: &str) -> IResult<&str, PkgOption> {
         return Ok((i, PkgOption::Local));
     }
 
+    if let Ok((i, _)) = tag::<_, _, Error<&str>>("recommends")(i) {
+        return Ok((i, PkgOption::Recommends));
+    }
+
     Err(nom::Err::Error(nom::error::Error::from_error_kind(i, ErrorKind::Alt)))
 }
 
@@ -135,6 +183,8 @@ fn package_line(i: &str) -> IResult<&str, PkgRequest> {
         version: VersionRequirement::default(),
         added_by: None,
         local: false,
+        recommends: false,
+        trailing_comment: None,
     };
 
     let i = if let Ok((i, opts)) = nom::sequence::delimited(
@@ -153,6 +203,9 @@ fn package_line(i: &str) -> IResult<&str, PkgRequest> {
                 PkgOption::Local => {
                     res.local = true;
                 }
+                PkgOption::Recommends => {
+                    res.recommends = true;
+                }
             }
         }
         i
@@ -160,6 +213,14 @@ fn package_line(i: &str) -> IResult<&str, PkgRequest> {
         i
     };
 
+    // Optional trailing comment
+    let i = if let Ok((r, _)) = char::<_, Error<&str>>('#')(i) {
+        res.trailing_comment = Some(r.to_string());
+        ""
+    } else {
+        i
+    };
+
     let (i, _) = nom::combinator::eof(i)?;
 
     Ok((i, res))
@@ -173,7 +234,7 @@ fn package_line_wrapper(i: &str) -> IResult<&str, BlueprintLine> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::PkgVersion;
+    use crate::{types::PkgVersion, utils::test::TempDir};
     use nom::{error::Error, IResult};
 
     #[test]
@@ -208,6 +269,68 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_trailing_comment() {
+        let req = package_line("foo (>=2.0)  # needed for build").unwrap().1;
+        assert_eq!(req.name, "foo");
+        assert_eq!(req.version, VersionRequirement::try_from(">=2.0").unwrap());
+        assert_eq!(req.trailing_comment, Some(" needed for build".to_string()));
+        assert_eq!(req.to_string(), "foo (>=2.0) # needed for build");
+
+        let req = package_line("bar#note").unwrap().1;
+        assert_eq!(req.name, "bar");
+        assert_eq!(req.trailing_comment, Some("note".to_string()));
+
+        let req = package_line("foo (>=2.0)").unwrap().1;
+        assert_eq!(req.trailing_comment, None);
+        assert_eq!(req.to_string(), "foo (>=2.0)");
+
+        // A line starting with `#` is still a comment line
+        let line = parse_blueprint_lines("# foo (>=2.0)".as_bytes()).unwrap();
+        assert_eq!(line, vec![BlueprintLine::Comment(" foo (>=2.0)".to_string())]);
+    }
+
+    #[test]
+    fn test_include_line() {
+        assert_eq!(
+            include_line("@include groups/devtools.blueprint  ").unwrap().1,
+            BlueprintLine::Include(
+                PathBuf::from("groups/devtools.blueprint"),
+                PathBuf::new(),
+                Vec::new()
+            )
+        );
+        assert!(include_line("@include").is_err());
+        assert!(include_line("@include   ").is_err());
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = TempDir::new();
+        std::fs::create_dir_all(dir.join("groups")).unwrap();
+        std::fs::write(dir.join("user.blueprint"), "firefox\n@include groups/dev.blueprint\n")
+            .unwrap();
+        std::fs::write(dir.join("groups/dev.blueprint"), "gcc\n").unwrap();
+
+        let lines = read_blueprint_from_file(&dir.join("user.blueprint"), "x86_64").unwrap();
+        assert_eq!(lines.len(), 2);
+        match &lines[1] {
+            BlueprintLine::Include(path, resolved, included) => {
+                assert_eq!(path, &PathBuf::from("groups/dev.blueprint"));
+                assert_eq!(resolved, &dir.join("groups/dev.blueprint"));
+                assert!(
+                    matches!(&included[0], BlueprintLine::PkgRequest(req) if req.name == "gcc")
+                );
+            }
+            _ => panic!("include not parsed"),
+        }
+
+        // a -> b -> a
+        std::fs::write(dir.join("a.blueprint"), "@include groups/b.blueprint\n").unwrap();
+        std::fs::write(dir.join("groups/b.blueprint"), "@include ../a.blueprint\n").unwrap();
+        assert!(read_blueprint_from_file(&dir.join("a.blueprint"), "x86_64").is_err());
+    }
+
     #[test]
     fn test_pkgname() {
         let t: Vec<(&str, IResult<&str, &str>)> = vec![
@@ -230,9 +353,12 @@ mod tests {
                 version: VersionRequirement {
                     lower_bond: Some((PkgVersion::try_from("1").unwrap(), false)),
                     upper_bond: None,
+                    exclusions: Vec::new(),
                 },
                 added_by: Some("wow".to_string()),
                 local: false,
+                recommends: false,
+                trailing_comment: None,
             },
             (
                 "pkgname (>>1, local, <<2)",
@@ -241,9 +367,12 @@ mod tests {
                     version: VersionRequirement {
                         lower_bond: Some((PkgVersion::try_from("1").unwrap(), false)),
                         upper_bond: Some((PkgVersion::try_from("2").unwrap(), true)),
+                        exclusions: Vec::new(),
                     },
                     added_by: None,
                     local: true,
+                    recommends: false,
+                    trailing_comment: None,
                 },
             ),
         )];
@@ -252,4 +381,20 @@ mod tests {
             assert_eq!(package_line(t.0).unwrap().1, t.1);
         }
     }
+
+    #[test]
+    fn test_recommends() {
+        let req = package_line("foo (recommends)").unwrap().1;
+        assert!(req.recommends);
+        assert_eq!(req.to_string(), "foo (recommends)");
+        assert_eq!(package_line(&req.to_string()).unwrap().1, req);
+
+        let req = package_line("foo (>=1, recommends)").unwrap().1;
+        assert!(req.recommends);
+        assert_eq!(package_line(&req.to_string()).unwrap().1, req);
+
+        let req = package_line("foo").unwrap().1;
+        assert!(!req.recommends);
+        assert_eq!(req.to_string(), "foo");
+    }
 }
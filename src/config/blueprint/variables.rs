@@ -2,15 +2,20 @@ use anyhow::{bail, Result};
 use lazy_static::lazy_static;
 use regex::{Captures, Regex};
 
-pub fn fill_variables(rule: &str) -> Result<String> {
+/// Fill variables like `{KERNEL_VERSION}` in a package name
+/// `arch` is the configured system architecture
+pub fn fill_variables(rule: &str, arch: &str) -> Result<String> {
     lazy_static! {
         static ref EXPANSION: Regex = Regex::new(r"\{([A-Z_]+)}").unwrap();
     }
 
     let kernel_version = get_kernel_version()?;
+    let hostname = get_hostname();
     let mut unknown_variable = Vec::new();
     let res = EXPANSION.replace_all(rule, |caps: &Captures| match caps.get(1).unwrap().as_str() {
         "KERNEL_VERSION" => &kernel_version,
+        "ARCH" => arch,
+        "HOSTNAME" => &hostname,
         unintended => {
             unknown_variable.push(unintended.to_owned());
             ""
@@ -33,3 +38,26 @@ fn get_kernel_version() -> Result<String> {
     }
     bail!("Failed to obtain kernel version: malformed kernel local version.");
 }
+
+fn get_hostname() -> String {
+    nix::sys::utsname::uname().nodename().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn variables() {
+        assert_eq!(fill_variables("vim", "x86_64").unwrap(), "vim");
+        assert_eq!(fill_variables("firmware-{ARCH}", "x86_64").unwrap(), "firmware-x86_64");
+        assert_eq!(
+            fill_variables("config-{HOSTNAME}", "x86_64").unwrap(),
+            format!("config-{}", get_hostname())
+        );
+        let kernel = fill_variables("linux-{KERNEL_VERSION}", "x86_64").unwrap();
+        assert_eq!(kernel, format!("linux-{}", get_kernel_version().unwrap()));
+        assert!(!kernel.contains('{'));
+        assert!(fill_variables("linux-{KERNEL_VER}", "x86_64").is_err());
+    }
+}
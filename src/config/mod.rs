@@ -1,27 +1,95 @@
 mod blueprint;
 pub use blueprint::{Blueprints, PkgRequest};
 mod repo;
-pub use repo::{CachedRepoDb, RepoConfig};
+pub use repo::{bench, CachedRepoDb, RepoConfig};
 
-use crate::{info, warn};
+use crate::{info, types::PinPolicy, warn};
 
 use anyhow::{bail, Context, Result};
-use clap::Parser;
+use clap::{ArgEnum, CommandFactory, Parser};
+use clap_complete::Shell;
 use console::style;
 use serde::{Deserialize, Serialize, Serializer};
 use std::{
     collections::{BTreeMap, HashMap},
     fs,
+    io::Write,
     path::PathBuf,
+    time::Duration,
 };
 
+/// Repository fields sasm used to accept but no longer uses
+const LEGACY_REPO_KEYS: &[&str] = &["distribution", "components", "tags"];
+
 #[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub arch: String,
+    // Filled into mirror urls as $releasever
+    #[serde(default)]
+    pub releasever: Option<String>,
+    // Custom variables for mirror urls, referenced as $name
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+    // Download concurrency limits, in total and per mirror host
+    #[serde(default)]
+    pub max_concurrent_downloads: Option<usize>,
+    #[serde(default)]
+    pub max_downloads_per_host: Option<usize>,
+    // Total download rate limit, in bytes per second
+    #[serde(default)]
+    pub max_bytes_per_sec: Option<u64>,
+    // Delay before retrying a failed download, doubled for every further retry
+    #[serde(default)]
+    pub retry_backoff_ms: Option<u64>,
+    // Version requirement for packages installed without one
+    #[serde(default)]
+    pub default_pin: Option<PinPolicy>,
     #[serde(serialize_with = "ordered_map")]
     pub repo: HashMap<String, RepoConfig>,
 }
 
+impl Config {
+    /// Parse config.toml
+    /// Repositories are checked one by one, so errors can name the offending repository
+    pub fn from_toml(data: &str) -> Result<Self> {
+        let mut table: toml::Table = toml::from_str(data)?;
+        if let Some(repos) = table.get_mut("repo").and_then(|repos| repos.as_table_mut()) {
+            for (name, repo) in repos.iter_mut() {
+                // Drop keys older configurations used to have, instead of refusing to start
+                if let Some(repo) = repo.as_table_mut() {
+                    for key in LEGACY_REPO_KEYS {
+                        if repo.remove(*key).is_some() {
+                            warn!(
+                                "Ignoring obsolete field {} of repository {}.",
+                                style(key).bold(),
+                                style(name).bold()
+                            );
+                        }
+                    }
+                }
+                let context =
+                    format!("Invalid configuration for repository {}.", style(name).bold());
+                let repo: RepoConfig = repo.clone().try_into().context(context.clone())?;
+                repo.check_sanity().context(context)?;
+            }
+        }
+        let config = toml::Value::Table(table).try_into()?;
+        Ok(config)
+    }
+
+    /// Variables available in mirror urls, besides $repo
+    pub fn url_variables(&self) -> HashMap<String, String> {
+        let mut res: HashMap<String, String> =
+            self.variables.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        res.insert("arch".to_string(), self.arch.clone());
+        if let Some(releasever) = &self.releasever {
+            res.insert("releasever".to_string(), releasever.clone());
+        }
+        res
+    }
+}
+
 fn ordered_map<S>(value: &HashMap<String, RepoConfig>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
@@ -48,32 +116,206 @@ pub struct Opts {
     pub verbose: bool,
     #[clap(display_order = 5, long, help = "Don't pipe long output into a pager")]
     pub no_pager: bool,
+    #[clap(display_order = 6, long, help = "Show pending actions without applying them")]
+    pub dry_run: bool,
+    #[clap(display_order = 7, long, help = "Print results as JSON for scripting")]
+    pub json: bool,
+    #[clap(display_order = 8, long, help = "Wait for other running instances instead of failing")]
+    pub wait: bool,
+    #[clap(
+        display_order = 9,
+        long,
+        value_name = "REPO",
+        multiple_occurrences = true,
+        help = "Use a disabled repository for this command"
+    )]
+    pub enable_repo: Vec<String>,
+    #[clap(
+        display_order = 10,
+        long,
+        value_name = "FILE",
+        help = "Plan against installed packages saved by `snapshot` instead of this machine"
+    )]
+    pub machine_state: Option<PathBuf>,
+    #[clap(display_order = 11, long, help = "Don't use colors in output")]
+    pub no_color: bool,
+    #[clap(
+        display_order = 12,
+        long,
+        help = "Fail if metadata of any repository cannot be refreshed, instead of carrying on without it"
+    )]
+    pub strict: bool,
     #[clap(subcommand)]
     pub subcmd: SubCmd,
 }
 
+#[derive(ArgEnum, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressFormat {
+    /// Progress bars on the terminal
+    Bar,
+    /// Newline-delimited JSON events on stderr
+    Json,
+}
+
 #[derive(Parser)]
 pub enum SubCmd {
+    /// Add packages to user blueprint and install them
+    #[clap(display_order = 2)]
+    Install(InstallPkg),
     /// Install and upgrade all packages according to Blueprint
     #[clap(display_order = 4, aliases = &["upgrade"])]
-    Execute,
+    Execute(ExecuteConfig),
+    /// Remove packages from user blueprint
+    #[clap(display_order = 3)]
+    Remove(RemovePkg),
+    /// Remove packages that are no longer required
+    #[clap(display_order = 6)]
+    Autoremove(AutoremoveConfig),
+    /// Pin a package to a specific version
+    #[clap(display_order = 5)]
+    Pick(PickPkg),
+    /// Search packages in repositories
+    #[clap(display_order = 8)]
+    Search(SearchPkg),
+    /// Search packages that contain a certain file
+    #[clap(display_order = 7)]
+    Provide(ProvideFile),
+    /// Show details of a package
+    #[clap(display_order = 9)]
+    Show(ShowPkg),
+    /// List packages in repositories
+    #[clap(display_order = 10)]
+    List(ListConfig),
+    /// Download a package file from the repositories to the current directory
+    #[clap(display_order = 12)]
+    Download(DownloadPkg),
+    /// Show why a package is installed
+    #[clap(display_order = 11)]
+    Why(WhyPkg),
+    /// Save installed packages to a file, for use with --machine-state
+    #[clap(display_order = 13)]
+    Snapshot(SnapshotConfig),
+    /// Check files of installed packages for corruption
+    #[clap(display_order = 14)]
+    Verify(VerifyConfig),
+    /// Print shell completion script to stdout
+    #[clap(hide = true)]
+    Completions {
+        #[clap(arg_enum)]
+        shell: Shell,
+    },
+    /// Benchmark mirrors of repositories
+    #[clap(display_order = 20)]
+    Bench(BenchConfig),
     /// Delete local package cache (optionally metadata cache)
     #[clap(display_order = 21)]
     Clean(CleanConfig),
 }
 
+impl SubCmd {
+    /// Download options of this command, if it downloads anything
+    pub fn download_opts(&self) -> Option<&DownloadOpts> {
+        match self {
+            SubCmd::Install(InstallPkg { download, .. })
+            | SubCmd::Execute(ExecuteConfig { download, .. })
+            | SubCmd::Remove(RemovePkg { download, .. })
+            | SubCmd::Autoremove(AutoremoveConfig { download, .. })
+            | SubCmd::Provide(ProvideFile { download, .. })
+            | SubCmd::Download(DownloadPkg { download, .. }) => Some(download),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Parser)]
 pub struct InstallPkg {
-    /// Package names or deb file names to install
+    /// Package names or deb file names to install, optionally with a version like `foo=1.0-1`
+    /// or `foo>=1.0`
     #[clap(min_values = 1)]
     pub names: Vec<String>,
 
-    /// Don't install recommended packages
+    /// Install recommended packages as well
+    #[clap(long)]
+    pub recommends: bool,
+    /// Pick optional dependencies of the new packages to add to blueprint as well
     #[clap(long)]
-    pub no_recommends: bool,
+    pub install_suggests: bool,
     /// Install local package files rather from the repositories
     #[clap(long)]
     pub local: bool,
+    #[clap(flatten)]
+    pub resolve: ResolveOpts,
+    #[clap(flatten)]
+    pub download: DownloadOpts,
+}
+
+#[derive(Parser)]
+pub struct ExecuteConfig {
+    /// Only upgrade these packages and what they newly require, keep other installed packages at
+    /// their current version
+    #[clap(long, min_values = 1, value_name = "PKG")]
+    pub only: Vec<String>,
+    #[clap(flatten)]
+    pub resolve: ResolveOpts,
+    #[clap(flatten)]
+    pub download: DownloadOpts,
+}
+
+#[derive(Parser)]
+pub struct AutoremoveConfig {
+    #[clap(flatten)]
+    pub resolve: ResolveOpts,
+    #[clap(flatten)]
+    pub download: DownloadOpts,
+}
+
+/// Options of commands that resolve and apply changes to installed packages
+#[derive(Parser)]
+pub struct ResolveOpts {
+    /// Refuse to downgrade any package
+    #[clap(long)]
+    pub no_downgrade: bool,
+    /// With --no-downgrade, still downgrade packages pinned to an older version in blueprint
+    #[clap(long)]
+    pub allow_downgrade: bool,
+    /// Explain which requested packages cannot be satisfied if resolving fails
+    #[clap(long)]
+    pub explain: bool,
+    /// Only install a requested package from the given repository
+    #[clap(
+        long,
+        value_name = "PKG=REPO",
+        multiple_occurrences = true,
+        parse(try_from_str = parse_from_repo)
+    )]
+    pub from_repo: Vec<(String, String)>,
+    /// Keep an installed package at its current version for this command
+    #[clap(long, value_name = "PKG", multiple_occurrences = true)]
+    pub ignore: Vec<String>,
+    /// Keep installed packages matching a pattern like `nvidia-*` at their current version for
+    /// this command
+    #[clap(long, value_name = "GLOB", multiple_occurrences = true)]
+    pub exclude: Vec<String>,
+    /// Install an installed package again, even if its version doesn't change
+    #[clap(long, value_name = "PKG", multiple_occurrences = true)]
+    pub reinstall: Vec<String>,
+    /// Remove essential packages without typing their names to confirm
+    #[clap(long)]
+    pub allow_essential_removal: bool,
+    /// Consider dependencies on a package met without installing it
+    #[clap(long, value_name = "PKG[=VER]", multiple_occurrences = true)]
+    pub assume_installed: Vec<String>,
+}
+
+/// Options of commands that download files
+#[derive(Parser)]
+pub struct DownloadOpts {
+    /// How to report download progress
+    #[clap(long, arg_enum, default_value = "bar")]
+    pub progress: ProgressFormat,
+    /// Limit total download rate, overriding max_bytes_per_sec in config
+    #[clap(long, value_name = "BYTES_PER_SEC")]
+    pub limit_rate: Option<u64>,
 }
 
 #[derive(Parser)]
@@ -84,6 +326,19 @@ pub struct RemovePkg {
     /// Also remove recommended packages
     #[clap(long)]
     pub remove_recommends: bool,
+    /// Also delete configuration files of removed packages
+    #[clap(long)]
+    pub purge: bool,
+    /// Only remove from blueprint, keep the packages installed as they are
+    #[clap(long, conflicts_with = "purge")]
+    pub forget: bool,
+    /// Remove packages many installed packages depend on without explicit confirmation
+    #[clap(long)]
+    pub allow_rdepends: bool,
+    #[clap(flatten)]
+    pub resolve: ResolveOpts,
+    #[clap(flatten)]
+    pub download: DownloadOpts,
 }
 
 #[derive(Parser)]
@@ -96,6 +351,18 @@ pub struct PickPkg {
 pub struct SearchPkg {
     /// Search keyword for package name
     pub keyword: String,
+    /// Ignore case when matching
+    #[clap(short, long)]
+    pub ignore_case: bool,
+    /// Only match package names, not descriptions
+    #[clap(long)]
+    pub names_only: bool,
+}
+
+#[derive(Parser)]
+pub struct ShowPkg {
+    /// Name of the package
+    pub name: String,
 }
 
 #[derive(Parser)]
@@ -105,6 +372,43 @@ pub struct ProvideFile {
     /// Search binary files only
     #[clap(long)]
     pub bin: bool,
+    #[clap(flatten)]
+    pub download: DownloadOpts,
+}
+
+#[derive(Parser)]
+pub struct BenchConfig {
+    /// Reuse results of repositories benchmarked within this many hours
+    #[clap(long, value_name = "HOURS")]
+    pub max_age: Option<u64>,
+}
+
+#[derive(Parser)]
+pub struct ListConfig {
+    /// Only show installed packages
+    #[clap(long)]
+    pub installed: bool,
+    /// Only show installed packages that have a newer version available
+    #[clap(long)]
+    pub upgradable: bool,
+}
+
+#[derive(Parser)]
+pub struct WhyPkg {
+    /// Name of the installed package
+    pub name: String,
+}
+
+#[derive(Parser)]
+pub struct SnapshotConfig {
+    /// File to write the snapshot to
+    pub path: PathBuf,
+}
+
+#[derive(Parser)]
+pub struct VerifyConfig {
+    /// Only check these packages, instead of all installed ones
+    pub names: Vec<String>,
 }
 
 #[derive(Parser)]
@@ -112,6 +416,12 @@ pub struct CleanConfig {
     /// Remove both package cache and local database
     #[clap(short, long)]
     pub all: bool,
+    /// Only remove cached packages not modified for this long, like `30d` or `2w` (default: days)
+    #[clap(long, value_name = "DURATION", parse(try_from_str = parse_duration))]
+    pub older_than: Option<Duration>,
+    /// Only remove cached packages superseded by a newer cached version
+    #[clap(long)]
+    pub keep_latest: bool,
 }
 
 #[derive(Parser)]
@@ -121,4 +431,157 @@ pub struct DownloadPkg {
     /// Use latest version automatically
     #[clap(long)]
     pub latest: bool,
+    /// Directory to save the package file to, instead of the current directory
+    #[clap(short, long, value_name = "DIR")]
+    pub output: Option<PathBuf>,
+    #[clap(flatten)]
+    pub download: DownloadOpts,
+}
+
+/// Parse `PKG=REPO` for --from-repo
+fn parse_from_repo(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((pkg, repo)) if !pkg.is_empty() && !repo.is_empty() => {
+            Ok((pkg.to_string(), repo.to_string()))
+        }
+        _ => Err(format!("expected PKG=REPO, got {s}")),
+    }
+}
+
+/// Parse a duration like `12h`, `30d` or `2w` for --older-than, plain numbers are days
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(pos) => s.split_at(pos),
+        None => (s, "d"),
+    };
+    let secs = match unit {
+        "h" => 3600,
+        "d" => 24 * 3600,
+        "w" => 7 * 24 * 3600,
+        _ => return Err(format!("expected a number followed by h, d or w, got {s}")),
+    };
+    match num.parse::<u64>().ok().and_then(|num| num.checked_mul(secs)) {
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => Err(format!("expected a number followed by h, d or w, got {s}")),
+    }
+}
+
+/// Generate shell completion script for sasm
+pub fn gen_completions(shell: Shell, out: &mut dyn Write) {
+    let mut cmd = Opts::command();
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, out);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn completions() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+            let mut out = Vec::new();
+            gen_completions(shell, &mut out);
+            let script = String::from_utf8(out).unwrap();
+            assert!(script.contains("sasm"));
+            assert!(script.contains("execute"));
+        }
+    }
+
+    #[test]
+    fn parse_config() {
+        let config = Config::from_toml(
+            "arch = \"x86_64\"\n[repo.core]\nsource = \"https://example.org/$repo/os/$arch\"\nkeys = []\n",
+        )
+        .unwrap();
+        assert!(config.repo.contains_key("core"));
+
+        let e = Config::from_toml(
+            "arch = \"x86_64\"\n[repo.core]\nsource = \"https://example.org\"\nkeys = []\nprioirty = 1\n",
+        )
+        .unwrap_err();
+        let e = format!("{e:#}");
+        assert!(e.contains("repository core"));
+        assert!(e.contains("unknown field `prioirty`"));
+
+        let e = Config::from_toml(
+            "arch = \"x86_64\"\n[repo.core]\nsource = \"https://example.org\"\nkeys = []\n[repo.testing]\nkeys = []\n",
+        )
+        .unwrap_err();
+        let e = format!("{e:#}");
+        assert!(e.contains("repository testing"));
+        assert!(e.contains("missing field `source`"));
+
+        let e = Config::from_toml(
+            "arch = \"x86_64\"\n[repo.extra]\nsource = { mirrorlist = \"/nonexistent\" }\nkeys = []\n",
+        )
+        .unwrap_err();
+        let e = format!("{e:#}");
+        assert!(e.contains("repository extra"));
+        assert!(e.contains("does not exist"));
+    }
+
+    #[test]
+    fn parse_legacy_config() {
+        // The example config.toml from older documentation
+        let config = Config::from_toml(
+            r#"arch = "amd64"
+
+[repo.main]
+source = "https://repo.aosc.io"
+distribution = "stable"
+components = ["main"]
+keys = ["main.asc"]
+priority = 10
+tags = ["topic-template"]
+"#,
+        )
+        .unwrap();
+        let main = &config.repo["main"];
+        assert_eq!(main.keys, vec!["main.asc".to_string()]);
+        assert_eq!(main.priority, Some(10));
+    }
+
+    #[test]
+    fn scoped_options() {
+        let opts = Opts::parse_from(["sasm", "install", "--no-downgrade", "--ignore", "b", "a"]);
+        match opts.subcmd {
+            SubCmd::Install(install) => {
+                assert_eq!(install.names, vec!["a"]);
+                assert!(install.resolve.no_downgrade);
+                assert_eq!(install.resolve.ignore, vec!["b"]);
+            }
+            _ => panic!("not parsed as install"),
+        }
+        let opts = Opts::parse_from(["sasm", "download", "--limit-rate", "1024", "a"]);
+        assert_eq!(opts.subcmd.download_opts().unwrap().limit_rate, Some(1024));
+
+        // Only accepted by commands they apply to
+        assert!(Opts::try_parse_from(["sasm", "--no-downgrade", "execute"]).is_err());
+        assert!(Opts::try_parse_from(["sasm", "search", "--reinstall", "a", "b"]).is_err());
+    }
+
+    #[test]
+    fn from_repo() {
+        assert_eq!(
+            parse_from_repo("foo=testing").unwrap(),
+            ("foo".to_string(), "testing".to_string())
+        );
+        assert!(parse_from_repo("foo").is_err());
+        assert!(parse_from_repo("=testing").is_err());
+        assert!(parse_from_repo("foo=").is_err());
+    }
+
+    #[test]
+    fn duration() {
+        let days = |n| Duration::from_secs(n * 24 * 3600);
+        assert_eq!(parse_duration("30").unwrap(), days(30));
+        assert_eq!(parse_duration("30d").unwrap(), days(30));
+        assert_eq!(parse_duration("2w").unwrap(), days(14));
+        assert_eq!(parse_duration("12h").unwrap(), Duration::from_secs(12 * 3600));
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("1y").is_err());
+        assert!(parse_duration("1.5d").is_err());
+        assert!(parse_duration("-1d").is_err());
+    }
 }
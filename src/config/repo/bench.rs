@@ -0,0 +1,147 @@
+/// Mirror benchmarks and their cached results
+use crate::{msg, types::Checksum, utils::atomic::atomic_write};
+
+use anyhow::{bail, Context, Result};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Benchmark result of all mirrors of a repository
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BenchRecord {
+    // Unix timestamp of the benchmark
+    pub timestamp: u64,
+    // (BaseURL, TimeTaken), fastest first. None if the mirror failed
+    pub results: Vec<(String, Option<Duration>)>,
+}
+
+impl BenchRecord {
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(now().saturating_sub(self.timestamp))
+    }
+}
+
+/// Benchmark results of every repository
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct BenchCache {
+    repos: BTreeMap<String, BenchRecord>,
+}
+
+impl BenchCache {
+    /// Load cached results. A missing cache is an empty one
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Ok(BenchCache::default());
+        }
+        let content = fs::read(path)?;
+        let cache = serde_json::from_slice(&content)
+            .context(format!("Failed to parse benchmark cache {}.", path.display()))?;
+        Ok(cache)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        atomic_write(path, &serde_json::to_vec_pretty(self)?)
+    }
+
+    pub fn get(&self, repo: &str) -> Option<&BenchRecord> {
+        self.repos.get(repo)
+    }
+
+    /// Get the result of a repository if it is not older than `max_age`
+    pub fn get_fresh(&self, repo: &str, max_age: Duration) -> Option<&BenchRecord> {
+        self.get(repo).filter(|record| record.age() <= max_age)
+    }
+
+    pub fn insert(&mut self, repo: &str, results: Vec<(String, Option<Duration>)>) {
+        self.repos.insert(repo.to_owned(), BenchRecord { timestamp: now(), results });
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Time downloading `remote_path` from every mirror
+/// The downloaded content is verified against `checksum`
+/// Returns (BaseURL, TimeTaken) with the fastest first. Failed mirrors are at the end with None
+pub async fn benchmark_mirrors(
+    client: &Client,
+    urls: &[String],
+    remote_path: &str,
+    checksum: &Checksum,
+) -> Vec<(String, Option<Duration>)> {
+    let mut res = Vec::with_capacity(urls.len());
+    for url in urls {
+        let start = Instant::now();
+        match try_download(client, &format!("{url}/{remote_path}"), checksum).await {
+            Ok(()) => res.push((url.clone(), Some(start.elapsed()))),
+            Err(e) => {
+                msg!("Mirror {url} failed to complete benchmark: {e}");
+                res.push((url.clone(), None));
+            }
+        }
+    }
+    sort_results(&mut res);
+    res
+}
+
+/// Fastest first, failed ones last
+fn sort_results(results: &mut [(String, Option<Duration>)]) {
+    results.sort_by_key(|(_, time)| time.unwrap_or(Duration::MAX));
+}
+
+async fn try_download(client: &Client, url: &str, checksum: &Checksum) -> Result<()> {
+    let mut resp = client.get(url).send().await?;
+    resp.error_for_status_ref()?;
+    let mut validator = checksum.get_validator();
+    while let Some(chunk) = resp.chunk().await? {
+        validator.update(&chunk);
+    }
+    if !validator.finish() {
+        bail!("checksum mismatched");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test::TempDir;
+
+    #[test]
+    fn cache() {
+        let dir = TempDir::new();
+        let path = dir.join("bench.json");
+        assert!(BenchCache::load(&path).unwrap().get("core").is_none());
+
+        let mut results = vec![
+            ("https://slow.example.org/core".to_string(), Some(Duration::from_millis(900))),
+            ("https://broken.example.org/core".to_string(), None),
+            ("https://fast.example.org/core".to_string(), Some(Duration::from_millis(100))),
+        ];
+        sort_results(&mut results);
+        assert_eq!(results[0].0, "https://fast.example.org/core");
+        assert_eq!(results[2].0, "https://broken.example.org/core");
+
+        let mut cache = BenchCache::default();
+        cache.insert("core", results.clone());
+        cache.save(&path).unwrap();
+        let cache = BenchCache::load(&path).unwrap();
+        assert_eq!(cache.get("core").unwrap().results, results);
+        assert!(cache.get_fresh("core", Duration::from_secs(3600)).is_some());
+
+        // Results older than max age are ignored
+        let mut cache = cache;
+        cache.repos.get_mut("core").unwrap().timestamp -= 7200;
+        assert!(cache.get_fresh("core", Duration::from_secs(3600)).is_none());
+        assert!(cache.get("core").is_some());
+    }
+}
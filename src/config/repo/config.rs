@@ -1,58 +1,159 @@
+use super::mirrorlist;
+
 use anyhow::{bail, Result};
+use console::style;
 use serde::{Deserialize, Serialize, Serializer};
-use std::path::PathBuf;
+use std::{collections::HashMap, path::PathBuf};
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct RepoConfig {
     pub source: Mirror,
     pub keys: Vec<String>,
+    // Packages from repositories with higher priority are preferred, regardless of version
+    #[serde(default)]
+    pub priority: Option<i32>,
+    // Disabled repositories are neither refreshed nor used, unless enabled on the command line
+    #[serde(default)]
+    pub enabled: Option<bool>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 #[serde(untagged)]
 pub enum Mirror {
     Simple(String),
-    MirrorList(PathBuf),
+    // Use a struct variant so untagged deserialization can tell it apart from Simple
+    MirrorList { mirrorlist: PathBuf },
 }
 
 impl RepoConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.unwrap_or(true)
+    }
+
     /// Check if there's some mirror available
     pub fn check_sanity(&self) -> Result<()> {
-        // TODO: Implement mirrorlist
-        if matches!(self.source, Mirror::MirrorList(_)) {
-            bail!("mirrorlist not supported yet!")
+        if let Mirror::MirrorList { mirrorlist: path } = &self.source {
+            if !path.is_file() {
+                bail!("Mirrorlist {} does not exist.", style(path.display()).bold());
+            }
+            if mirrorlist::read_servers_from_file(path)?.is_empty() {
+                bail!("Mirrorlist {} does not contain any server.", style(path.display()).bold());
+            }
         }
 
         Ok(())
     }
 
+    /// Get the base url of the preferred mirror for this repository
+    pub fn get_url(&self, name: &str, variables: &HashMap<String, String>) -> Result<String> {
+        match self.get_urls(name, variables)?.into_iter().next() {
+            Some(url) => Ok(url),
+            None => bail!("No mirror available for repository {}.", style(name).bold()),
+        }
+    }
+
     /// Get base urls for all repositories
     /// Returns a list of possible urls for the repository
-    pub fn get_url(&self, name: &str, arch: &str) -> Result<String> {
-        let mut url = match &self.source {
-            Mirror::Simple(m) => {
-                let mut url = m.clone();
-                normalize_mirror_url(&mut url);
-                url
-            }
-            Mirror::MirrorList(path) => {
-                unimplemented!()
-            }
+    /// `$repo` is replaced with the repository name, other `$name`s are looked up in `variables`
+    pub fn get_urls(&self, name: &str, variables: &HashMap<String, String>) -> Result<Vec<String>> {
+        let urls = match &self.source {
+            Mirror::Simple(m) => vec![m.clone()],
+            Mirror::MirrorList { mirrorlist: path } => mirrorlist::read_servers_from_file(path)?,
         };
 
-        // Replace variables
-        // $repo: Repository name
-        // $arch: Current system architecture
-        url = url.replace("$repo", name);
-        url = url.replace("$arch", arch);
-        Ok(url)
+        let mut res = Vec::with_capacity(urls.len());
+        for mut url in urls {
+            normalize_mirror_url(&mut url);
+            res.push(fill_url_variables(&url, name, variables)?);
+        }
+        Ok(res)
     }
 }
 
+/// Replace `$name` in url with its value
+fn fill_url_variables(
+    url: &str,
+    repo: &str,
+    variables: &HashMap<String, String>,
+) -> Result<String> {
+    let mut res = String::with_capacity(url.len());
+    let mut rest = url;
+    while let Some(pos) = rest.find('$') {
+        res.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        let len =
+            rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        let var = &rest[..len];
+        if var.is_empty() {
+            // Not a variable
+            res.push('$');
+            continue;
+        }
+        let value = match var {
+            "repo" => repo,
+            _ => match variables.get(var) {
+                Some(value) => value,
+                None => bail!(
+                    "Unknown variable {} in mirror url {}.",
+                    style(format!("${var}")).bold(),
+                    url
+                ),
+            },
+        };
+        res.push_str(value);
+        rest = &rest[len..];
+    }
+    res.push_str(rest);
+    Ok(res)
+}
+
 fn normalize_mirror_url(url: &mut String) {
     if url.ends_with('/') {
         url.pop();
     }
 }
 
-pub enum MirrorlistLine {}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn variables() -> HashMap<String, String> {
+        HashMap::from([
+            ("arch".to_string(), "x86_64".to_string()),
+            ("releasever".to_string(), "2024".to_string()),
+            ("flavor".to_string(), "desktop".to_string()),
+        ])
+    }
+
+    #[test]
+    fn releasever() {
+        assert_eq!(
+            fill_url_variables(
+                "https://example.org/$releasever/$repo/os/$arch",
+                "core",
+                &variables()
+            )
+            .unwrap(),
+            "https://example.org/2024/core/os/x86_64"
+        );
+    }
+
+    #[test]
+    fn custom_variable() {
+        assert_eq!(
+            fill_url_variables("https://example.org/$flavor/$repo", "extra", &variables()).unwrap(),
+            "https://example.org/desktop/extra"
+        );
+        // A lone `$` is not a variable
+        assert_eq!(
+            fill_url_variables("https://example.org/$/$repo", "extra", &variables()).unwrap(),
+            "https://example.org/$/extra"
+        );
+    }
+
+    #[test]
+    fn unknown_variable() {
+        assert!(fill_url_variables("https://example.org/$basearch", "core", &variables()).is_err());
+    }
+}
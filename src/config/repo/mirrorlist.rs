@@ -1 +1,92 @@
 /// Parse pacman-style mirrorlist file
+use anyhow::{bail, Context, Result};
+use console::style;
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MirrorlistLine {
+    Server(String),
+    Comment(String),
+    EmptyLine,
+}
+
+pub fn read_mirrorlist_from_file(path: &Path) -> Result<Vec<MirrorlistLine>> {
+    let f = File::open(path)
+        .context(format!("Failed to open mirrorlist at {}.", style(path.display()).bold()))?;
+    let reader = BufReader::new(f);
+    parse_mirrorlist_lines(reader)
+        .context(format!("Failed to parse mirrorlist {}.", style(path.display()).bold()))
+}
+
+/// Get all server urls in a mirrorlist file, in the order they appear
+pub fn read_servers_from_file(path: &Path) -> Result<Vec<String>> {
+    let servers = read_mirrorlist_from_file(path)?
+        .into_iter()
+        .filter_map(|line| match line {
+            MirrorlistLine::Server(url) => Some(url),
+            _ => None,
+        })
+        .collect();
+    Ok(servers)
+}
+
+fn parse_mirrorlist_lines(reader: impl BufRead) -> Result<Vec<MirrorlistLine>> {
+    let mut res = Vec::new();
+    for (no, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            res.push(MirrorlistLine::EmptyLine);
+        } else if let Some(comment) = line.strip_prefix('#') {
+            res.push(MirrorlistLine::Comment(comment.to_owned()));
+        } else {
+            // Both `Server = url` and plain `url` are accepted
+            let url = match line.split_once('=') {
+                Some((key, url)) if key.trim() == "Server" => url.trim(),
+                Some(_) => bail!("Unknown key in mirrorlist at line {}.", no + 1),
+                None => line,
+            };
+            if url.is_empty() {
+                bail!("Empty server url in mirrorlist at line {}.", no + 1);
+            }
+            res.push(MirrorlistLine::Server(url.to_owned()));
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mirrorlist_lines() {
+        let mirrorlist = "# Main mirror
+Server = https://repo.example.org/$repo/os/$arch
+
+https://mirror.example.org/$repo/$arch/
+  # Disabled
+  #Server = https://bad.example.org/$repo
+";
+        let lines = parse_mirrorlist_lines(mirrorlist.as_bytes()).unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                MirrorlistLine::Comment(" Main mirror".to_string()),
+                MirrorlistLine::Server("https://repo.example.org/$repo/os/$arch".to_string()),
+                MirrorlistLine::EmptyLine,
+                MirrorlistLine::Server("https://mirror.example.org/$repo/$arch/".to_string()),
+                MirrorlistLine::Comment(" Disabled".to_string()),
+                MirrorlistLine::Comment("Server = https://bad.example.org/$repo".to_string()),
+            ]
+        );
+
+        assert!(parse_mirrorlist_lines("Include = /etc/other".as_bytes()).is_err());
+        assert!(parse_mirrorlist_lines("Server =".as_bytes()).is_err());
+    }
+}
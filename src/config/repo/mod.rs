@@ -1,11 +1,15 @@
+pub mod bench;
 mod config;
+mod mirrorlist;
 pub use config::RepoConfig;
 
 use crate::{
     debug, info,
     utils::downloader::{Compression, DownloadJob, Downloader},
+    warn,
 };
-use anyhow::Result;
+use anyhow::{bail, format_err, Result};
+use bench::{BenchCache, BenchRecord};
 use console::style;
 use std::{collections::HashMap, path::PathBuf};
 
@@ -13,74 +17,370 @@ use std::{collections::HashMap, path::PathBuf};
 pub struct CachedRepoDb {
     // root directory for dbs
     root: PathBuf,
-    arch: String,
+    // Variables to be filled into mirror urls
+    variables: HashMap<String, String>,
     repos: HashMap<String, RepoConfig>,
+    // Mirror benchmark results, used to prefer faster mirrors
+    bench: BenchCache,
 }
 
 impl CachedRepoDb {
-    pub fn new(root: PathBuf, repos: HashMap<String, RepoConfig>, arch: &str) -> Self {
-        CachedRepoDb { root, arch: arch.to_owned(), repos }
+    /// Disabled repositories are left out, unless listed in `enable`
+    pub fn new(
+        root: PathBuf,
+        repos: HashMap<String, RepoConfig>,
+        variables: HashMap<String, String>,
+        enable: &[String],
+    ) -> Self {
+        let repos = repos
+            .into_iter()
+            .filter(|(name, repo)| repo.is_enabled() || enable.contains(name))
+            .collect();
+        CachedRepoDb { root, variables, repos, bench: BenchCache::default() }
+    }
+
+    /// Prefer faster mirrors according to benchmark results
+    pub fn with_bench_results(mut self, bench: BenchCache) -> Self {
+        self.bench = bench;
+        self
+    }
+
+    /// Get base urls of a repository, fastest mirror first
+    fn get_urls(&self, name: &str, repo: &RepoConfig) -> Result<Vec<String>> {
+        let urls = repo.get_urls(name, &self.variables)?;
+        Ok(order_by_bench(urls, self.bench.get(name)))
     }
 
     /// Get the remote (relative) path and local path for a repository
     pub fn get_package_db(&self, name: &str) -> Result<(String, PathBuf)> {
         let remote_relative_path = format!("{0}.db", name);
-        let local_path = self.root.join(self.root.join(format!("{}.db", name)));
+        let local_path = self.root.join(format!("{}.db", name));
 
         Ok((remote_relative_path, local_path))
     }
 
-    // Get (BaseURL, FilePath) of all configured repos
-    pub fn get_all_package_db(&self) -> Result<Vec<(String, PathBuf)>> {
+    // Get (RepoName, BaseURL, FilePath) of all configured repos
+    pub fn get_all_package_db(&self) -> Result<Vec<(String, String, PathBuf)>> {
         let mut res = Vec::new();
-        for repo in &self.repos {
-            res.push(self.get_package_db(repo.0)?);
+        for (name, repo) in &self.repos {
+            let (_, local_path) = self.get_package_db(name)?;
+            let url = self.get_urls(name, repo)?.into_iter().next().ok_or_else(|| {
+                format_err!("No mirror available for repository {}.", style(name).bold())
+            })?;
+            res.push((name.clone(), url, local_path));
         }
         Ok(res)
     }
 
+    /// Get (RepoName, BaseURLs) of all configured repos, sorted by repository name
+    pub fn get_all_mirror_urls(&self) -> Result<Vec<(String, Vec<String>)>> {
+        let mut res = Vec::new();
+        for (name, repo) in &self.repos {
+            res.push((name.clone(), repo.get_urls(name, &self.variables)?));
+        }
+        res.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(res)
+    }
+
+    /// Get priorities of repos that have one
+    pub fn get_repo_priorities(&self) -> HashMap<String, i32> {
+        self.repos
+            .iter()
+            .filter_map(|(name, repo)| repo.priority.map(|p| (name.clone(), p)))
+            .collect()
+    }
+
+    /// Get the path of the cached package pool built from package dbs
+    pub fn get_pool_cache_path(&self) -> PathBuf {
+        self.root.join("pool.cache")
+    }
+
     pub fn get_contents_db(&self, name: &str) -> Result<(String, PathBuf)> {
-        let arch = &self.arch;
         let remote_relative_path = format!("{0}.files", name);
-        let local_path = self.root.join(self.root.join(format!("{}.files", name)));
+        let local_path = self.root.join(format!("{}.files", name));
 
         Ok((remote_relative_path, local_path))
     }
 
-    // Get (BaseURL, FilePath) of all configured repos
+    // Get (RepoName, FilePath) of all configured repos
     pub fn get_all_contents_db(&self) -> Result<Vec<(String, PathBuf)>> {
         let mut res = Vec::new();
-        for repo in &self.repos {
-            res.push(self.get_contents_db(repo.0)?);
+        for name in self.repos.keys() {
+            let (_, local_path) = self.get_contents_db(name)?;
+            res.push((name.clone(), local_path));
         }
         Ok(res)
     }
 
-    pub async fn update(&self, downloader: &Downloader) -> Result<()> {
+    /// Refresh file databases, which are only needed for searching files
+    /// Like package databases, they are only downloaded again if the server says they changed
+    pub async fn update_contents(&self, downloader: &Downloader) -> Result<()> {
+        info!("Refreshing local file databases...");
+
+        let mut download_jobs = Vec::with_capacity(self.repos.len());
+        for name in self.repos.keys() {
+            let (remote_path, _local_path) = self.get_contents_db(name)?;
+            download_jobs.push(self.gen_download_job(
+                name,
+                &remote_path,
+                format!("File database for {}", style(name).bold()),
+            )?);
+        }
+        downloader.fetch(download_jobs, &self.root, false).await?;
+
+        Ok(())
+    }
+
+    fn gen_download_job(
+        &self,
+        name: &str,
+        remote_path: &str,
+        description: String,
+    ) -> Result<DownloadJob> {
+        let repo = self
+            .repos
+            .get(name)
+            .ok_or_else(|| format_err!("Repository {} is not configured.", style(name).bold()))?;
+        let mut urls =
+            self.get_urls(name, repo)?.into_iter().map(|base| format!("{}/{}", base, remote_path));
+        Ok(DownloadJob {
+            url: urls.next().ok_or_else(|| {
+                format_err!("No mirror available for repository {}.", style(name).bold())
+            })?,
+            fallback_urls: urls.collect(),
+            current_url: None,
+            description: Some(description),
+            filename: Some(remote_path.to_owned()),
+            size: None,
+            compression: Compression::None(None),
+            // Databases are replaced in place, so the server can tell us if ours is up to date
+            conditional: true,
+        })
+    }
+
+    /// Refresh package databases
+    /// Unless `strict`, a repository that fails to refresh only gets a warning, as long as some
+    /// other repository refreshes fine. Its cached database is used if there is one, otherwise
+    /// it is left out.
+    pub async fn update(&mut self, downloader: &Downloader, strict: bool) -> Result<()> {
         info!("Refreshing local repository metadata...");
 
         let package_dbs = self.get_all_package_db()?;
         if crate::verbose() {
             for db in &package_dbs {
-                debug!("Downloading {} {}", db.0, db.1.display());
+                debug!("Downloading {} {}", db.1, db.2.display());
             }
         }
 
         let mut download_jobs = Vec::with_capacity(package_dbs.len());
-        for (name, repo) in &self.repos {
-            let (remote_path, _local_path) = self.get_package_db(&name)?;
-            download_jobs.push(DownloadJob {
-                url: format!("{}/{}", repo.get_url(name, &self.arch)?, remote_path),
-                description: Some(format!("Package database for {}", style(name).bold())),
-                filename: Some(format!("{}.db", name)),
-                size: None,
-                compression: Compression::None(None),
-            })
+        // Remote path -> repository name, to tell which repository a failed job belongs to
+        let mut job_repos = HashMap::with_capacity(package_dbs.len());
+        for name in self.repos.keys() {
+            let (remote_path, _local_path) = self.get_package_db(name)?;
+            download_jobs.push(self.gen_download_job(
+                name,
+                &remote_path,
+                format!("Package database for {}", style(name).bold()),
+            )?);
+            job_repos.insert(remote_path, name.clone());
         }
 
         // The downloader will verify the checksum for us
-        downloader.fetch(download_jobs, &self.root, false).await?;
+        if strict {
+            downloader.fetch(download_jobs, &self.root, false).await?;
+            return Ok(());
+        }
+        let (_, failed) = downloader.fetch_each(download_jobs, &self.root, false).await?;
+        let mut failed: Vec<(String, anyhow::Error)> = failed
+            .into_iter()
+            .map(|(job, e)| {
+                (job.filename.and_then(|f| job_repos.remove(&f)).unwrap_or_default(), e)
+            })
+            .collect();
+        failed.sort_by(|a, b| a.0.cmp(&b.0));
+        if !failed.is_empty() && failed.len() == self.repos.len() {
+            for (name, e) in failed {
+                warn!("Failed to refresh repository {}: {:#}", style(&name).bold(), e);
+            }
+            bail!("None of the repositories could be refreshed.");
+        }
+        for (name, e) in failed {
+            let (_, local_path) = self.get_package_db(&name)?;
+            if local_path.is_file() {
+                warn!(
+                    "Failed to refresh repository {}, using cached metadata: {:#}",
+                    style(&name).bold(),
+                    e
+                );
+            } else {
+                warn!(
+                    "Failed to refresh repository {}, leaving it out: {:#}",
+                    style(&name).bold(),
+                    e
+                );
+                self.repos.remove(&name);
+            }
+        }
 
         Ok(())
     }
 }
+
+/// Order mirrors by benchmark results
+/// Benchmarked mirrors come first, fastest first, then mirrors without results in config order.
+/// Mirrors that failed the benchmark come last.
+fn order_by_bench(urls: Vec<String>, record: Option<&BenchRecord>) -> Vec<String> {
+    let record = match record {
+        Some(record) => record,
+        None => return urls,
+    };
+    let mut urls: Vec<(usize, String)> = urls
+        .into_iter()
+        .map(|url| {
+            let rank = match record.results.iter().position(|(bench_url, _)| bench_url == &url) {
+                Some(i) if record.results[i].1.is_some() => i,
+                Some(_) => usize::MAX,
+                None => usize::MAX - 1,
+            };
+            (rank, url)
+        })
+        .collect();
+    // Stable sort keeps config order for mirrors with the same rank
+    urls.sort_by_key(|(rank, _)| *rank);
+    urls.into_iter().map(|(_, url)| url).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test::TempDir;
+    use config::Mirror;
+    use std::time::Duration;
+
+    fn repo(enabled: Option<bool>) -> RepoConfig {
+        RepoConfig {
+            source: Mirror::Simple("https://example.org/$repo/os/$arch".to_string()),
+            keys: Vec::new(),
+            priority: None,
+            enabled,
+        }
+    }
+
+    #[test]
+    fn conditional_db_jobs() {
+        let repos = HashMap::from([("core".to_string(), repo(None))]);
+        let variables = HashMap::from([("arch".to_string(), "x86_64".to_string())]);
+        let db = CachedRepoDb::new(PathBuf::new(), repos, variables, &[]);
+        for (remote_path, _) in
+            [db.get_package_db("core").unwrap(), db.get_contents_db("core").unwrap()]
+        {
+            let job = db.gen_download_job("core", &remote_path, String::new()).unwrap();
+            assert_eq!(job.url, format!("https://example.org/core/os/x86_64/{remote_path}"));
+            assert!(job.conditional);
+        }
+    }
+
+    #[test]
+    fn prefer_fastest_mirror() {
+        let urls: Vec<String> = [
+            "https://a.example.org",
+            "https://b.example.org",
+            "https://c.example.org",
+            "https://d.example.org",
+        ]
+        .iter()
+        .map(|url| url.to_string())
+        .collect();
+        // No benchmark data, keep config order
+        assert_eq!(order_by_bench(urls.clone(), None), urls);
+
+        // Results are sorted fastest first. d was added to the mirrorlist after benchmarking
+        let record = BenchRecord {
+            timestamp: 0,
+            results: vec![
+                ("https://c.example.org".to_string(), Some(Duration::from_millis(100))),
+                ("https://a.example.org".to_string(), Some(Duration::from_millis(500))),
+                ("https://b.example.org".to_string(), None),
+            ],
+        };
+        assert_eq!(
+            order_by_bench(urls, Some(&record)),
+            vec![
+                "https://c.example.org",
+                "https://a.example.org",
+                "https://d.example.org",
+                "https://b.example.org"
+            ]
+        );
+    }
+
+    #[test]
+    fn db_path() {
+        // A relative root must not be joined twice
+        let db = CachedRepoDb::new(PathBuf::from("var/cache"), HashMap::new(), HashMap::new(), &[]);
+        assert_eq!(
+            db.get_package_db("core").unwrap(),
+            ("core.db".to_string(), PathBuf::from("var/cache/core.db"))
+        );
+        assert_eq!(
+            db.get_contents_db("core").unwrap(),
+            ("core.files".to_string(), PathBuf::from("var/cache/core.files"))
+        );
+    }
+
+    #[test]
+    fn disabled_repo() {
+        let repos = HashMap::from([
+            ("core".to_string(), repo(None)),
+            ("extra".to_string(), repo(Some(true))),
+            ("testing".to_string(), repo(Some(false))),
+        ]);
+        let names = |db: &CachedRepoDb| {
+            let mut names: Vec<String> =
+                db.get_all_package_db().unwrap().into_iter().map(|(name, _, _)| name).collect();
+            names.sort();
+            names
+        };
+
+        let variables = HashMap::from([("arch".to_string(), "x86_64".to_string())]);
+        let db = CachedRepoDb::new(PathBuf::from("/tmp"), repos.clone(), variables.clone(), &[]);
+        assert_eq!(names(&db), vec!["core", "extra"]);
+
+        let enable = ["testing".to_string()];
+        let db = CachedRepoDb::new(PathBuf::from("/tmp"), repos, variables, &enable);
+        assert_eq!(names(&db), vec!["core", "extra", "testing"]);
+    }
+
+    #[tokio::test]
+    async fn failed_repo() {
+        let base = TempDir::new();
+        let mirror = base.join("mirror");
+        std::fs::create_dir_all(mirror.join("core")).unwrap();
+        std::fs::write(mirror.join("core/core.db"), "core").unwrap();
+        // extra has no database on the mirror
+        let url = format!("file://{}/$repo", mirror.display());
+        let repo = RepoConfig {
+            source: Mirror::Simple(url),
+            keys: Vec::new(),
+            priority: None,
+            enabled: None,
+        };
+        let repos =
+            HashMap::from([("core".to_string(), repo.clone()), ("extra".to_string(), repo)]);
+        let downloader = Downloader::new().with_retry_backoff(Some(Duration::from_millis(1)));
+
+        let mut db = CachedRepoDb::new(base.join("strict"), repos.clone(), HashMap::new(), &[]);
+        assert!(db.update(&downloader, true).await.is_err());
+
+        let mut db = CachedRepoDb::new(base.join("db"), repos, HashMap::new(), &[]);
+        db.update(&downloader, false).await.unwrap();
+        let dbs = db.get_all_package_db().unwrap();
+        assert_eq!(dbs.len(), 1);
+        assert_eq!(dbs[0].0, "core");
+        assert_eq!(std::fs::read_to_string(&dbs[0].2).unwrap(), "core");
+
+        // Nothing left to build a pool from
+        std::fs::remove_file(mirror.join("core/core.db")).unwrap();
+        assert!(db.update(&downloader, false).await.is_err());
+    }
+}
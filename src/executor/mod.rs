@@ -1,7 +1,19 @@
-use crate::types::{PkgActions, PkgMeta, PkgStatus};
+use crate::{
+    config::Opts,
+    solver::{pool::PkgPool, sort::sort_pkgs},
+    types::{PkgActions, PkgMeta, PkgStatus},
+    utils::atomic::atomic_write,
+    warn,
+};
 
-use anyhow::{Context, Result};
-use std::{collections::HashMap, fs, path::Path};
+use anyhow::{bail, Context, Result};
+use console::style;
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+};
 
 /// Status of this machine
 pub struct MachineStatus {
@@ -17,13 +29,192 @@ impl MachineStatus {
                 .context("Failed to initialize ALPM local database.")?;
         }
 
-        let pkgs = crate::alpm::local::read_alpm_local_db(&alpm_local_db_root).await?;
+        let mut pkgs = crate::alpm::local::read_alpm_local_db(&alpm_local_db_root).await?;
+
+        // Packages ignored by pacman are held at their installed version
+        let ignored = crate::alpm::conf::read_ignored_pkgs(&root.join("etc/pacman.conf"))?;
+        // And those pacman holds on to are essential
+        let essential = crate::alpm::conf::read_essential_pkgs(&root.join("etc/pacman.conf"))?;
+        for pkg in pkgs.values_mut() {
+            pkg.held = ignored.iter().any(|pattern| pattern.is_match(&pkg.name));
+            pkg.essential = essential.iter().any(|pattern| pattern.is_match(&pkg.name));
+        }
 
         Ok(MachineStatus { pkgs })
     }
 
+    /// Read the status of the machine sasm operates on
+    /// With --machine-state, use the saved snapshot instead of the real ALPM local db
+    pub async fn from_opts(opts: &Opts) -> Result<Self> {
+        match &opts.machine_state {
+            Some(path) => Self::load(path),
+            None => Self::new(&opts.root).await,
+        }
+    }
+
+    /// Load a snapshot written by `save`
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read(path)
+            .context(format!("Failed to read machine state snapshot {}.", path.display()))?;
+        let pkgs = serde_json::from_slice(&content)
+            .context(format!("Invalid machine state snapshot {}.", path.display()))?;
+        Ok(MachineStatus { pkgs })
+    }
+
+    /// Save installed packages to a snapshot
+    pub fn save(&self, path: &Path) -> Result<()> {
+        // Sorted, so snapshots of the same state are identical
+        let ordered: BTreeMap<_, _> = self.pkgs.iter().collect();
+        atomic_write(path, &serde_json::to_vec_pretty(&ordered)?)
+    }
+
+    /// Hold installed packages for this invocation only
+    pub fn hold(&mut self, names: &[String]) -> Result<()> {
+        for name in names {
+            match self.pkgs.get_mut(name) {
+                Some(pkg) => pkg.held = true,
+                None => bail!("Cannot ignore {}: package is not installed.", style(name).bold()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Hold every installed package except the given ones, for this invocation only
+    pub fn hold_except(&mut self, names: &[String]) -> Result<()> {
+        for name in names {
+            if !self.pkgs.contains_key(name) {
+                bail!("Cannot upgrade {}: package is not installed.", style(name).bold());
+            }
+        }
+        for pkg in self.pkgs.values_mut() {
+            if !names.contains(&pkg.name) {
+                pkg.held = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Hold installed packages whose names match any of the patterns, for this invocation only
+    /// Returns the number of packages held
+    pub fn hold_matching(&mut self, patterns: &[Regex]) -> usize {
+        let mut count = 0;
+        for pkg in self.pkgs.values_mut() {
+            if patterns.iter().any(|pattern| pattern.is_match(&pkg.name)) {
+                pkg.held = true;
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Get names of installed packages that depend on the given package
+    pub fn installed_rdepends(&self, pool: &dyn PkgPool, pkgname: &str) -> Result<Vec<String>> {
+        let ids = pool.get_pkgs_by_name(pkgname).unwrap_or_default();
+        // Prefer the installed version, if it is still available
+        let installed: Vec<usize> = match self.pkgs.get(pkgname) {
+            Some(pkg) => ids
+                .iter()
+                .copied()
+                .filter(|id| pool.get_pkg_by_id(*id).unwrap().version == pkg.version)
+                .collect(),
+            None => Vec::new(),
+        };
+        let ids = if installed.is_empty() { ids } else { installed };
+
+        let mut res = Vec::new();
+        for id in ids {
+            for rdep in pool.rdepends(id)? {
+                let name = &pool.get_pkg_by_id(rdep).unwrap().name;
+                if name != pkgname && self.pkgs.contains_key(name) && !res.contains(name) {
+                    res.push(name.clone());
+                }
+            }
+        }
+        res.sort();
+        Ok(res)
+    }
+
+    /// Get installed packages that are held
+    pub fn held_pkgs(&self) -> Vec<&PkgStatus> {
+        self.pkgs.values().filter(|pkg| pkg.held).collect()
+    }
+
+    /// Names of essential packages that actions will remove
+    pub fn essential_removals<'a>(&self, actions: &'a PkgActions) -> Vec<&'a str> {
+        actions
+            .remove
+            .iter()
+            .chain(&actions.purge)
+            .filter(|(name, _)| self.pkgs.get(name).map(|pkg| pkg.essential).unwrap_or(false))
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Make sure actions don't change any held package
+    pub fn check_held(&self, actions: &PkgActions) -> Result<()> {
+        for (newpkg, old) in &actions.install {
+            if let (Some(pkg), Some((oldver, _))) = (self.pkgs.get(&newpkg.name), old) {
+                // Reinstalling the same version is fine
+                if pkg.held && oldver != &newpkg.version {
+                    bail!(
+                        "Package {} is held at version {}, but sasm needs to change it to {}.",
+                        style(&pkg.name).bold(),
+                        oldver,
+                        newpkg.version
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Order removals so packages are removed before the packages they depend on
+    /// Dependencies are taken from the installed versions in the pool
+    pub fn sort_removals(&self, pool: &dyn PkgPool, actions: &mut PkgActions) -> Result<()> {
+        self.sort_by_rdepends(pool, &mut actions.remove)?;
+        self.sort_by_rdepends(pool, &mut actions.purge)
+    }
+
+    fn sort_by_rdepends(&self, pool: &dyn PkgPool, list: &mut Vec<(String, u64)>) -> Result<()> {
+        let mut ids = Vec::new();
+        let mut known = HashMap::new();
+        let mut unknown = Vec::new();
+        for (name, size) in list.drain(..) {
+            match self.installed_id(pool, &name) {
+                Some(id) => {
+                    ids.push(id);
+                    known.insert(id, (name, size));
+                }
+                None => unknown.push((name, size)),
+            }
+        }
+        sort_pkgs(pool, &mut ids)?;
+        // Packages missing from the pool have no known dependencies, so they can go first
+        list.append(&mut unknown);
+        // sort_pkgs puts dependencies first, removal needs the opposite
+        for id in ids.into_iter().rev() {
+            list.push(known.remove(&id).unwrap());
+        }
+        Ok(())
+    }
+
+    /// Get the ID of the installed version of a package, if it is in the pool
+    fn installed_id(&self, pool: &dyn PkgPool, pkgname: &str) -> Option<usize> {
+        let version = &self.pkgs.get(pkgname)?.version;
+        pool.get_pkgs_by_name(pkgname)?
+            .into_iter()
+            .find(|id| &pool.get_pkg_by_id(*id).unwrap().version == version)
+    }
+
     /// Generate a list of actions according to machine status and package blueprint
-    pub fn gen_actions<'a>(&self, blueprint: &[&'a PkgMeta]) -> PkgActions<'a> {
+    /// Packages in `reinstall` are installed again even if their version doesn't change
+    /// Packages in `purge` have their configuration files deleted as well if they are removed
+    pub fn gen_actions<'a>(
+        &self,
+        blueprint: &[&'a PkgMeta],
+        reinstall: &[String],
+        purge: &[String],
+    ) -> PkgActions<'a> {
         let mut res = PkgActions::default();
         // We will modify the list, so do a clone
         let mut old_pkgs = self.pkgs.clone();
@@ -42,14 +233,227 @@ impl MachineStatus {
                 } else if oldpkg.version > newpkg.version {
                     // Downgrade
                     res.install.push((newpkg, Some((oldpkg.version, oldpkg.install_size))))
+                } else if reinstall.contains(&newpkg.name) {
+                    // Reinstall
+                    res.install.push((newpkg, Some((oldpkg.version, oldpkg.install_size))))
                 }
             }
         }
 
         // Now deal with the leftovers
-        for oldpkg in old_pkgs {
-            res.remove.push((oldpkg.0, oldpkg.1.install_size));
+        // The blueprint is a solver result and so already closed under dependencies,
+        // anything installed but not in it is no longer needed. Held packages are kept
+        let mut leftovers: Vec<PkgStatus> =
+            old_pkgs.into_values().filter(|oldpkg| !oldpkg.held).collect();
+        leftovers.sort_by(|a, b| a.name.cmp(&b.name));
+        let essential: Vec<String> = leftovers
+            .iter()
+            .filter(|oldpkg| oldpkg.essential)
+            .map(|oldpkg| style(&oldpkg.name).bold().to_string())
+            .collect();
+        if !essential.is_empty() {
+            warn!("The following essential package(s) will be removed: {}.", essential.join(", "));
+        }
+        for oldpkg in leftovers {
+            if purge.contains(&oldpkg.name) {
+                res.purge.push((oldpkg.name, oldpkg.install_size));
+            } else {
+                res.remove.push((oldpkg.name, oldpkg.install_size));
+            }
         }
         res
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        types::{PkgVersion, VersionRequirement},
+        utils::test::TempDir,
+    };
+
+    fn pkgmeta(name: &str, depends: &[&str]) -> PkgMeta {
+        let depends = depends
+            .iter()
+            .map(|dep| (dep.to_string(), VersionRequirement::default(), None))
+            .collect();
+        PkgMeta { depends, ..PkgMeta::test(name, "1") }
+    }
+
+    fn pkgstatus(name: &str, install_size: u64) -> (String, PkgStatus) {
+        let status = PkgStatus {
+            name: name.to_string(),
+            version: PkgVersion::try_from("1").unwrap(),
+            install_size,
+            held: false,
+            essential: false,
+        };
+        (name.to_string(), status)
+    }
+
+    #[test]
+    fn gen_removes() {
+        let ms = MachineStatus {
+            pkgs: HashMap::from([
+                pkgstatus("a", 1),
+                pkgstatus("b", 2),
+                pkgstatus("c", 3),
+                pkgstatus("d", 4),
+            ]),
+        };
+        // A solver result always contains the dependencies of what it picks
+        let a = pkgmeta("a", &["c"]);
+        let c = pkgmeta("c", &[]);
+        let blueprint = vec![&a, &c];
+
+        let actions = ms.gen_actions(&blueprint, &[], &[]);
+        assert!(actions.install.is_empty());
+        assert_eq!(actions.remove, vec![("b".to_string(), 2), ("d".to_string(), 4)]);
+    }
+
+    #[test]
+    fn essential_leftovers() {
+        let mut ms = MachineStatus {
+            pkgs: HashMap::from([pkgstatus("a", 1), pkgstatus("b", 2), pkgstatus("c", 3)]),
+        };
+        ms.pkgs.get_mut("b").unwrap().essential = true;
+        let a = pkgmeta("a", &[]);
+
+        // Essential leftovers are still removed, but reported so they can be confirmed
+        let actions = ms.gen_actions(&[&a], &[], &[]);
+        assert_eq!(actions.remove, vec![("b".to_string(), 2), ("c".to_string(), 3)]);
+        assert_eq!(ms.essential_removals(&actions), vec!["b"]);
+    }
+
+    #[test]
+    fn rdepends() {
+        let ms = MachineStatus {
+            pkgs: HashMap::from([pkgstatus("a", 1), pkgstatus("b", 2), pkgstatus("c", 3)]),
+        };
+        let mut pool = crate::solver::pool::InMemoryPool::new();
+        pool.add(pkgmeta("a", &["c"]));
+        pool.add(pkgmeta("b", &["c"]));
+        pool.add(pkgmeta("c", &[]));
+        // Not installed, so removing c doesn't break it
+        pool.add(pkgmeta("d", &["c"]));
+        pool.finalize();
+
+        assert_eq!(ms.installed_rdepends(&pool, "c").unwrap(), vec!["a", "b"]);
+        assert!(ms.installed_rdepends(&pool, "a").unwrap().is_empty());
+    }
+
+    #[test]
+    fn removal_order() {
+        let ms = MachineStatus {
+            pkgs: HashMap::from([
+                pkgstatus("a", 1),
+                pkgstatus("b", 2),
+                pkgstatus("c", 3),
+                pkgstatus("d", 4),
+            ]),
+        };
+        let mut pool = crate::solver::pool::InMemoryPool::new();
+        // c -> b -> a, and d is not in the pool
+        pool.add(pkgmeta("a", &[]));
+        pool.add(pkgmeta("b", &["a"]));
+        pool.add(pkgmeta("c", &["b"]));
+        pool.finalize();
+
+        let mut actions = ms.gen_actions(&[], &[], &[]);
+        ms.sort_removals(&pool, &mut actions).unwrap();
+        // Dependents are removed before their dependencies
+        let names: Vec<&str> = actions.remove.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["d", "c", "b", "a"]);
+    }
+
+    #[test]
+    fn held() {
+        let mut ms = MachineStatus {
+            pkgs: HashMap::from([pkgstatus("a", 1), pkgstatus("b", 2), pkgstatus("c", 3)]),
+        };
+        ms.pkgs.get_mut("b").unwrap().held = true;
+        ms.pkgs.get_mut("c").unwrap().held = true;
+        assert_eq!(ms.held_pkgs().len(), 2);
+        ms.hold(&["a".to_string()]).unwrap();
+        assert_eq!(ms.held_pkgs().len(), 3);
+        assert!(ms.hold(&["x".to_string()]).is_err());
+        ms.pkgs.get_mut("a").unwrap().held = false;
+
+        // Held packages are never removed
+        let a = pkgmeta("a", &[]);
+        let actions = ms.gen_actions(&[&a], &[], &[]);
+        assert!(actions.remove.is_empty());
+        assert!(ms.check_held(&actions).is_ok());
+
+        // Upgrading a held package is an error
+        let mut c = pkgmeta("c", &[]);
+        c.version = PkgVersion::try_from("2").unwrap();
+        let actions = ms.gen_actions(&[&a, &c], &[], &[]);
+        assert!(ms.check_held(&actions).is_err());
+    }
+
+    #[test]
+    fn reinstall() {
+        let ms = MachineStatus { pkgs: HashMap::from([pkgstatus("a", 1), pkgstatus("b", 2)]) };
+        let a = pkgmeta("a", &[]);
+        let b = pkgmeta("b", &[]);
+
+        assert!(ms.gen_actions(&[&a, &b], &[], &[]).is_empty());
+        let actions = ms.gen_actions(&[&a, &b], &["a".to_string()], &[]);
+        assert_eq!(actions.install.len(), 1);
+        let (new, old) = &actions.install[0];
+        assert_eq!(new.name, "a");
+        assert_eq!(old.as_ref().map(|(ver, _)| ver), Some(&new.version));
+        assert_eq!(actions.reinstalls(), vec!["a"]);
+    }
+
+    #[test]
+    fn snapshot() {
+        let mut ms = MachineStatus {
+            pkgs: HashMap::from([pkgstatus("a", 1), pkgstatus("b", 2), pkgstatus("c", 3)]),
+        };
+        ms.pkgs.get_mut("a").unwrap().version = PkgVersion::try_from("1:2.0-3").unwrap();
+        ms.pkgs.get_mut("c").unwrap().held = true;
+        let dir = TempDir::new();
+        let path = dir.join("snapshot.json");
+        ms.save(&path).unwrap();
+        let loaded = MachineStatus::load(&path).unwrap();
+
+        assert_eq!(loaded.pkgs.len(), 3);
+        assert_eq!(loaded.pkgs["a"].version, ms.pkgs["a"].version);
+        assert!(loaded.pkgs["c"].held);
+        // Plans against the snapshot are the same as against the original
+        let mut a = pkgmeta("a", &[]);
+        a.version = PkgVersion::try_from("1:2.1-1").unwrap();
+        let b = pkgmeta("b", &[]);
+        let blueprint = vec![&a, &b];
+        let expected = ms.gen_actions(&blueprint, &[], &[]);
+        let actual = loaded.gen_actions(&blueprint, &[], &[]);
+        assert_eq!(actual.remove, expected.remove);
+        assert!(actual.remove.is_empty());
+        let names = |actions: &PkgActions| -> Vec<(String, Option<String>)> {
+            actions
+                .install
+                .iter()
+                .map(|(new, old)| (new.name.clone(), old.as_ref().map(|(v, _)| v.to_string())))
+                .collect()
+        };
+        assert_eq!(names(&actual), names(&expected));
+        assert_eq!(names(&actual), vec![("a".to_string(), Some("1:2.0-3".to_string()))]);
+    }
+
+    #[test]
+    fn purge() {
+        let ms = MachineStatus { pkgs: HashMap::from([pkgstatus("a", 1), pkgstatus("b", 2)]) };
+        let a = pkgmeta("a", &[]);
+
+        let actions = ms.gen_actions(&[&a], &[], &[]);
+        assert_eq!(actions.remove, vec![("b".to_string(), 2)]);
+        assert!(actions.purge.is_empty());
+
+        let actions = ms.gen_actions(&[&a], &[], &["b".to_string()]);
+        assert!(actions.remove.is_empty());
+        assert_eq!(actions.purge, vec![("b".to_string(), 2)]);
+    }
+}
@@ -8,7 +8,7 @@ mod executor;
 mod solver;
 mod types;
 mod utils;
-use config::{Blueprints, Config, Opts};
+use config::{Blueprints, Config, Opts, SubCmd};
 
 use anyhow::{bail, Context, Result};
 use clap::Parser;
@@ -28,6 +28,8 @@ lazy_static! {
 }
 // Debug flag
 static VERBOSE: AtomicBool = AtomicBool::new(false);
+// JSON output flag
+static JSON: AtomicBool = AtomicBool::new(false);
 // Global states
 static DPKG_RUNNING: AtomicBool = AtomicBool::new(false);
 static LOCKED: AtomicBool = AtomicBool::new(false);
@@ -35,6 +37,7 @@ static SUBPROCESS: AtomicI32 = AtomicI32::new(-1);
 // Global constants
 const DB_KEY_PATH: &str = "etc/sasm/keys";
 const DB_CACHE_PATH: &str = "var/cache/sasm/db";
+const BENCH_CACHE_PATH: &str = "var/cache/sasm/bench.json";
 const PKG_CACHE_PATH: &str = "var/cache/sasm/pkgs";
 const LOCK_PATH: &str = "var/lib/sasm/lock";
 const LOCAL_REPO_PATH: &str = "var/lib/sasm/local_repo";
@@ -44,6 +47,11 @@ fn verbose() -> bool {
     crate::VERBOSE.load(std::sync::atomic::Ordering::Relaxed)
 }
 
+/// Check if in JSON output mode
+fn json() -> bool {
+    crate::JSON.load(std::sync::atomic::Ordering::Relaxed)
+}
+
 /// Exit codes:
 /// 1 => program screwed up
 /// 2 => user cancelled operation
@@ -53,6 +61,10 @@ async fn main() {
     let mut opts: Opts = Opts::parse();
     // Configure debug globally
     VERBOSE.store(opts.verbose, Ordering::Relaxed);
+    // Configure JSON output globally. Must be done before WRITER is used
+    JSON.store(opts.json, Ordering::Relaxed);
+    // Configure colors globally, before anything is styled
+    utils::cli::init_colors(opts.no_color);
     // If yes mode is enabled, pager will be disabled
     if opts.yes {
         opts.no_pager = true;
@@ -92,6 +104,12 @@ async fn main() {
 }
 
 async fn try_main(opts: &Opts) -> Result<i32> {
+    // Completions don't need any configuration
+    if let SubCmd::Completions { shell } = &opts.subcmd {
+        config::gen_completions(*shell, &mut std::io::stdout());
+        return Ok(0);
+    }
+
     // Start reading configs
     let config_root = opts.root.join(&opts.config_root).canonicalize().context(format!(
         "Failed to find config_root in Sasm configuration file {} .",
@@ -110,7 +128,7 @@ async fn try_main(opts: &Opts) -> Result<i32> {
         .context(format!("Failed to open configuration file {} .", config_path.display()))?;
     let mut data = String::new();
     config_file.read_to_string(&mut data).context("Failed to read configuration file.")?;
-    let config: Config = toml::from_str(&data).context("Failed to parse configuration file.")?;
+    let config = Config::from_toml(&data).context("Failed to parse configuration file.")?;
 
     // Set-up blueprints
     let mut vendor_blueprint_paths = Vec::new();
@@ -132,8 +150,11 @@ async fn try_main(opts: &Opts) -> Result<i32> {
             }
         }
     }
-    let mut blueprint =
-        Blueprints::from_files(config_root.join("user.blueprint"), &vendor_blueprint_paths)?;
+    let mut blueprint = Blueprints::from_files(
+        config_root.join("user.blueprint"),
+        &vendor_blueprint_paths,
+        &config.arch,
+    )?;
 
     // Do stuff
     warn!("Sasm is currently under construction and active testing. Proceed with caution on production systems!");
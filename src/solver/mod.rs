@@ -4,45 +4,161 @@ pub mod pool;
 
 mod improve;
 mod incompatible;
-mod sort;
+pub mod sort;
 
-use crate::{config::Blueprints, debug, types::PkgMeta};
+use crate::{
+    config::{Blueprints, PkgRequest},
+    debug,
+    types::{PkgMeta, PkgSource, PkgStatus, PkgVersion},
+};
 use anyhow::{bail, format_err, Context, Result};
+use console::style;
 use pool::PkgPool;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+    time::Instant,
+};
 use varisat::{lit::Lit, ExtendFormula};
 
 pub struct Solver {
     pub pool: Box<dyn PkgPool>,
+    // IDs of packages that are only pretended to be installed
+    assumed: Vec<usize>,
 }
 
 impl From<Box<dyn PkgPool>> for Solver {
     fn from(pool: Box<dyn PkgPool>) -> Self {
-        Solver { pool }
+        Solver { pool, assumed: Vec::new() }
     }
 }
 
 impl From<pool::InMemoryPool> for Solver {
     fn from(pool: pool::InMemoryPool) -> Self {
-        Solver { pool: Box::new(pool) }
+        Solver { pool: Box::new(pool), assumed: Vec::new() }
     }
 }
 
 impl Solver {
-    pub fn install(&self, blueprints: &Blueprints) -> Result<Vec<&PkgMeta>> {
+    /// Pretend a package is installed, so dependencies on it are met without installing it
+    /// Without a version, the most preferred version in the pool is assumed
+    pub fn assume_installed(&mut self, name: &str, version: Option<PkgVersion>) -> Result<()> {
+        let version = match version {
+            Some(version) => version,
+            None => match self.pool.get_pkgs_by_name(name) {
+                Some(ids) => self.pool.get_pkg_by_id(ids[0]).unwrap().version.clone(),
+                None => bail!(
+                    "Package {} is not available, please specify a version to assume.",
+                    style(name).bold()
+                ),
+            },
+        };
+        let id = self.pool.add(PkgMeta {
+            name: name.to_owned(),
+            description: "Assumed to be installed".to_owned(),
+            version,
+            depends: Vec::new(),
+            optional: Vec::new(),
+            conflicts: Vec::new(),
+            provides: Vec::new(),
+            replaces: Vec::new(),
+            install_size: 0,
+            source: PkgSource::Local(PathBuf::new()),
+            repo: None,
+            maintainer: None,
+            homepage: None,
+        });
+        self.pool.finalize();
+        self.assumed.push(id);
+        Ok(())
+    }
+
+    /// Solve for packages requested by blueprints
+    /// `held` packages are pinned to their installed version, if it's available in the pool
+    /// Requested packages in `from_repo` (name -> repository) only use candidates from that repository
+    pub fn install(
+        &self,
+        blueprints: &Blueprints,
+        held: &[&PkgStatus],
+        from_repo: &HashMap<String, String>,
+    ) -> Result<Vec<&PkgMeta>> {
         let mut formula = self.pool.gen_formula(None);
         debug!("Adding requested packages to solver formula...");
         let mut ids = Vec::new();
-        for req in blueprints.get_pkg_requests() {
-            let id = self.pool.pick_best_pkg(&req.name, &req.version, req.local)?;
+        // Pin held packages first
+        for pkg in held {
+            if self.assumed_id(&pkg.name).is_some() {
+                continue;
+            }
+            let installed =
+                self.pool.get_pkgs_by_name(&pkg.name).unwrap_or_default().into_iter().find(|id| {
+                    self.pool.get_pkg_by_id(*id).map(|meta| &meta.version) == Some(&pkg.version)
+                });
+            if let Some(id) = installed {
+                formula.add_clause(&[Lit::from_dimacs(id as isize)]);
+                ids.push(id);
+            }
+        }
+        // Assumed packages are always there, which rules out other versions of them
+        for id in &self.assumed {
+            formula.add_clause(&[Lit::from_dimacs(*id as isize)]);
+            ids.push(*id);
+        }
+        let reqs = blueprints.get_pkg_requests()?;
+        // Requested packages whose optional dependencies should be pulled in
+        let mut recommend_from = Vec::new();
+        for name in from_repo.keys() {
+            if !reqs.iter().any(|req| &req.name == name) {
+                bail!("Package {} is not requested in blueprint.", style(name).bold());
+            }
+        }
+        for req in reqs {
+            if let Some(id) = self.assumed_id(&req.name) {
+                let version = &self.pool.get_pkg_by_id(id).unwrap().version;
+                if !req.version.contains(version) {
+                    bail!(
+                        "Package {} is assumed to be installed at version {}, but blueprint requests {}.",
+                        style(&req.name).bold(),
+                        version,
+                        req.version
+                    );
+                }
+                continue;
+            }
+            if let Some(pkg) = held.iter().find(|pkg| pkg.name == req.name) {
+                if !req.version.contains(&pkg.version) {
+                    bail!(
+                        "Package {} is held at version {}, but blueprint requests {}.",
+                        style(&pkg.name).bold(),
+                        pkg.version,
+                        req.version
+                    );
+                }
+                if ids.iter().any(|id| self.pool.get_pkg_by_id(*id).unwrap().name == pkg.name) {
+                    // Already pinned
+                    continue;
+                }
+            }
+            let id = self.pick_requested(&req, from_repo)?;
             formula.add_clause(&[Lit::from_dimacs(id as isize)]);
             ids.push(id);
+            if req.recommends {
+                recommend_from.push(id);
+            }
         }
+        debug!(
+            "Solver formula has {} clauses and {} variables, with {} requested package(s).",
+            formula.len(),
+            formula.var_count(),
+            ids.len()
+        );
         // Add rules to solver
         let mut solver = varisat::Solver::new();
         solver.add_formula(&formula);
 
         // Initial solve
         debug!("Computing initial solution...");
+        let start = Instant::now();
         let mut res = match solve(&mut solver) {
             Ok(r) => r,
             Err(_) => {
@@ -54,20 +170,133 @@ impl Solver {
             }
         };
 
+        debug!("Adding recommended packages...");
+        let requested = ids.len();
+        self.add_recommends(&mut solver, &recommend_from, &mut ids)?;
+        if ids.len() > requested {
+            res = solve(&mut solver)?;
+        }
+
+        let solve_time = start.elapsed();
+
         // Improve the result to remove redundant packages
         // and select best possible packages
         debug!("Refining dependency solution...");
+        let start = Instant::now();
         improve::upgrade(self.pool.as_ref(), &mut res, &mut solver)?;
         improve::reduce(self.pool.as_ref(), &mut res, &ids)?;
+        let improve_time = start.elapsed();
         // Sort result
+        let start = Instant::now();
         sort::sort_pkgs(self.pool.as_ref(), &mut res).context("Failed to sort packages")?;
+        debug!(
+            "Solving took {:?}, refining {:?}, sorting {:?}.",
+            solve_time,
+            improve_time,
+            start.elapsed()
+        );
 
-        // Generate result
-        let pkgs: Vec<&PkgMeta> =
-            res.into_iter().map(|pkgid| self.pool.get_pkg_by_id(pkgid).unwrap()).collect();
+        // Generate result, without packages that are not going to be installed
+        let pkgs: Vec<&PkgMeta> = res
+            .into_iter()
+            .filter(|pkgid| !self.assumed.contains(pkgid))
+            .map(|pkgid| self.pool.get_pkg_by_id(pkgid).unwrap())
+            .collect();
 
         Ok(pkgs)
     }
+
+    /// The package to install for a blueprint request
+    fn pick_requested(
+        &self,
+        req: &PkgRequest,
+        from_repo: &HashMap<String, String>,
+    ) -> Result<usize> {
+        match from_repo.get(&req.name) {
+            Some(repo) => self.pool.pick_best_pkg_from_repo(&req.name, &req.version, repo),
+            None => self.pool.pick_best_pkg(&req.name, &req.version, req.local),
+        }
+    }
+
+    fn assumed_id(&self, name: &str) -> Option<usize> {
+        self.assumed.iter().copied().find(|id| self.pool.get_pkg_by_id(*id).unwrap().name == name)
+    }
+
+    /// Request optional dependencies of `pkgs` as well, as long as the formula stays satisfiable
+    /// Accepted packages are added to `ids`
+    fn add_recommends(
+        &self,
+        solver: &mut varisat::Solver,
+        pkgs: &[usize],
+        ids: &mut Vec<usize>,
+    ) -> Result<()> {
+        for pkgid in pkgs {
+            let pkg = self.pool.get_pkg_by_id(*pkgid).unwrap();
+            for (name, ver_req, _) in &pkg.optional {
+                let requested =
+                    ids.iter().any(|id| self.pool.get_pkg_by_id(*id).unwrap().name == *name);
+                if requested {
+                    continue;
+                }
+                let id = match self.pool.pick_best_pkg(name, ver_req, false) {
+                    Ok(id) => id,
+                    Err(_) => {
+                        debug!("Recommended package {} of {} is not available.", name, pkg.name);
+                        continue;
+                    }
+                };
+                let lit = Lit::from_dimacs(id as isize);
+                solver.assume(&[lit]);
+                let satisfiable = solver.solve().context(format!(
+                    "Failed to check recommended package {} of {}.",
+                    style(name).bold(),
+                    style(&pkg.name).bold()
+                ))?;
+                if satisfiable {
+                    solver.add_clause(&[lit]);
+                    ids.push(id);
+                } else {
+                    debug!("Recommended package {} of {} cannot be installed.", name, pkg.name);
+                }
+            }
+        }
+        solver.assume(&[]);
+        Ok(())
+    }
+
+    /// Explain why a single requested package cannot be installed
+    /// The package is picked the same way `install` does. Returns Ok(()) if it can be installed
+    /// on its own
+    pub fn explain(&self, req: &PkgRequest, from_repo: &HashMap<String, String>) -> Result<()> {
+        let id = self.pick_requested(req, from_repo)?;
+
+        let mut formula = self.pool.gen_formula(None);
+        formula.add_clause(&[Lit::from_dimacs(id as isize)]);
+        let mut solver = varisat::Solver::new();
+        solver.add_formula(&formula);
+        let solvable = solve(&mut solver).is_ok();
+
+        // Packages with unmet dependencies are left out of the formula,
+        // so walk the dependency tree even if the formula is satisfiable
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::from([id]);
+        while let Some(pkgid) = queue.pop_front() {
+            if !visited.insert(pkgid) {
+                continue;
+            }
+            for choices in self.pool.get_deps(pkgid)? {
+                // Follow the most preferred candidate
+                if let Some(dep) = choices.first() {
+                    queue.push_back(*dep);
+                }
+            }
+        }
+
+        if !solvable {
+            bail!(incompatible::find_incompatible_friendly(self.pool.as_ref(), &[id]));
+        }
+        Ok(())
+    }
 }
 
 /// Helper function to get PkgID list
@@ -86,3 +315,278 @@ pub fn solve(solver: &mut varisat::Solver) -> Result<Vec<usize>> {
     }
     Ok(res)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        executor::MachineStatus,
+        types::{PkgVersion, VersionRequirement},
+        utils::test::TempDir,
+    };
+    use std::fs;
+
+    #[test]
+    fn explain() {
+        let mut pool = pool::InMemoryPool::new();
+        let mut a = PkgMeta::test_remote("a", "1");
+        a.depends.push(("b".to_string(), VersionRequirement::default(), None));
+        pool.add(a);
+        let mut b = PkgMeta::test_remote("b", "1");
+        b.depends.push(("c".to_string(), VersionRequirement::try_from(">=2").unwrap(), None));
+        pool.add(b);
+        pool.add(PkgMeta::test_remote("c", "1"));
+        let mut d = PkgMeta::test_remote("d", "1");
+        d.depends.push(("e".to_string(), VersionRequirement::default(), None));
+        pool.add(d);
+        // Only the older version of f can be installed
+        let mut f = PkgMeta::test_remote("f", "2");
+        f.depends.push(("e".to_string(), VersionRequirement::default(), None));
+        pool.add(f);
+        pool.add(PkgMeta::test_remote("f", "1"));
+        pool.finalize();
+        let solver = Solver::from(pool);
+        let explain = |name: &str, version: &str| {
+            let req = PkgRequest {
+                name: name.to_string(),
+                version: VersionRequirement::try_from(version).unwrap(),
+                ..Default::default()
+            };
+            solver.explain(&req, &HashMap::new())
+        };
+
+        assert!(explain("c", "").is_ok());
+        // The unmet edge is b -> c, not a -> b
+        let e = format!("{:#}", explain("a", "").unwrap_err());
+        assert!(e.contains("no suitable version"));
+        assert!(e.contains("only the following version(s) are available: 1"));
+        let e = format!("{:#}", explain("d", "").unwrap_err());
+        assert!(e.contains("no such package"));
+        assert!(explain("x", "").is_err());
+        // The version requirement of the request picks the package to explain
+        assert!(explain("f", "").is_err());
+        assert!(explain("f", "=1").is_ok());
+    }
+
+    #[test]
+    fn assume_installed() {
+        let dir = TempDir::new();
+        let path = dir.join("user.blueprint");
+        fs::write(&path, "a\nc\n").unwrap();
+        let blueprints = Blueprints::from_files(path, &[], "x86_64").unwrap();
+
+        // The only b available conflicts with c
+        let mut pool = pool::InMemoryPool::new();
+        let mut a = PkgMeta::test_remote("a", "1");
+        a.depends.push(("b".to_string(), VersionRequirement::try_from(">=1").unwrap(), None));
+        pool.add(a);
+        let mut b = PkgMeta::test_remote("b", "2");
+        b.conflicts.push(("c".to_string(), VersionRequirement::default(), None));
+        pool.add(b);
+        pool.add(PkgMeta::test_remote("c", "1"));
+        pool.finalize();
+        let mut solver = Solver::from(pool);
+        assert!(solver.install(&blueprints, &[], &HashMap::new()).is_err());
+
+        solver.assume_installed("b", Some(PkgVersion::try_from("1").unwrap())).unwrap();
+        let res = solver.install(&blueprints, &[], &HashMap::new()).unwrap();
+        let mut names: Vec<&str> = res.iter().map(|pkg| pkg.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["a", "c"]);
+
+        // Unknown packages need a version
+        assert!(solver.assume_installed("x", None).is_err());
+    }
+
+    #[test]
+    fn formula_size() {
+        let mut pool = pool::InMemoryPool::new();
+        let mut a = PkgMeta::test_remote("a", "1");
+        a.depends.push(("b".to_string(), VersionRequirement::default(), None));
+        pool.add(a);
+        pool.add(PkgMeta::test_remote("b", "1"));
+        pool.add(PkgMeta::test_remote("b", "2"));
+        pool.finalize();
+
+        // a needs one version of b, and only one version of b can be installed
+        assert_eq!(pool.formula_size(None), (2, 3));
+    }
+
+    #[test]
+    fn ignore() {
+        let dir = TempDir::new();
+        let path = dir.join("user.blueprint");
+        fs::write(&path, "a\nb\n").unwrap();
+        let blueprints = Blueprints::from_files(path, &[], "x86_64").unwrap();
+
+        let mut pool = pool::InMemoryPool::new();
+        for (name, version) in [("a", "1"), ("a", "2"), ("b", "1"), ("b", "2")] {
+            pool.add(PkgMeta::test_remote(name, version));
+        }
+        pool.finalize();
+        let solver = Solver::from(pool);
+
+        let mut ms = MachineStatus { pkgs: HashMap::new() };
+        for name in ["a", "b"] {
+            let status = PkgStatus {
+                name: name.to_string(),
+                version: PkgVersion::try_from("1").unwrap(),
+                install_size: 0,
+                held: false,
+                essential: false,
+            };
+            ms.pkgs.insert(name.to_string(), status);
+        }
+        ms.hold(&["a".to_string()]).unwrap();
+
+        let res = solver.install(&blueprints, &ms.held_pkgs(), &HashMap::new()).unwrap();
+        let version =
+            |name: &str| res.iter().find(|pkg| pkg.name == name).unwrap().version.to_string();
+        // a stays, b is still upgraded
+        assert_eq!(version("a"), "1");
+        assert_eq!(version("b"), "2");
+    }
+
+    #[test]
+    fn exclude() {
+        let dir = TempDir::new();
+        let path = dir.join("user.blueprint");
+        fs::write(&path, "nvidia-utils\nnvidia-dkms\nmesa\n").unwrap();
+        let blueprints = Blueprints::from_files(path, &[], "x86_64").unwrap();
+
+        let names = ["nvidia-utils", "nvidia-dkms", "mesa"];
+        let mut pool = pool::InMemoryPool::new();
+        for name in names {
+            pool.add(PkgMeta::test_remote(name, "1"));
+            pool.add(PkgMeta::test_remote(name, "2"));
+        }
+        pool.finalize();
+        let solver = Solver::from(pool);
+
+        let mut ms = MachineStatus { pkgs: HashMap::new() };
+        for name in names {
+            let status = PkgStatus {
+                name: name.to_string(),
+                version: PkgVersion::try_from("1").unwrap(),
+                install_size: 0,
+                held: false,
+                essential: false,
+            };
+            ms.pkgs.insert(name.to_string(), status);
+        }
+        let pattern = crate::alpm::conf::glob_to_regex("nvidia-*").unwrap();
+        assert_eq!(ms.hold_matching(&[pattern]), 2);
+
+        let res = solver.install(&blueprints, &ms.held_pkgs(), &HashMap::new()).unwrap();
+        let version =
+            |name: &str| res.iter().find(|pkg| pkg.name == name).unwrap().version.to_string();
+        assert_eq!(version("nvidia-utils"), "1");
+        assert_eq!(version("nvidia-dkms"), "1");
+        assert_eq!(version("mesa"), "2");
+    }
+
+    #[test]
+    fn upgrade_only() {
+        let dir = TempDir::new();
+        let path = dir.join("user.blueprint");
+        fs::write(&path, "foo\nbar\n").unwrap();
+        let blueprints = Blueprints::from_files(path, &[], "x86_64").unwrap();
+
+        let mut pool = pool::InMemoryPool::new();
+        for name in ["foo", "bar"] {
+            pool.add(PkgMeta::test_remote(name, "1"));
+            pool.add(PkgMeta::test_remote(name, "2"));
+        }
+        pool.finalize();
+        let solver = Solver::from(pool);
+
+        let mut ms = MachineStatus { pkgs: HashMap::new() };
+        for name in ["foo", "bar"] {
+            let status = PkgStatus {
+                name: name.to_string(),
+                version: PkgVersion::try_from("1").unwrap(),
+                install_size: 0,
+                held: false,
+                essential: false,
+            };
+            ms.pkgs.insert(name.to_string(), status);
+        }
+        ms.hold_except(&["foo".to_string()]).unwrap();
+        // Only installed packages can be upgraded
+        let mut empty = MachineStatus { pkgs: HashMap::new() };
+        assert!(empty.hold_except(&["foo".to_string()]).is_err());
+
+        let res = solver.install(&blueprints, &ms.held_pkgs(), &HashMap::new()).unwrap();
+        let version =
+            |name: &str| res.iter().find(|pkg| pkg.name == name).unwrap().version.to_string();
+        assert_eq!(version("foo"), "2");
+        assert_eq!(version("bar"), "1");
+    }
+
+    #[test]
+    fn forget() {
+        let dir = TempDir::new();
+        let path = dir.join("user.blueprint");
+        fs::write(&path, "a\nb\n").unwrap();
+        let mut blueprints = Blueprints::from_files(path, &[], "x86_64").unwrap();
+
+        let mut pool = pool::InMemoryPool::new();
+        for (name, version) in [("a", "1"), ("a", "2"), ("b", "1")] {
+            pool.add(PkgMeta::test_remote(name, version));
+        }
+        pool.finalize();
+        let solver = Solver::from(pool);
+
+        let mut ms = MachineStatus { pkgs: HashMap::new() };
+        for name in ["a", "b"] {
+            let status = PkgStatus {
+                name: name.to_string(),
+                version: PkgVersion::try_from("1").unwrap(),
+                install_size: 0,
+                held: false,
+                essential: false,
+            };
+            ms.pkgs.insert(name.to_string(), status);
+        }
+
+        // What remove --forget does: drop the request, then hold the installed package
+        blueprints.remove("a", false).unwrap();
+        ms.hold(&["a".to_string()]).unwrap();
+        let res = solver.install(&blueprints, &ms.held_pkgs(), &HashMap::new()).unwrap();
+        let actions = ms.gen_actions(res.as_slice(), &[], &[]);
+        // a is neither removed nor upgraded
+        assert!(actions.is_empty());
+    }
+
+    #[test]
+    fn recommends() {
+        let mut pool = pool::InMemoryPool::new();
+        let mut a = PkgMeta::test_remote("a", "1");
+        a.optional.push(("b".to_string(), VersionRequirement::default(), None));
+        // Recommends that cannot be installed are skipped
+        a.optional.push(("c".to_string(), VersionRequirement::default(), None));
+        a.optional.push(("missing".to_string(), VersionRequirement::default(), None));
+        pool.add(a);
+        pool.add(PkgMeta::test_remote("b", "1"));
+        let mut c = PkgMeta::test_remote("c", "1");
+        c.conflicts.push(("a".to_string(), VersionRequirement::default(), None));
+        pool.add(c);
+        pool.finalize();
+        let solver = Solver::from(pool);
+
+        let dir = TempDir::new();
+        let path = dir.join("user.blueprint");
+        let install = |blueprint: &str| -> Vec<String> {
+            fs::write(&path, blueprint).unwrap();
+            let blueprints = Blueprints::from_files(path.clone(), &[], "x86_64").unwrap();
+            let res = solver.install(&blueprints, &[], &HashMap::new()).unwrap();
+            let mut names: Vec<String> = res.into_iter().map(|pkg| pkg.name.clone()).collect();
+            names.sort();
+            names
+        };
+
+        // Recommends are opt-in
+        assert_eq!(install("a\n"), vec!["a"]);
+        assert_eq!(install("a (recommends)\n"), vec!["a", "b"]);
+    }
+}
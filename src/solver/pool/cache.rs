@@ -0,0 +1,111 @@
+use super::{BasicPkgPool, InMemoryPool};
+use crate::{types::PkgMeta, utils::atomic::atomic_write};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Bump this whenever the layout of PkgMeta or PoolCache changes
+const CACHE_FORMAT_VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize)]
+struct PoolCache {
+    format_version: u32,
+    // Identifies the package databases this pool is built from
+    key: Vec<Vec<u8>>,
+    // Packages in the order of PkgID
+    pkgs: Vec<PkgMeta>,
+}
+
+impl InMemoryPool {
+    /// Load a finalized pool from cache file, if it's built from the same databases
+    pub fn load_cache(path: &Path, key: &[Vec<u8>]) -> Result<Option<Self>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let bytes = fs::read(path)
+            .with_context(|| format!("Failed to read package pool cache {}.", path.display()))?;
+        let cache: PoolCache = match bincode::deserialize(&bytes) {
+            Ok(cache) => cache,
+            // Probably created by an incompatible version of sasm
+            Err(_) => return Ok(None),
+        };
+        if cache.format_version != CACHE_FORMAT_VERSION || cache.key != key {
+            return Ok(None);
+        }
+
+        let mut pool = InMemoryPool::new();
+        for pkg in cache.pkgs {
+            pool.add(pkg);
+        }
+        pool.finalize();
+        Ok(Some(pool))
+    }
+
+    /// Write this pool to cache file, keyed by the databases it's built from
+    pub fn save_cache(&self, path: &Path, key: &[Vec<u8>]) -> Result<()> {
+        let cache = PoolCache {
+            format_version: CACHE_FORMAT_VERSION,
+            key: key.to_vec(),
+            pkgs: self.pkgid_iter().map(|(_, meta)| meta.clone()).collect(),
+        };
+        let bytes = bincode::serialize(&cache)?;
+        // An interrupted write shouldn't leave a broken cache
+        atomic_write(path, &bytes)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        types::{Checksum, PkgSource, VersionRequirement},
+        utils::test::TempDir,
+    };
+
+    #[test]
+    fn roundtrip() {
+        let mut pool = InMemoryPool::new();
+        for (name, version) in [("a", "1.0-1"), ("b", "1:2.3"), ("a", "1.1-1")] {
+            pool.add(PkgMeta {
+                description: format!("Package {name}"),
+                depends: vec![(
+                    "c".to_string(),
+                    VersionRequirement::try_from(">=1, <2").unwrap(),
+                    None,
+                )],
+                optional: vec![("d".to_string(), VersionRequirement::default(), Some("d".into()))],
+                install_size: 42,
+                source: PkgSource::Http((
+                    format!("https://example.org/{name}"),
+                    1,
+                    Checksum::Sha256(vec![1, 2, 3]),
+                )),
+                repo: Some("core".to_string()),
+                ..PkgMeta::test(name, version)
+            });
+        }
+        pool.finalize();
+
+        let dir = TempDir::new();
+        let path = dir.join("pool.cache");
+        let key = vec![vec![1, 2, 3]];
+        pool.save_cache(&path, &key).unwrap();
+
+        // A different key invalidates the cache
+        assert!(InMemoryPool::load_cache(&path, &[vec![4]]).unwrap().is_none());
+
+        let loaded = InMemoryPool::load_cache(&path, &key).unwrap().unwrap();
+        for id in 1..=3 {
+            let (a, b) = (pool.get_pkg_by_id(id).unwrap(), loaded.get_pkg_by_id(id).unwrap());
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.version, b.version);
+            assert_eq!(a.depends, b.depends);
+            assert_eq!(a.optional, b.optional);
+            assert_eq!(a.install_size, b.install_size);
+            assert_eq!(a.repo, b.repo);
+        }
+        assert_eq!(pool.get_pkgs_by_name("a"), loaded.get_pkgs_by_name("a"));
+    }
+}
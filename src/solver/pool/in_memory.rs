@@ -1,4 +1,4 @@
-use super::{BasicPkgPool, PkgPool};
+use super::{provide_satisfies, BasicPkgPool, PkgPool};
 use crate::types::{PkgMeta, PkgVersion, VersionRequirement};
 
 use rayon::prelude::*;
@@ -7,10 +7,12 @@ use std::collections::HashMap;
 
 pub struct InMemoryPool {
     pkgs: Vec<PkgMeta>,
-    // The id of packages for each name, sorted by version
+    // The id of packages for each name, sorted by repo priority and version
     name_to_ids: HashMap<String, Vec<(usize, PkgVersion)>>,
     // The ids of packages that provide a certain package, for accelerated provide lookup
     provide_to_ids: HashMap<String, Vec<(usize, VersionRequirement)>>,
+    // Priority of repositories, 0 if not specified
+    repo_priorities: HashMap<String, i32>,
 }
 
 impl InMemoryPool {
@@ -19,12 +21,39 @@ impl InMemoryPool {
             pkgs: Vec::new(),
             name_to_ids: HashMap::new(),
             provide_to_ids: HashMap::new(),
+            repo_priorities: HashMap::new(),
         }
     }
+
+    /// Set repository priorities, takes effect after finalize
+    pub fn set_repo_priorities(&mut self, priorities: HashMap<String, i32>) {
+        self.repo_priorities = priorities;
+    }
+
+    fn get_priority(&self, id: usize) -> i32 {
+        self.pkgs[id - 1]
+            .repo
+            .as_ref()
+            .and_then(|repo| self.repo_priorities.get(repo))
+            .copied()
+            .unwrap_or(0)
+    }
 }
 
 impl BasicPkgPool for InMemoryPool {
     fn add(&mut self, meta: PkgMeta) -> usize {
+        // A package listed more than once in the same repository keeps its first ID, otherwise
+        // the copies would exclude each other as different versions
+        if let Some(ids) = self.name_to_ids.get(&meta.name) {
+            let duplicate = ids.iter().find(|(id, version)| {
+                let pkg = &self.pkgs[id - 1];
+                version == &meta.version && pkg.repo == meta.repo && pkg.source == meta.source
+            });
+            if let Some((id, _)) = duplicate {
+                return *id;
+            }
+        }
+
         // Find out which names are provided
         let provide_names: Vec<(String, VersionRequirement)> =
             meta.provides.iter().map(|p| (p.0.clone(), p.1.clone())).collect();
@@ -56,10 +85,14 @@ impl BasicPkgPool for InMemoryPool {
     }
 
     fn finalize(&mut self) {
+        let priorities: Vec<i32> = (1..=self.pkgs.len()).map(|id| self.get_priority(id)).collect();
         // Sort versions
         self.name_to_ids.iter_mut().for_each(|(_, pkgs)| {
-            // Sort in descending order
-            pkgs.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+            // Sort in descending order, packages from repos with higher priority go first
+            // Use a stable sort so packages with the same version keep the order they're added
+            pkgs.sort_by(|a, b| {
+                priorities[b.0 - 1].cmp(&priorities[a.0 - 1]).then_with(|| b.1.cmp(&a.1))
+            });
         });
     }
 
@@ -87,7 +120,7 @@ impl BasicPkgPool for InMemoryPool {
         let res = if let Some(provides) = self.provide_to_ids.get(name) {
             let res = provides
                 .into_iter()
-                .filter(|pkg| ver_req.overlap(&pkg.1))
+                .filter(|pkg| provide_satisfies(&self.pkgs[pkg.0 - 1], &pkg.1, ver_req))
                 .map(|pkg| pkg.0)
                 .collect();
             Some(res)
@@ -1,3 +1,4 @@
+mod cache;
 mod in_memory;
 pub use in_memory::InMemoryPool;
 
@@ -11,6 +12,20 @@ use anyhow::{anyhow, bail, format_err, Context, Result};
 use console::style;
 use varisat::{lit::Lit, CnfFormula, ExtendFormula};
 
+/// Check if `provider` satisfies a dependency requirement `dep_req` through a provide
+/// Versioned provides are compared by the provided version, unversioned ones by the provider's
+pub fn provide_satisfies(
+    provider: &PkgMeta,
+    provide_req: &VersionRequirement,
+    dep_req: &VersionRequirement,
+) -> bool {
+    if provide_req.is_arbitary() {
+        dep_req.contains(&provider.version)
+    } else {
+        dep_req.overlap(provide_req)
+    }
+}
+
 /// The basic PkgPool interface
 pub trait BasicPkgPool {
     // Add a package to the pool
@@ -19,7 +34,8 @@ pub trait BasicPkgPool {
     fn finalize(&mut self);
     // Get PkgMeta from Pkg ID
     fn get_pkg_by_id(&self, id: usize) -> Option<&PkgMeta>;
-    // Get a list of available package IDs based on the given name
+    // Get a list of available package IDs based on the given name, most preferred first
+    // Packages from repos with higher priority are preferred, then newer versions
     fn get_pkgs_by_name(&self, name: &str) -> Option<Vec<usize>>;
     // Get a list of packages that provide a certain package
     fn get_pkgs_by_provide(&self, name: &str, ver_req: &VersionRequirement) -> Option<Vec<usize>>;
@@ -51,7 +67,13 @@ pub trait PkgPool: BasicPkgPool {
             for provide_pkgid in &provides {
                 deps_id.push(*provide_pkgid);
             }
-            if deps_id.is_empty() {
+            if available.is_empty() && provides.is_empty() {
+                bail!(
+                    "Cannot fulfill dependency {} for {}: no such package.",
+                    style(&dep.0).bold(),
+                    style(&pkg.name).bold()
+                );
+            } else if deps_id.is_empty() {
                 let error = anyhow!(
                     "{} requires {} ({}), but only the following version(s) are available: {}.",
                     pkg.name,
@@ -76,6 +98,29 @@ pub trait PkgPool: BasicPkgPool {
         Ok(res)
     }
 
+    /// Get IDs of packages that depend on the given package, either directly or via provides
+    fn rdepends(&self, pkgid: usize) -> Result<Vec<usize>> {
+        let pkg = self
+            .get_pkg_by_id(pkgid)
+            .ok_or_else(|| format_err!("Package with ID {pkgid} not found."))?;
+        let mut res = Vec::new();
+        for (id, meta) in self.pkgid_iter() {
+            let depended = meta.depends.iter().any(|dep| {
+                (dep.0 == pkg.name && dep.1.contains(&pkg.version))
+                    || pkg
+                        .provides
+                        .iter()
+                        .any(|p| p.0 == dep.0 && provide_satisfies(pkg, &p.1, &dep.1))
+            });
+            if depended {
+                res.push(id);
+            }
+        }
+        Ok(res)
+    }
+
+    /// Pick the most preferred package that satisfies the version requirement
+    /// Relies on get_pkgs_by_name returning IDs in order of preference
     fn pick_best_pkg(
         &self,
         pkgname: &str,
@@ -111,11 +156,35 @@ pub trait PkgPool: BasicPkgPool {
         }
     }
 
+    /// Like pick_best_pkg, but only consider packages from the given repository
+    fn pick_best_pkg_from_repo(
+        &self,
+        pkgname: &str,
+        ver_req: &VersionRequirement,
+        repo: &str,
+    ) -> Result<usize> {
+        let pkgs = match self.get_pkgs_by_name(pkgname) {
+            Some(pkgs) => pkgs,
+            None => bail!("Package {pkgname} not found."),
+        };
+        let mut in_repo = pkgs
+            .into_iter()
+            .filter(|id| self.get_pkg_by_id(*id).unwrap().repo.as_deref() == Some(repo))
+            .peekable();
+        if in_repo.peek().is_none() {
+            bail!("Package {pkgname} is not available in repository {repo}.");
+        }
+        match in_repo.find(|id| ver_req.contains(&self.get_pkg_by_id(*id).unwrap().version)) {
+            Some(id) => Ok(id),
+            None => bail!("Cannot find a suitable version for {pkgname} in repository {repo}."),
+        }
+    }
+
     fn find_provide(&self, name: &str, ver_req: &Option<VersionRequirement>) -> Option<String> {
         let ver_req = ver_req.clone().unwrap_or_default();
         for (_, pkg) in self.pkgid_iter() {
             for provide in &pkg.provides {
-                if provide.0 == name && provide.1.combine(&ver_req).is_ok() {
+                if provide.0 == name && provide_satisfies(pkg, &provide.1, &ver_req) {
                     return Some(pkg.name.to_owned());
                 }
             }
@@ -153,7 +222,13 @@ pub trait PkgPool: BasicPkgPool {
                 None => Vec::new(),
             };
             // Provides can be considered as dependencies as well
-            let provides: Vec<usize> = self.get_pkgs_by_provide(&dep.0, &dep.1).unwrap_or_default();
+            let provides: Vec<usize> = match self.get_pkgs_by_provide(&dep.0, &dep.1) {
+                Some(pkgs) => match subset {
+                    Some(ids) => pkgs.into_iter().filter(|id| ids.contains(id)).collect(),
+                    None => pkgs,
+                },
+                None => Vec::new(),
+            };
 
             if available.is_empty() && provides.is_empty() {
                 bail!("Cannot find a package which fulfills dependency {}.", style(&dep.0).bold());
@@ -210,6 +285,12 @@ pub trait PkgPool: BasicPkgPool {
         Ok(res)
     }
 
+    /// Get (clauses, variables) of the formula `gen_formula` generates, without solving it
+    fn formula_size(&self, subset: Option<&[usize]>) -> (usize, usize) {
+        let formula = self.gen_formula(subset);
+        (formula.len(), formula.var_count())
+    }
+
     fn gen_formula(&self, subset: Option<&[usize]>) -> CnfFormula {
         let mut formula = CnfFormula::new();
 
@@ -257,7 +338,32 @@ pub trait PkgPool: BasicPkgPool {
 mod test {
     use super::*;
     use crate::types::{PkgMeta, PkgVersion, VersionRequirement};
-    use std::path::PathBuf;
+    use std::{collections::HashMap, path::PathBuf};
+
+    #[test]
+    fn duplicate_pkgs() {
+        let pkg = PkgMeta {
+            provides: vec![("b".to_string(), VersionRequirement::default(), None)],
+            source: PkgSource::Local(PathBuf::from("a-1-1-any.pkg.tar.zst")),
+            repo: Some("core".to_string()),
+            ..PkgMeta::test("a", "1")
+        };
+        let mut pool = InMemoryPool::new();
+        let id = pool.add(pkg.clone());
+        assert_eq!(pool.add(pkg.clone()), id);
+        // The same version from another repository is a separate candidate
+        let mut other_repo = pkg.clone();
+        other_repo.repo = Some("extra".to_string());
+        let other_id = pool.add(other_repo);
+        pool.finalize();
+
+        assert_eq!(pool.get_pkgs_by_name("a").unwrap(), vec![id, other_id]);
+        assert_eq!(
+            pool.get_pkgs_by_provide("b", &VersionRequirement::default()).unwrap(),
+            vec![id, other_id]
+        );
+        assert_eq!(pool.pkgid_iter().count(), 2);
+    }
 
     #[test]
     fn trivial_pool() {
@@ -267,69 +373,61 @@ mod test {
             description: "".to_string(),
             version: PkgVersion::try_from("1").unwrap(),
 
-            depends: vec![(
-                "c".to_string(),
-                VersionRequirement { lower_bond: None, upper_bond: None },
-                None,
-            )],
+            depends: vec![("c".to_string(), VersionRequirement::default(), None)],
             optional: Vec::new(),
-            conflicts: vec![(
-                "d".to_string(),
-                VersionRequirement { lower_bond: None, upper_bond: None },
-                None,
-            )],
+            conflicts: vec![("d".to_string(), VersionRequirement::default(), None)],
             provides: Vec::new(),
             replaces: Vec::new(),
             install_size: 0,
             source: PkgSource::Local(PathBuf::new()),
+            repo: None,
+            maintainer: None,
+            homepage: None,
         });
         let b_id = pool.add(PkgMeta {
             name: "b".to_string(),
             description: "".to_string(),
             version: PkgVersion::try_from("1").unwrap(),
-            depends: vec![(
-                "a".to_string(),
-                VersionRequirement { lower_bond: None, upper_bond: None },
-                None,
-            )],
+            depends: vec![("a".to_string(), VersionRequirement::default(), None)],
             optional: Vec::new(),
             conflicts: Vec::new(),
             replaces: Vec::new(),
             provides: Vec::new(),
             install_size: 0,
             source: PkgSource::Local(PathBuf::new()),
+            repo: None,
+            maintainer: None,
+            homepage: None,
         });
         let c_id = pool.add(PkgMeta {
             name: "c".to_string(),
             description: "".to_string(),
             version: PkgVersion::try_from("1").unwrap(),
-            depends: vec![(
-                "b".to_string(),
-                VersionRequirement { lower_bond: None, upper_bond: None },
-                None,
-            )],
+            depends: vec![("b".to_string(), VersionRequirement::default(), None)],
             optional: Vec::new(),
             conflicts: Vec::new(),
             replaces: Vec::new(),
             provides: Vec::new(),
             install_size: 0,
             source: PkgSource::Local(PathBuf::new()),
+            repo: None,
+            maintainer: None,
+            homepage: None,
         });
         let d_id = pool.add(PkgMeta {
             name: "d".to_string(),
             description: "".to_string(),
             version: PkgVersion::try_from("1").unwrap(),
-            depends: vec![(
-                "b".to_string(),
-                VersionRequirement { lower_bond: None, upper_bond: None },
-                None,
-            )],
+            depends: vec![("b".to_string(), VersionRequirement::default(), None)],
             optional: Vec::new(),
             conflicts: Vec::new(),
             replaces: Vec::new(),
             provides: Vec::new(),
             install_size: 0,
             source: PkgSource::Local(PathBuf::new()),
+            repo: None,
+            maintainer: None,
+            homepage: None,
         });
         pool.finalize();
 
@@ -349,4 +447,153 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn virtual_pkg() {
+        let mut pool = InMemoryPool::new();
+        let mut a = PkgMeta::test("a", "1");
+        a.depends.push(("x".to_string(), VersionRequirement::default(), None));
+        let a_id = pool.add(a);
+        let mut b = PkgMeta::test("b", "1");
+        b.provides.push(("x".to_string(), VersionRequirement::default(), None));
+        let b_id = pool.add(b);
+        pool.finalize();
+
+        let mut solver = varisat::Solver::new();
+        solver.add_formula(&pool.gen_formula(None));
+        solver.assume(&[Lit::from_dimacs(a_id as isize)]);
+        assert!(solver.solve().unwrap());
+        assert!(solver.model().unwrap().contains(&Lit::from_dimacs(b_id as isize)));
+    }
+
+    #[test]
+    fn versioned_provide() {
+        let mut pool = InMemoryPool::new();
+        // Provides x=1.2 at its own version 5
+        let mut b = PkgMeta::test("b", "5");
+        b.provides.push(("x".to_string(), VersionRequirement::try_from("=1.2").unwrap(), None));
+        let b_id = pool.add(b);
+        // Provides y without a version, so its own version 2 counts
+        let mut c = PkgMeta::test("c", "2");
+        c.provides.push(("y".to_string(), VersionRequirement::default(), None));
+        let c_id = pool.add(c);
+        pool.finalize();
+
+        let by_provide = |name: &str, req: &str| {
+            pool.get_pkgs_by_provide(name, &VersionRequirement::try_from(req).unwrap())
+                .unwrap_or_default()
+        };
+        assert_eq!(by_provide("x", ">=1.1"), vec![b_id]);
+        // The provider's own version doesn't matter for versioned provides
+        assert!(by_provide("x", ">=2").is_empty());
+        assert_eq!(by_provide("y", ">=2"), vec![c_id]);
+        assert!(by_provide("y", ">=3").is_empty());
+
+        let req = |s: &str| Some(VersionRequirement::try_from(s).unwrap());
+        assert_eq!(pool.find_provide("x", &req(">=1.1")), Some("b".to_string()));
+        assert_eq!(pool.find_provide("x", &req(">=2")), None);
+        assert_eq!(pool.find_provide("y", &None), Some("c".to_string()));
+        assert_eq!(pool.find_provide("y", &req("<2")), None);
+    }
+
+    #[test]
+    fn versioned_conflict() {
+        let mut pool = InMemoryPool::new();
+        let mut a = PkgMeta::test("a", "1");
+        a.conflicts.push(("d".to_string(), VersionRequirement::try_from("<2.0").unwrap(), None));
+        let a_id = pool.add(a);
+        let d1_id = pool.add(PkgMeta::test("d", "1.0"));
+        let d2_id = pool.add(PkgMeta::test("d", "2.0"));
+        pool.finalize();
+
+        let mut solver = varisat::Solver::new();
+        solver.add_formula(&pool.gen_formula(None));
+        solver.assume(&[Lit::from_dimacs(a_id as isize), Lit::from_dimacs(d1_id as isize)]);
+        assert!(!solver.solve().unwrap());
+        solver.assume(&[Lit::from_dimacs(a_id as isize), Lit::from_dimacs(d2_id as isize)]);
+        assert!(solver.solve().unwrap());
+    }
+
+    #[test]
+    fn pick_best() {
+        let mut pool = InMemoryPool::new();
+        let v2_id = pool.add(PkgMeta::test("a", "2"));
+        let v3_id = pool.add(PkgMeta::test("a", "3"));
+        let v1_id = pool.add(PkgMeta::test("a", "1"));
+        pool.finalize();
+
+        assert_eq!(pool.get_pkgs_by_name("a").unwrap(), vec![v3_id, v2_id, v1_id]);
+        assert_eq!(pool.pick_best_pkg("a", &VersionRequirement::default(), true).unwrap(), v3_id);
+        let ver_req = VersionRequirement::try_from("<3").unwrap();
+        assert_eq!(pool.pick_best_pkg("a", &ver_req, true).unwrap(), v2_id);
+    }
+
+    #[test]
+    fn repo_priority() {
+        let mut pool = InMemoryPool::new();
+        let mut stable = PkgMeta::test("a", "1");
+        stable.repo = Some("stable".to_string());
+        let stable_id = pool.add(stable);
+        let mut testing = PkgMeta::test("a", "2");
+        testing.repo = Some("testing".to_string());
+        let testing_id = pool.add(testing);
+        pool.finalize();
+        // Newest version wins without priorities
+        assert_eq!(
+            pool.pick_best_pkg("a", &VersionRequirement::default(), true).unwrap(),
+            testing_id
+        );
+
+        pool.set_repo_priorities(HashMap::from([("stable".to_string(), 10)]));
+        pool.finalize();
+        assert_eq!(pool.get_pkgs_by_name("a").unwrap(), vec![stable_id, testing_id]);
+        assert_eq!(
+            pool.pick_best_pkg("a", &VersionRequirement::default(), true).unwrap(),
+            stable_id
+        );
+        // Version requirement still applies
+        let ver_req = VersionRequirement::try_from(">=2").unwrap();
+        assert_eq!(pool.pick_best_pkg("a", &ver_req, true).unwrap(), testing_id);
+    }
+
+    #[test]
+    fn from_repo() {
+        let mut pool = InMemoryPool::new();
+        let mut stable = PkgMeta::test("a", "1");
+        stable.repo = Some("stable".to_string());
+        let stable_id = pool.add(stable);
+        let mut testing = PkgMeta::test("a", "2");
+        testing.repo = Some("testing".to_string());
+        let testing_id = pool.add(testing);
+        let mut b = PkgMeta::test("b", "1");
+        b.repo = Some("stable".to_string());
+        pool.add(b);
+        pool.finalize();
+
+        let any = VersionRequirement::default();
+        assert_eq!(pool.pick_best_pkg_from_repo("a", &any, "stable").unwrap(), stable_id);
+        assert_eq!(pool.pick_best_pkg_from_repo("a", &any, "testing").unwrap(), testing_id);
+        let ver_req = VersionRequirement::try_from(">=2").unwrap();
+        assert!(pool.pick_best_pkg_from_repo("a", &ver_req, "stable").is_err());
+        assert!(pool.pick_best_pkg_from_repo("b", &any, "testing").is_err());
+        assert!(pool.pick_best_pkg_from_repo("c", &any, "stable").is_err());
+    }
+
+    #[test]
+    fn reverse_deps() {
+        let mut pool = InMemoryPool::new();
+        let mut a = PkgMeta::test("a", "1");
+        a.depends.push(("c".to_string(), VersionRequirement::default(), None));
+        let a_id = pool.add(a);
+        let mut b = PkgMeta::test("b", "1");
+        b.depends.push(("x".to_string(), VersionRequirement::default(), None));
+        let b_id = pool.add(b);
+        let mut c = PkgMeta::test("c", "1");
+        c.provides.push(("x".to_string(), VersionRequirement::default(), None));
+        let c_id = pool.add(c);
+        pool.finalize();
+
+        assert_eq!(pool.rdepends(c_id).unwrap(), vec![a_id, b_id]);
+        assert!(pool.rdepends(a_id).unwrap().is_empty());
+    }
 }
@@ -0,0 +1,70 @@
+/// Show actions as a JSON document
+use super::PkgActions;
+use crate::{types::PkgSource, utils::cli::print_json};
+
+use anyhow::Result;
+use serde::Serialize;
+
+#[derive(Serialize, Default)]
+struct JsonActions {
+    install: Vec<JsonRow>,
+    upgrade: Vec<JsonRow>,
+    downgrade: Vec<JsonRow>,
+    reinstall: Vec<JsonRow>,
+    remove: Vec<JsonRow>,
+    purge: Vec<JsonRow>,
+}
+
+#[derive(Serialize)]
+struct JsonRow {
+    name: String,
+    old_version: Option<String>,
+    new_version: Option<String>,
+    download_size: u64,
+    install_size: u64,
+}
+
+pub fn show_json(actions: &PkgActions) -> Result<()> {
+    let mut res = JsonActions::default();
+    for (new, old) in &actions.install {
+        let download_size = match new.source {
+            PkgSource::Http((_, size, _)) => size,
+            PkgSource::Local(_) => 0,
+        };
+        let row = JsonRow {
+            name: new.name.clone(),
+            old_version: old.as_ref().map(|(ver, _)| ver.to_string()),
+            new_version: Some(new.version.to_string()),
+            download_size,
+            install_size: new.install_size,
+        };
+        match old {
+            Some((oldver, _)) if oldver < &new.version => res.upgrade.push(row),
+            Some((oldver, _)) if oldver == &new.version => res.reinstall.push(row),
+            Some(_) => res.downgrade.push(row),
+            None => res.install.push(row),
+        }
+    }
+
+    for (name, size) in &actions.remove {
+        res.remove.push(JsonRow {
+            name: name.clone(),
+            old_version: None,
+            new_version: None,
+            download_size: 0,
+            install_size: *size,
+        });
+    }
+
+    for (name, size) in &actions.purge {
+        res.purge.push(JsonRow {
+            name: name.clone(),
+            old_version: None,
+            new_version: None,
+            download_size: 0,
+            install_size: *size,
+        });
+    }
+
+    print_json(&res)
+}
@@ -1,8 +1,9 @@
+mod json;
 mod table;
 
 use super::{Checksum, PkgMeta, PkgSource, PkgVersion};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use console::style;
 use indicatif::HumanBytes;
 
@@ -11,6 +12,8 @@ pub struct PkgActions<'a> {
     pub install: Vec<(&'a PkgMeta, Option<(PkgVersion, u64)>)>,
     // (Name, InstallSize)
     pub remove: Vec<(String, u64)>,
+    // Removals that also delete configuration files, (Name, InstallSize)
+    pub purge: Vec<(String, u64)>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -30,7 +33,7 @@ pub trait PkgActionModifier {
 
 impl PkgActions<'_> {
     pub fn is_empty(&self) -> bool {
-        self.install.is_empty() && self.remove.is_empty()
+        self.install.is_empty() && self.remove.is_empty() && self.purge.is_empty()
     }
 
     pub fn show(&self) {
@@ -90,6 +93,11 @@ impl PkgActions<'_> {
         let downgrade_prefix = style("DOWNGRADE").on_yellow().white().bold().to_string();
         crate::WRITER.write_chunks(&downgrade_prefix, &to_downgrade).unwrap();
 
+        let to_reinstall: Vec<String> =
+            self.reinstalls().into_iter().map(|name| name.to_string()).collect();
+        let reinstall_prefix = style("REINSTALL").on_blue().bold().to_string();
+        crate::WRITER.write_chunks(&reinstall_prefix, &to_reinstall).unwrap();
+
         let removes: Vec<String> = self
             .remove
             .iter()
@@ -100,12 +108,71 @@ impl PkgActions<'_> {
             .collect();
         let remove_prefix = style("REMOVE").on_red().bold().white().to_string();
         crate::WRITER.write_chunks(&remove_prefix, &removes).unwrap();
+
+        let purges: Vec<String> = self.purge.iter().map(|(name, _)| name.clone()).collect();
+        let purge_prefix = style("PURGE").on_red().bold().white().to_string();
+        crate::WRITER.write_chunks(&purge_prefix, &purges).unwrap();
+    }
+
+    /// Names of packages that will be downgraded
+    pub fn downgrades(&self) -> Vec<&str> {
+        self.install
+            .iter()
+            .filter_map(|(new, old)| match old {
+                Some((oldver, _)) if &new.version < oldver => Some(new.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Names of packages that will be installed again at the same version
+    pub fn reinstalls(&self) -> Vec<&str> {
+        self.install
+            .iter()
+            .filter_map(|(new, old)| match old {
+                Some((oldver, _)) if &new.version == oldver => Some(new.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Refuse downgrades if `no_downgrade` is set
+    /// With `allow_downgrade`, downgrades of packages in `pinned` are still allowed
+    pub fn check_downgrades(
+        &self,
+        no_downgrade: bool,
+        allow_downgrade: bool,
+        pinned: &[String],
+    ) -> Result<()> {
+        if !no_downgrade {
+            return Ok(());
+        }
+        let refused: Vec<&str> = self
+            .downgrades()
+            .into_iter()
+            .filter(|name| !(allow_downgrade && pinned.iter().any(|p| p == name)))
+            .collect();
+        if !refused.is_empty() {
+            bail!(
+                "Refusing to downgrade the following package(s): {}.",
+                refused
+                    .iter()
+                    .map(|name| style(name).bold().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        Ok(())
     }
 
     pub fn show_tables(&self, no_pager: bool) -> Result<()> {
         table::show_table(self, no_pager)
     }
 
+    pub fn show_json(&self) -> Result<()> {
+        json::show_json(self)
+    }
+
     pub fn show_size_change(&self) {
         crate::WRITER
             .writeln(
@@ -117,43 +184,25 @@ impl PkgActions<'_> {
                 ),
             )
             .unwrap();
-        let install_size_change = self.calculate_size_change();
-        let abs_install_size_change = install_size_change.abs() as u64;
-        if install_size_change >= 0 {
-            crate::WRITER
-                .writeln(
-                    "",
-                    &format!(
-                        "{} +{}",
-                        &style("Estimated change in storage usage:").bold().to_string(),
-                        HumanBytes(abs_install_size_change)
-                    ),
-                )
-                .unwrap();
-        } else {
-            crate::WRITER
-                .writeln(
-                    "",
-                    &format!(
-                        "{} -{}",
-                        &style("Estimated change in storage usage:").bold().to_string(),
-                        HumanBytes(abs_install_size_change)
-                    ),
-                )
-                .unwrap();
-        }
+        crate::WRITER
+            .writeln(
+                "",
+                &format!(
+                    "{} {}",
+                    &style("Estimated change in storage usage:").bold().to_string(),
+                    format_size_change(self.calculate_size_change())
+                ),
+            )
+            .unwrap();
     }
 
     fn calculate_size_change(&self) -> i128 {
         let mut res: i128 = 0;
-        for install in &self.install {
-            res += i128::from(install.0.install_size);
-            if let Some(oldpkg) = &install.1 {
-                res -= i128::from(oldpkg.1);
-            }
+        for (new, old) in &self.install {
+            res += install_size_change(new, old);
         }
 
-        for remove in &self.remove {
+        for remove in self.remove.iter().chain(&self.purge) {
             res -= i128::from(remove.1);
         }
 
@@ -170,3 +219,77 @@ impl PkgActions<'_> {
         res
     }
 }
+
+/// Change in storage usage of installing `new`, replacing `old` if it exists
+/// Both sizes are in bytes
+fn install_size_change(new: &PkgMeta, old: &Option<(PkgVersion, u64)>) -> i128 {
+    let mut res = i128::from(new.install_size);
+    if let Some((_, oldsize)) = old {
+        res -= i128::from(*oldsize);
+    }
+    res
+}
+
+/// Show a size change with an explicit sign
+fn format_size_change(change: i128) -> String {
+    let symbol = if change >= 0 { '+' } else { '-' };
+    format!("{}{}", symbol, HumanBytes(change.unsigned_abs() as u64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::PkgMeta;
+
+    #[test]
+    fn downgrades() {
+        let a = PkgMeta::test("a", "1");
+        let b = PkgMeta::test("b", "3");
+        let c = PkgMeta::test("c", "1");
+        let actions = PkgActions {
+            install: vec![
+                (&a, Some((PkgVersion::try_from("2").unwrap(), 0))),
+                (&b, Some((PkgVersion::try_from("2").unwrap(), 0))),
+                (&c, None),
+            ],
+            remove: Vec::new(),
+            purge: Vec::new(),
+        };
+        assert_eq!(actions.downgrades(), vec!["a"]);
+
+        // Downgrades are fine by default
+        assert!(actions.check_downgrades(false, false, &[]).is_ok());
+        // --no-downgrade refuses them, even if pinned
+        assert!(actions.check_downgrades(true, false, &["a".to_string()]).is_err());
+        // Unless --allow-downgrade is given and the package is pinned
+        assert!(actions.check_downgrades(true, true, &["a".to_string()]).is_ok());
+        assert!(actions.check_downgrades(true, true, &["b".to_string()]).is_err());
+    }
+
+    #[test]
+    fn size_change() {
+        let mut install = PkgMeta::test("install", "1");
+        install.install_size = 4096;
+        let mut upgrade = PkgMeta::test("upgrade", "2");
+        upgrade.install_size = 3000;
+        let mut downgrade = PkgMeta::test("downgrade", "1");
+        downgrade.install_size = 1000;
+        let actions = PkgActions {
+            install: vec![
+                (&install, None),
+                (&upgrade, Some((PkgVersion::try_from("1").unwrap(), 2000))),
+                (&downgrade, Some((PkgVersion::try_from("2").unwrap(), 1500))),
+            ],
+            remove: vec![("remove".to_string(), 10000)],
+            purge: Vec::new(),
+        };
+        // 4096 + (3000 - 2000) + (1000 - 1500) - 10000
+        assert_eq!(actions.calculate_size_change(), -5404);
+        // Per-package changes shown in tables add up to the total
+        let rows: i128 =
+            actions.install.iter().map(|(new, old)| install_size_change(new, old)).sum();
+        assert_eq!(rows - 10000, actions.calculate_size_change());
+        assert_eq!(format_size_change(-5404), format!("-{}", HumanBytes(5404)));
+        assert_eq!(format_size_change(0), format!("+{}", HumanBytes(0)));
+    }
+}
@@ -1,9 +1,9 @@
 /// Show actions in tables
-use super::PkgActions;
+use super::{format_size_change, install_size_change, PkgActions};
 use crate::utils::pager::Pager;
 
 use anyhow::Result;
-use console::style;
+use console::{style, StyledObject};
 use indicatif::HumanBytes;
 use std::io::Write;
 use tabled::{Alignment, Column, Full, Modify, Style, Table, Tabled};
@@ -16,6 +16,8 @@ struct InstallRow {
     version: String,
     #[header("Installed Size")]
     size: String,
+    #[header("Repository")]
+    repo: String,
 }
 
 #[derive(Tabled)]
@@ -30,43 +32,62 @@ struct RemoveRow {
 }
 
 pub fn show_table(actions: &PkgActions, no_pager: bool) -> Result<()> {
+    let mut pager = Pager::new(no_pager)?;
+    let pager_name = pager.pager_name().to_owned();
+    let mut out = pager.get_writer()?;
+    let colors = console::colors_enabled();
+
+    write_review_help_message(&mut out, colors)?;
+    // Show help message about how to exit review view
+    if pager_name == Some("less") {
+        writeln!(out, "{}", paint("Press [q] to finish review.\n", colors).bold())?;
+    }
+    write_tables(actions, &mut out, colors)?;
+
+    // Finish writing
+    drop(out);
+    // Wait until pager exits
+    pager.wait_for_exit()?;
+
+    Ok(())
+}
+
+/// Write tables of actions and the size changes, colored only if `colors` is set
+fn write_tables(actions: &PkgActions, out: &mut dyn Write, colors: bool) -> Result<()> {
     let mut install_rows = Vec::new();
     let mut upgrade_rows = Vec::new();
     let mut downgrade_rows = Vec::new();
+    let mut reinstall_rows = Vec::new();
     let mut remove_rows = Vec::new();
 
     for (new, old) in actions.install.iter().rev() {
-        let mut install_size_change: i128 = new.install_size.into();
-        if let Some((_, oldsize)) = old {
-            install_size_change -= *oldsize as i128;
-        }
-        let mut install_size_change_str = HumanBytes(install_size_change.abs() as u64).to_string();
-        if install_size_change >= 0 {
-            install_size_change_str.insert(0, '+');
-        } else {
-            install_size_change_str.insert(0, '-');
-        }
         let mut row = InstallRow {
             name: new.name.clone(),
             version: match old {
-                Some((oldver, _)) => format!("{} -> {}", oldver, new.version),
-                None => new.version.to_string(),
+                Some((oldver, _)) if oldver != &new.version => {
+                    format!("{} -> {}", oldver, new.version)
+                }
+                _ => new.version.to_string(),
             },
-            size: install_size_change_str,
+            size: format_size_change(install_size_change(new, old)),
+            repo: new.repo.clone().unwrap_or_else(|| "-".to_string()),
         };
         // Insert to different row based on operation
         if let Some(old) = old {
             // Upgrade/downgrade
             if old.0 < new.version {
-                row.name = style(row.name).green().to_string();
+                row.name = paint(row.name, colors).green().to_string();
                 upgrade_rows.push(row);
+            } else if old.0 == new.version {
+                row.name = paint(row.name, colors).blue().to_string();
+                reinstall_rows.push(row);
             } else {
-                row.name = style(row.name).yellow().to_string();
+                row.name = paint(row.name, colors).yellow().to_string();
                 downgrade_rows.push(row);
             }
         } else {
             // New package
-            row.name = style(row.name).green().to_string();
+            row.name = paint(row.name, colors).green().to_string();
             install_rows.push(row);
         }
     }
@@ -74,25 +95,27 @@ pub fn show_table(actions: &PkgActions, no_pager: bool) -> Result<()> {
     for (name, size) in &actions.remove {
         let detail = String::new();
         let row = RemoveRow {
-            name: style(name).red().to_string(),
+            name: paint(name, colors).red().to_string(),
             size: HumanBytes(*size).to_string(),
             detail,
         };
         remove_rows.push(row);
     }
-
-    let mut pager = Pager::new(no_pager)?;
-    let pager_name = pager.pager_name().to_owned();
-    let mut out = pager.get_writer()?;
-
-    write_review_help_message(&mut out)?;
-    // Show help message about how to exit review view
-    if pager_name == Some("less") {
-        writeln!(out, "{}", style("Press [q] to finish review.\n").bold())?;
+    for (name, size) in &actions.purge {
+        let row = RemoveRow {
+            name: paint(name, colors).red().to_string(),
+            size: HumanBytes(*size).to_string(),
+            detail: "Purge configuration files".to_string(),
+        };
+        remove_rows.push(row);
     }
 
     if !remove_rows.is_empty() {
-        writeln!(out, "The following packages will be {}:\n", style("REMOVED").red().bold())?;
+        writeln!(
+            out,
+            "The following packages will be {}:\n",
+            paint("REMOVED", colors).red().bold()
+        )?;
         let table = Table::new(&remove_rows)
             .with(Modify::new(Full).with(Alignment::left()))
             // Install Size column should align right
@@ -103,7 +126,11 @@ pub fn show_table(actions: &PkgActions, no_pager: bool) -> Result<()> {
     }
 
     if !install_rows.is_empty() {
-        writeln!(out, "The following packages will be {}:\n", style("installed").green().bold())?;
+        writeln!(
+            out,
+            "The following packages will be {}:\n",
+            paint("installed", colors).green().bold()
+        )?;
         let table = Table::new(&install_rows)
             .with(Modify::new(Full).with(Alignment::left()))
             // Install Size column should align right
@@ -114,7 +141,11 @@ pub fn show_table(actions: &PkgActions, no_pager: bool) -> Result<()> {
     }
 
     if !upgrade_rows.is_empty() {
-        writeln!(out, "The following packages will be {}:\n", style("upgraded").green().bold())?;
+        writeln!(
+            out,
+            "The following packages will be {}:\n",
+            paint("upgraded", colors).green().bold()
+        )?;
         let table = Table::new(&upgrade_rows)
             .with(Modify::new(Full).with(Alignment::left()))
             // Install Size column should align right
@@ -125,11 +156,30 @@ pub fn show_table(actions: &PkgActions, no_pager: bool) -> Result<()> {
     }
 
     if !downgrade_rows.is_empty() {
-        writeln!(out, "The following packages will be {}:\n", style("downgraded").yellow().bold())?;
+        writeln!(
+            out,
+            "The following packages will be {}:\n",
+            paint("downgraded", colors).yellow().bold()
+        )?;
         let table = Table::new(&downgrade_rows)
             .with(Modify::new(Full).with(Alignment::left()))
             // Install Size column should align right
-            .with(Modify::new(Column(1..2)).with(Alignment::right()))
+            .with(Modify::new(Column(2..3)).with(Alignment::right()))
+            .with(Modify::new(Full).with(|s: &str| format!(" {s} ")))
+            .with(Style::PSQL);
+        writeln!(out, "{table}")?;
+    }
+
+    if !reinstall_rows.is_empty() {
+        writeln!(
+            out,
+            "The following packages will be {}:\n",
+            paint("reinstalled", colors).blue().bold()
+        )?;
+        let table = Table::new(&reinstall_rows)
+            .with(Modify::new(Full).with(Alignment::left()))
+            // Install Size column should align right
+            .with(Modify::new(Column(2..3)).with(Alignment::right()))
             .with(Modify::new(Full).with(|s: &str| format!(" {s} ")))
             .with(Style::PSQL);
         writeln!(out, "{table}")?;
@@ -139,33 +189,56 @@ pub fn show_table(actions: &PkgActions, no_pager: bool) -> Result<()> {
     writeln!(
         out,
         "{} {}",
-        style("Total download size:").bold(),
+        paint("Total download size:", colors).bold(),
         HumanBytes(actions.calculate_download_size())
     )?;
-    let install_size_change = actions.calculate_size_change();
-    let abs_install_size_change = install_size_change.abs() as u64;
-    let symbol = if install_size_change >= 0 { '+' } else { '-' };
     writeln!(
         out,
-        "{} {}{}",
-        style("Estimated change in storage usage:").bold(),
-        symbol,
-        HumanBytes(abs_install_size_change)
+        "{} {}",
+        paint("Estimated change in storage usage:", colors).bold(),
+        format_size_change(actions.calculate_size_change())
     )?;
 
-    // Finish writing
-    drop(out);
-    // Wait until pager exits
-    pager.wait_for_exit()?;
-
     Ok(())
 }
 
-fn write_review_help_message(w: &mut dyn Write) -> Result<()> {
-    writeln!(w, "{}", style("Pending Operations").bold())?;
+fn write_review_help_message(w: &mut dyn Write, colors: bool) -> Result<()> {
+    writeln!(w, "{}", paint("Pending Operations", colors).bold())?;
     writeln!(w)?;
     writeln!(w, "Shown below is an overview of the pending changes sasm will apply to your system, please review them carefully.")?;
-    writeln!(w, "Please note that sasm may {}, {}, {}, {}, or {} packages in order to fulfill your requested changes.", style("install").green(), style("remove").red(), style("upgrade").green(), style("downgrade").yellow(), style("configure").blue())?;
+    writeln!(w, "Please note that sasm may {}, {}, {}, {}, or {} packages in order to fulfill your requested changes.", paint("install", colors).green(), paint("remove", colors).red(), paint("upgrade", colors).green(), paint("downgrade", colors).yellow(), paint("configure", colors).blue())?;
     writeln!(w)?;
     Ok(())
 }
+
+/// Style without looking at the global color setting
+fn paint<D>(val: D, colors: bool) -> StyledObject<D> {
+    style(val).force_styling(colors)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{PkgMeta, PkgVersion};
+
+    #[test]
+    fn no_color() {
+        let pkg = PkgMeta::test("a", "2");
+        let actions = PkgActions {
+            install: vec![(&pkg, Some((PkgVersion::try_from("1").unwrap(), 0)))],
+            remove: vec![("b".to_string(), 0)],
+            purge: vec![("c".to_string(), 0)],
+        };
+
+        let render = |colors| {
+            let mut out = Vec::new();
+            write_review_help_message(&mut out, colors).unwrap();
+            write_tables(&actions, &mut out, colors).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let out = render(false);
+        assert!(out.contains("1 -> 2"));
+        assert!(!out.contains('\x1b'));
+        assert!(render(true).contains('\x1b'));
+    }
+}
@@ -1,18 +1,27 @@
 use anyhow::{bail, Context, Result};
+use blake2::Blake2b512;
+use indicatif::ProgressBar;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256, Sha512};
-use std::{fmt::Display, fs::File, io, path::Path};
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
 
 #[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
 pub enum Checksum {
     Sha256(Vec<u8>),
     Sha512(Vec<u8>),
+    Blake2b(Vec<u8>),
 }
 
 #[derive(Clone)]
 pub enum ChecksumValidator {
     Sha256((Vec<u8>, Sha256)),
     Sha512((Vec<u8>, Sha512)),
+    Blake2b((Vec<u8>, Blake2b512)),
 }
 
 impl ChecksumValidator {
@@ -20,6 +29,7 @@ impl ChecksumValidator {
         match self {
             ChecksumValidator::Sha256((_, v)) => v.update(data),
             ChecksumValidator::Sha512((_, v)) => v.update(data),
+            ChecksumValidator::Blake2b((_, v)) => v.update(data),
         }
     }
 
@@ -27,18 +37,23 @@ impl ChecksumValidator {
         match self {
             ChecksumValidator::Sha256((c, v)) => c == v.finalize().to_vec(),
             ChecksumValidator::Sha512((c, v)) => c == v.finalize().to_vec(),
+            ChecksumValidator::Blake2b((c, v)) => c == v.finalize().to_vec(),
         }
     }
 }
 
+/// Get the SHA256 digest of a file
+pub fn sha256_file(path: &Path) -> Result<Vec<u8>> {
+    let mut file = File::open(path)
+        .context(format!("Failed to open {} for checking checksum", path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
 impl Checksum {
     pub fn from_file_sha256(path: &Path) -> Result<Self> {
-        let mut file = File::open(path)
-            .context(format!("Failed to open {} for checking checksum", path.display()))?;
-        let mut hasher = Sha256::new();
-        io::copy(&mut file, &mut hasher)?;
-        let hash = hasher.finalize().to_vec();
-        Ok(Self::Sha256(hash))
+        Ok(Self::Sha256(sha256_file(path)?))
     }
 
     /// This function does not do input sanitization, so do checks before!
@@ -57,10 +72,19 @@ impl Checksum {
         Ok(Checksum::Sha512(hex::decode(s)?))
     }
 
+    /// This function does not do input sanitization, so do checks before!
+    pub fn from_blake2b_str(s: &str) -> Result<Self> {
+        if s.len() != 128 {
+            bail!("Malformed BLAKE2b checksum: bad length.")
+        }
+        Ok(Checksum::Blake2b(hex::decode(s)?))
+    }
+
     pub fn get_validator(&self) -> ChecksumValidator {
         match self {
             Checksum::Sha256(c) => ChecksumValidator::Sha256((c.clone(), Sha256::new())),
             Checksum::Sha512(c) => ChecksumValidator::Sha512((c.clone(), Sha512::new())),
+            Checksum::Blake2b(c) => ChecksumValidator::Blake2b((c.clone(), Blake2b512::new())),
         }
     }
 
@@ -86,7 +110,32 @@ impl Checksum {
                     Ok(false)
                 }
             }
+            Checksum::Blake2b(hex) => {
+                let mut hasher = Blake2b512::new();
+                io::copy(&mut r, &mut hasher)?;
+                let hash = hasher.finalize().to_vec();
+                if hex == &hash {
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+        }
+    }
+
+    /// Like cmp_read, but increase the progress bar as data is hashed
+    pub fn cmp_read_with_progress(&self, mut r: impl Read, bar: &ProgressBar) -> Result<bool> {
+        let mut validator = self.get_validator();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let len = r.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            validator.update(&buf[..len]);
+            bar.inc(len as u64);
         }
+        Ok(validator.finish())
     }
 
     pub fn cmp_file(&self, path: &Path) -> Result<bool> {
@@ -95,6 +144,13 @@ impl Checksum {
 
         self.cmp_read(Box::new(file) as Box<dyn std::io::Read>)
     }
+
+    pub fn cmp_file_with_progress(&self, path: &Path, bar: &ProgressBar) -> Result<bool> {
+        let file = File::open(path)
+            .context(format!("Failed to open {} for checking checksum", path.display()))?;
+
+        self.cmp_read_with_progress(file, bar)
+    }
 }
 
 impl Display for Checksum {
@@ -108,6 +164,58 @@ impl Display for Checksum {
                 f.write_str("sha512::")?;
                 f.write_str(&hex::encode(hex))
             }
+            Checksum::Blake2b(hex) => {
+                f.write_str("blake2b::")?;
+                f.write_str(&hex::encode(hex))
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test::TempDir;
+
+    // BLAKE2b-512 of "abc", from RFC 7693
+    const ABC_BLAKE2B: &str = "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923";
+
+    #[test]
+    fn blake2b() {
+        let dir = TempDir::new();
+        let path = dir.join("abc");
+        std::fs::write(&path, "abc").unwrap();
+
+        let checksum = Checksum::from_blake2b_str(ABC_BLAKE2B).unwrap();
+        assert!(checksum.cmp_file(&path).unwrap());
+        let mut validator = checksum.get_validator();
+        validator.update("ab");
+        validator.update("c");
+        assert!(validator.finish());
+
+        // Mismatch
+        std::fs::write(&path, "abd").unwrap();
+        assert!(!checksum.cmp_file(&path).unwrap());
+        assert!(Checksum::from_blake2b_str(&ABC_BLAKE2B[..64]).is_err());
+    }
+
+    #[test]
+    fn progress() {
+        let dir = TempDir::new();
+        let path = dir.join("content");
+        let content = vec![42u8; 4 * 1024 * 1024 + 1];
+        std::fs::write(&path, &content).unwrap();
+        let checksum = Checksum::Sha256(Sha256::digest(&content).to_vec());
+
+        let bar = ProgressBar::hidden();
+        bar.set_length(content.len() as u64);
+        assert!(checksum.cmp_file_with_progress(&path, &bar).unwrap());
+        assert_eq!(bar.position(), content.len() as u64);
+
+        // Mismatch still reports all progress
+        let bar = ProgressBar::hidden();
+        let checksum = Checksum::Sha256(vec![0; 32]);
+        assert!(!checksum.cmp_file_with_progress(&path, &bar).unwrap());
+        assert_eq!(bar.position(), content.len() as u64);
+    }
+}
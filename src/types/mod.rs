@@ -3,9 +3,12 @@ mod checksum;
 mod version;
 
 pub use actions::{PkgActionModifier, PkgActions, PkgInstallAction};
-pub use checksum::{Checksum, ChecksumValidator};
+pub use checksum::{sha256_file, Checksum, ChecksumValidator};
 use nom::error::VerboseError;
-pub use version::{parse_version, parse_version_requirement, PkgVersion, VersionRequirement};
+pub use version::{
+    compare_versions, parse_version, parse_version_requirement, version_satisfies, PinPolicy,
+    PkgVersion, VersionRequirement,
+};
 
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -28,11 +31,52 @@ pub struct PkgMeta {
     pub provides: Vec<(String, VersionRequirement, Option<String>)>,
     pub replaces: Vec<(String, VersionRequirement, Option<String>)>,
     pub install_size: u64,
+    // Who built the package, `%PACKAGER%` of ALPM databases
+    #[serde(default)]
+    pub maintainer: Option<String>,
+    // Upstream project url, `%URL%` of ALPM databases
+    #[serde(default)]
+    pub homepage: Option<String>,
 
     pub source: PkgSource,
+    // Name of the repository this package comes from
+    #[serde(default)]
+    pub repo: Option<String>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg(test)]
+impl PkgMeta {
+    /// A local package with no dependencies, for tests to fill in what they need
+    pub fn test(name: &str, version: &str) -> Self {
+        PkgMeta {
+            name: name.to_string(),
+            description: String::new(),
+            version: PkgVersion::try_from(version).unwrap(),
+            depends: Vec::new(),
+            optional: Vec::new(),
+            conflicts: Vec::new(),
+            provides: Vec::new(),
+            replaces: Vec::new(),
+            install_size: 0,
+            maintainer: None,
+            homepage: None,
+            source: PkgSource::Local(PathBuf::new()),
+            repo: None,
+        }
+    }
+
+    /// Like `test`, but from a repository. Local packages are only picked for `local` requests
+    pub fn test_remote(name: &str, version: &str) -> Self {
+        let source = PkgSource::Http((
+            format!("https://repo.example.org/{name}-{version}.pkg.tar.zst"),
+            0,
+            Checksum::from_sha256_str(&"ab".repeat(32)).unwrap(),
+        ));
+        PkgMeta { source, ..PkgMeta::test(name, version) }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PkgSource {
     // Http((url, size, checksum))
     Http((String, u64, Checksum)),
@@ -42,9 +86,13 @@ pub enum PkgSource {
 
 /// Status of package on this instance, extracted from pacman local state db
 /// Usually located at /var/lib/pacman/local
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PkgStatus {
     pub name: String,
     pub version: PkgVersion,
     pub install_size: u64,
+    // Held packages must not be upgraded, downgraded or removed
+    pub held: bool,
+    // Removing essential packages needs the user to type their names
+    pub essential: bool,
 }
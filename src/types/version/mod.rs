@@ -1,12 +1,15 @@
 mod ord;
 mod parse;
+mod pin;
 mod requirement;
 mod test;
 pub use parse::parse_version;
+pub use pin::PinPolicy;
 pub use requirement::{parse_version_requirement, VersionRequirement};
 
+use anyhow::Result;
 use serde::{Deserialize, Serialize, Serializer};
-use std::fmt;
+use std::{cmp::Ordering, fmt};
 
 #[derive(PartialEq, Eq, Clone, Debug, Deserialize)]
 pub enum PkgVersionSegment {
@@ -17,10 +20,12 @@ pub enum PkgVersionSegment {
 
 /// RPM style package version comparison
 #[derive(PartialEq, Eq, Clone, Debug, Deserialize)]
+#[serde(try_from = "&str")]
 pub struct PkgVersion {
     pub epoch: u64,
     pub version: Vec<PkgVersionSegment>,
-    pub revision: Option<u64>,
+    // Dotted numeric revision, like 3.1 in 1.2-3.1
+    pub revision: Option<Vec<u64>>,
 }
 
 impl fmt::Display for PkgVersionSegment {
@@ -42,8 +47,9 @@ impl fmt::Display for PkgVersion {
         for segment in &self.version {
             write!(f, "{}", &segment)?;
         }
-        if let Some(rev) = self.revision {
-            write!(f, "-{}", rev)?;
+        if let Some(rev) = &self.revision {
+            let rev: Vec<String> = rev.iter().map(|x| x.to_string()).collect();
+            write!(f, "-{}", rev.join("."))?;
         }
         Ok(())
     }
@@ -58,3 +64,13 @@ impl Serialize for PkgVersion {
         serializer.serialize_str(&res)
     }
 }
+
+/// Compare two version strings
+pub fn compare_versions(a: &str, b: &str) -> Result<Ordering> {
+    Ok(PkgVersion::try_from(a)?.cmp(&PkgVersion::try_from(b)?))
+}
+
+/// Check if a version string satisfies a version requirement string, like `>=1.0`
+pub fn version_satisfies(ver: &str, req: &str) -> Result<bool> {
+    Ok(VersionRequirement::try_from(req)?.contains(&PkgVersion::try_from(ver)?))
+}
@@ -81,6 +81,7 @@ impl Ord for PkgVersion {
             }
         }
 
+        // Dotted revisions are compared segment by segment
         if self.revision.is_some() && other.revision.is_some() {
             self.revision.cmp(&other.revision)
         } else {
@@ -52,14 +52,14 @@ fn upstream_version_separater(i: &str) -> IResult<&str, &str> {
     i.split_at_position1_complete(|item| !is_upstream_version_separater(item), ErrorKind::Char)
 }
 
-fn revision(i: &str) -> IResult<&str, &str> {
+fn revision(i: &str) -> IResult<&str, Vec<u64>> {
     let (i, _) = char('-')(i)?;
-    let (i, rev) = digit1(i)?;
+    let (i, rev) = nom::multi::separated_list1(char('.'), digit1)(i)?;
     let (i, _) = eof(i)?;
-    Ok((i, rev))
+    Ok((i, rev.into_iter().map(|x| x.parse().unwrap()).collect()))
 }
 
-fn upstream_version(i: &str) -> IResult<&str, (Vec<PkgVersionSegment>, Option<u64>)> {
+fn upstream_version(i: &str) -> IResult<&str, (Vec<PkgVersionSegment>, Option<Vec<u64>>)> {
     if i.is_empty() {
         return Err(nom::Err::Error(nom::error::Error::from_error_kind(i, ErrorKind::Eof)));
     }
@@ -69,7 +69,7 @@ fn upstream_version(i: &str) -> IResult<&str, (Vec<PkgVersionSegment>, Option<u6
     }
 
     let mut result = Vec::new();
-    let mut rev = None;
+    let mut rev: Option<Vec<u64>> = None;
     let mut ti = i;
     loop {
         if ti.len() == 0 {
@@ -77,7 +77,7 @@ fn upstream_version(i: &str) -> IResult<&str, (Vec<PkgVersionSegment>, Option<u6
             break;
         } else if let Ok((i, r)) = revision(ti) {
             // We've reached the end and there's a revision
-            rev = Some(r.parse().unwrap());
+            rev = Some(r);
             ti = i;
             break;
         } else if let Ok((i, digits)) = digit1::<_, ()>(ti) {
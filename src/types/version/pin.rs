@@ -0,0 +1,63 @@
+use super::{PkgVersion, PkgVersionSegment, VersionRequirement};
+use serde::{Deserialize, Serialize};
+
+/// How to pin packages added without a version requirement
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PinPolicy {
+    /// Accept any version
+    None,
+    /// Accept newer versions with the same major version, like `>=1.2-1, <2`
+    Major,
+    /// Only accept this exact version
+    Exact,
+}
+
+impl PinPolicy {
+    /// Derive a requirement from the version being installed
+    pub fn requirement(&self, version: &PkgVersion) -> VersionRequirement {
+        let mut res = VersionRequirement::default();
+        match self {
+            PinPolicy::None => (),
+            PinPolicy::Exact => {
+                res.lower_bond = Some((version.clone(), true));
+                res.upper_bond = Some((version.clone(), true));
+            }
+            PinPolicy::Major => {
+                res.lower_bond = Some((version.clone(), true));
+                // Versions not starting with a number have no major version to stay within
+                if let Some(PkgVersionSegment::Number(major)) = version.version.first() {
+                    let next = PkgVersion {
+                        epoch: version.epoch,
+                        version: vec![PkgVersionSegment::Number(major + 1)],
+                        revision: None,
+                    };
+                    res.upper_bond = Some((next, false));
+                }
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pin_policy() {
+        let req = |policy: PinPolicy, version: &str| {
+            policy.requirement(&PkgVersion::try_from(version).unwrap()).to_string()
+        };
+        assert_eq!(req(PinPolicy::None, "1.2.3-1"), "");
+        assert_eq!(req(PinPolicy::Exact, "1.2.3-1"), "=1.2.3-1");
+        assert_eq!(req(PinPolicy::Major, "1.2.3-1"), ">=1.2.3-1, <2");
+        assert_eq!(req(PinPolicy::Major, "2:15.0-3"), ">=2:15.0-3, <2:16");
+        assert_eq!(req(PinPolicy::Major, "r1234.abcdef-1"), ">=r1234.abcdef-1");
+
+        let major = PinPolicy::Major.requirement(&PkgVersion::try_from("1.2.3-1").unwrap());
+        assert!(major.contains(&PkgVersion::try_from("1.9-1").unwrap()));
+        assert!(!major.contains(&PkgVersion::try_from("2.0-1").unwrap()));
+        assert!(!major.contains(&PkgVersion::try_from("1.2.2-1").unwrap()));
+    }
+}
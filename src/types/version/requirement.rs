@@ -11,16 +11,18 @@ pub struct VersionRequirement {
     // The bool represents if the restriction is inclusive
     pub lower_bond: Option<(PkgVersion, bool)>,
     pub upper_bond: Option<(PkgVersion, bool)>,
+    // Versions that are explicitly excluded, like `!= 1.4.2-3`
+    pub exclusions: Vec<PkgVersion>,
 }
 
 impl VersionRequirement {
     pub fn new() -> Self {
-        VersionRequirement { lower_bond: None, upper_bond: None }
+        VersionRequirement { lower_bond: None, upper_bond: None, exclusions: Vec::new() }
     }
 
     /// Check if this VersionRequirement accepts arbitary version
     pub fn is_arbitary(&self) -> bool {
-        self.lower_bond.is_none() && self.upper_bond.is_none()
+        self.lower_bond.is_none() && self.upper_bond.is_none() && self.exclusions.is_empty()
     }
 
     /// Create a new VersionRequirment that satisfies both original requirements
@@ -31,9 +33,15 @@ impl VersionRequirement {
         } else if self.lower_bond.is_some() && other.lower_bond.is_some() {
             let this = self.lower_bond.as_ref().unwrap();
             let other = other.lower_bond.as_ref().unwrap();
-            if this.0 < other.0 || (this.0 == other.0 && this.1 && !other.1) {
-                // Either other is stricter than this (higher lower-bond),
-                // or same bond but other is not inclusive
+            // Either other is stricter than this (higher lower-bond),
+            // or same bond but other is not inclusive
+            // Versions like `1.0` and `1.0-0` are the same bond, so compare with Ord
+            let stricter = match this.0.cmp(&other.0) {
+                Less => true,
+                Equal => this.1 && !other.1,
+                Greater => false,
+            };
+            if stricter {
                 new.lower_bond = Some(other.clone());
             }
         }
@@ -43,13 +51,25 @@ impl VersionRequirement {
         } else if self.upper_bond.is_some() && other.upper_bond.is_some() {
             let this = self.upper_bond.as_ref().unwrap();
             let other = other.upper_bond.as_ref().unwrap();
-            if this.0 > other.0 || (this.0 == other.0 && this.1 && !other.1) {
-                // Either other is stricter than this (lower upper-bond),
-                // or same bond but other is not inclusive
+            // Either other is stricter than this (lower upper-bond),
+            // or same bond but other is not inclusive
+            let stricter = match this.0.cmp(&other.0) {
+                Greater => true,
+                Equal => this.1 && !other.1,
+                Less => false,
+            };
+            if stricter {
                 new.upper_bond = Some(other.clone());
             }
         }
 
+        // Exclusions from both sides apply
+        for ex in &other.exclusions {
+            if !new.exclusions.iter().any(|e| e.cmp(ex) == Equal) {
+                new.exclusions.push(ex.clone());
+            }
+        }
+
         if !new.valid() {
             bail!("Failed to reach a solution for version requirements {} and {} .", self, other);
         }
@@ -65,8 +85,10 @@ impl VersionRequirement {
             match lower.0.cmp(&upper.0) {
                 Greater => false,
                 Equal => {
-                    // must be both inclusive to be valid
-                    lower.1 && upper.1
+                    // must be both inclusive and not excluded to be valid
+                    lower.1
+                        && upper.1
+                        && !self.exclusions.iter().any(|ex| ex.cmp(&lower.0) == Equal)
                 }
                 Less => true,
             }
@@ -99,6 +121,10 @@ impl VersionRequirement {
             }
         }
 
+        if self.exclusions.iter().any(|ex| ex.cmp(ver) == Equal) {
+            return false;
+        }
+
         true
     }
 
@@ -112,7 +138,9 @@ impl VersionRequirement {
             || self.upper_bond.is_some()
                 && that.upper_bond.is_some()
                 && self.upper_bond.as_ref().unwrap() >= that.upper_bond.as_ref().unwrap();
-        lower_within && upper_within
+        // Every version we exclude must not be accepted by that
+        let exclusions_within = self.exclusions.iter().all(|ex| !that.contains(ex));
+        lower_within && upper_within && exclusions_within
     }
 
     // Check if there's an overlap between two VersionRequirements
@@ -125,13 +153,14 @@ impl VersionRequirement {
 pub fn parse_version_requirement(i: &str) -> IResult<&str, VersionRequirement> {
     let (i, compare) = context(
         "Parsing compare literal...",
-        alt((tag(">="), tag("<="), tag("="), tag(">"), tag("<"))),
+        alt((tag(">="), tag("<="), tag(">>"), tag("<<"), tag("!="), tag("="), tag(">"), tag("<"))),
     )(i)?;
     let (i, _) = space0(i)?;
     let (i, ver) = context("Parsing version in VersionRequirement...", parse_version)(i)?;
     let mut res = VersionRequirement::default();
     match compare {
-        ">" => {
+        // `>>` and `<<` are the dpkg spellings of strict comparisons
+        ">" | ">>" => {
             res.lower_bond = Some((ver, false));
         }
         ">=" => {
@@ -141,12 +170,15 @@ pub fn parse_version_requirement(i: &str) -> IResult<&str, VersionRequirement> {
             res.lower_bond = Some((ver.clone(), true));
             res.upper_bond = Some((ver, true));
         }
-        "<" => {
+        "<" | "<<" => {
             res.upper_bond = Some((ver, false));
         }
         "<=" => {
             res.upper_bond = Some((ver, true));
         }
+        "!=" => {
+            res.exclusions.push(ver);
+        }
         _ => panic!(),
     }
 
@@ -156,9 +188,15 @@ pub fn parse_version_requirement(i: &str) -> IResult<&str, VersionRequirement> {
 impl TryFrom<&str> for VersionRequirement {
     type Error = anyhow::Error;
 
+    /// Accepts the format produced by `Display`: comma separated requirements, or
+    /// an empty string for arbitary version
     fn try_from(s: &str) -> Result<Self> {
-        let (_, ver_req) =
-            parse_version_requirement(s).map_err(|e| format_err!("Malformed version: {}", e))?;
+        let mut ver_req = VersionRequirement::default();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let (_, new) = parse_version_requirement(part)
+                .map_err(|e| format_err!("Malformed version: {}", e))?;
+            ver_req = ver_req.combine(&new)?;
+        }
         if !ver_req.valid() {
             bail!("Failed to parse version requirements: lower bound is greater than upper bound.")
         }
@@ -168,36 +206,43 @@ impl TryFrom<&str> for VersionRequirement {
 
 impl fmt::Display for VersionRequirement {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // First, deal with equal
+        let mut written = false;
         if self.lower_bond.is_some() && self.lower_bond == self.upper_bond {
+            // First, deal with equal
             let lower = self.lower_bond.as_ref().unwrap();
             write!(f, "={}", lower.0)?;
-            // We are done!
-            return Ok(());
-        }
-
-        // If not equal, write two parts
-        let mut written = false;
-        if let Some(lower) = &self.lower_bond {
-            // If inclusive
-            if lower.1 {
-                write!(f, ">={}", lower.0)?;
-            } else {
-                write!(f, ">{}", lower.0)?;
-            }
             written = true;
+        } else {
+            // If not equal, write two parts
+            if let Some(lower) = &self.lower_bond {
+                // If inclusive
+                if lower.1 {
+                    write!(f, ">={}", lower.0)?;
+                } else {
+                    write!(f, ">{}", lower.0)?;
+                }
+                written = true;
+            }
+            if let Some(upper) = &self.upper_bond {
+                // Add comma
+                if written {
+                    write!(f, ", ")?;
+                }
+                // If inclusive
+                if upper.1 {
+                    write!(f, "<={}", upper.0)?;
+                } else {
+                    write!(f, "<{}", upper.0)?;
+                }
+                written = true;
+            }
         }
-        if let Some(upper) = &self.upper_bond {
-            // Add comma
+        for ex in &self.exclusions {
             if written {
                 write!(f, ", ")?;
             }
-            // If inclusive
-            if upper.1 {
-                write!(f, "<={}", upper.0)?;
-            } else {
-                write!(f, "<{}", upper.0)?;
-            }
+            write!(f, "!={}", ex)?;
+            written = true;
         }
         Ok(())
     }
@@ -250,6 +295,7 @@ mod test {
                 VersionRequirement {
                     lower_bond: Some((PkgVersion::try_from("1").unwrap(), false)),
                     upper_bond: Some((PkgVersion::try_from("2").unwrap(), true)),
+                    exclusions: Vec::new(),
                 },
             ),
         ];
@@ -270,4 +316,118 @@ mod test {
             assert_eq!(t.0.combine(&t.1).is_ok(), false);
         }
     }
+
+    #[test]
+    fn merge_exact() {
+        let req = |s: &str| VersionRequirement::try_from(s).unwrap();
+        let ok = vec![
+            ("=1.0", "=1.0", "=1.0"),
+            ("=1.0", ">=1.0", "=1.0"),
+            ("=1.0", "<=1.0", "=1.0"),
+            (">=1.0", "=1.0", "=1.0"),
+            ("=1.0", ">0.9, <1.1", "=1.0"),
+            ("=1.0", "!=1.1", "=1.0, !=1.1"),
+            (">=1.0", "<=1.0", "=1.0"),
+        ];
+        for (a, b, expected) in ok {
+            assert_eq!(req(a).combine(&req(b)).unwrap().to_string(), expected, "{a} and {b}");
+            assert_eq!(req(b).combine(&req(a)).unwrap().to_string(), expected, "{b} and {a}");
+        }
+
+        let empty = vec![
+            ("=1.0", ">1.0"),
+            ("=1.0", "<1.0"),
+            ("=1.0", "=1.1"),
+            ("=1.0", ">=1.1"),
+            ("=1.0", "<=0.9"),
+            (">=1.0", "<1.0"),
+            (">1.0", "<=1.0"),
+            // Same version spelled differently
+            ("=1.0", "<1.0-0"),
+            ("=1.0-0", ">1.0"),
+            (">=1.0-0", "<1.0"),
+        ];
+        for (a, b) in empty {
+            assert!(req(a).combine(&req(b)).is_err(), "{a} and {b}");
+            assert!(req(b).combine(&req(a)).is_err(), "{b} and {a}");
+            assert!(!req(a).overlap(&req(b)));
+        }
+    }
+
+    #[test]
+    fn parse_strict_ops() {
+        let tests = vec![
+            (">> 1.0", Some((PkgVersion::try_from("1.0").unwrap(), false)), None),
+            (">>1.0", Some((PkgVersion::try_from("1.0").unwrap(), false)), None),
+            ("> 1.0", Some((PkgVersion::try_from("1.0").unwrap(), false)), None),
+            ("<< 2.0", None, Some((PkgVersion::try_from("2.0").unwrap(), false))),
+            ("<<2.0", None, Some((PkgVersion::try_from("2.0").unwrap(), false))),
+            ("<2.0", None, Some((PkgVersion::try_from("2.0").unwrap(), false))),
+            (">= 1.0", Some((PkgVersion::try_from("1.0").unwrap(), true)), None),
+            ("<=2.0", None, Some((PkgVersion::try_from("2.0").unwrap(), true))),
+        ];
+
+        for (s, lower_bond, upper_bond) in tests {
+            let (rest, req) = parse_version_requirement(s).unwrap();
+            assert_eq!(rest, "");
+            assert_eq!(req.lower_bond, lower_bond);
+            assert_eq!(req.upper_bond, upper_bond);
+        }
+    }
+
+    #[test]
+    fn display_roundtrip() {
+        let tests =
+            vec!["", "=1.0-1", ">=1, <2", ">1, <=2", "<2, !=1.5", ">=1:1.0, !=1:1.2, !=1:1.3"];
+        for t in tests {
+            let ver_req = VersionRequirement::try_from(t).unwrap();
+            assert_eq!(ver_req.to_string(), t);
+            assert_eq!(
+                VersionRequirement::try_from(ver_req.to_string().as_str()).unwrap(),
+                ver_req
+            );
+        }
+    }
+
+    #[test]
+    fn exclusions() {
+        let req = VersionRequirement::try_from("!= 1.4.2-3").unwrap();
+        assert_eq!(req.exclusions, vec![PkgVersion::try_from("1.4.2-3").unwrap()]);
+        assert!(!req.contains(&PkgVersion::try_from("1.4.2-3").unwrap()));
+        assert!(req.contains(&PkgVersion::try_from("1.4.2-2").unwrap()));
+        assert!(req.contains(&PkgVersion::try_from("1.4.3").unwrap()));
+        assert_eq!(req.to_string(), "!=1.4.2-3");
+
+        // Exclusions are unioned when combining
+        let combined = req
+            .combine(&VersionRequirement::try_from(">=1.4").unwrap())
+            .unwrap()
+            .combine(&VersionRequirement::try_from("!=1.5").unwrap())
+            .unwrap();
+        assert_eq!(combined.exclusions.len(), 2);
+        assert!(!combined.contains(&PkgVersion::try_from("1.5").unwrap()));
+        assert!(!combined.contains(&PkgVersion::try_from("1.3").unwrap()));
+        assert_eq!(combined.to_string(), ">=1.4, !=1.4.2-3, !=1.5");
+
+        // Excluding the only acceptable version is invalid
+        assert!(VersionRequirement::try_from("=1.0")
+            .unwrap()
+            .combine(&VersionRequirement::try_from("!=1.0").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn within_exclusions() {
+        let req = VersionRequirement::try_from("!=2").unwrap();
+        // Accepts 2, so it's not within
+        assert!(!req.within(&VersionRequirement::try_from(">1").unwrap()));
+        // Also excludes 2, so it's within
+        let that = VersionRequirement::try_from(">1")
+            .unwrap()
+            .combine(&VersionRequirement::try_from("!=2").unwrap())
+            .unwrap();
+        assert!(req.within(&that));
+        // Does not touch 2 at all
+        assert!(req.within(&VersionRequirement::try_from(">=3").unwrap()));
+    }
 }
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod test {
-    use super::super::PkgVersion;
+    use super::super::{compare_versions, version_satisfies, PkgVersion};
     use std::cmp::Ordering::*;
     #[test]
     fn pkg_ver_ord() {
@@ -29,6 +29,13 @@ mod test {
             ("1.5rc1", Less, "1.5+1"),
             ("1.5rc1", Less, "1.5rc2"),
             ("1.5rc1", Greater, "1.5dev0"),
+            ("1.2-3.1", Less, "1.2-3.2"),
+            ("1.2-3", Less, "1.2-3.1"),
+            ("1.2-3.10", Greater, "1.2-3.9"),
+            ("1.2-4", Greater, "1.2-3.9"),
+            // Hyphens inside upstream version
+            ("1.0-rc1-2", Less, "1.0-rc1-3"),
+            ("1.0-rc1-5", Less, "1.0-rc2-1"),
         ];
 
         for e in source {
@@ -42,9 +49,29 @@ mod test {
                 PkgVersion::try_from(e.0).unwrap().cmp(&PkgVersion::try_from(e.2).unwrap()),
                 e.1
             );
+            assert_eq!(compare_versions(e.0, e.2).unwrap(), e.1);
         }
     }
 
+    #[test]
+    fn satisfies() {
+        assert!(version_satisfies("1.2-3", ">=1.0").unwrap());
+        assert!(!version_satisfies("1.2-3", "<1.0").unwrap());
+        assert!(version_satisfies("1.2-3", ">=1.0, !=1.1").unwrap());
+        assert!(version_satisfies("1.2-3", "").unwrap());
+        assert!(version_satisfies("1.0", "~1.0").is_err());
+    }
+
+    #[test]
+    fn dotted_revision() {
+        let ver = PkgVersion::try_from("1:1.2-3.1").unwrap();
+        assert_eq!(ver.revision, Some(vec![3, 1]));
+        assert_eq!(ver.to_string(), "1:1.2-3.1");
+        let ver = PkgVersion::try_from("1.0-rc1-2").unwrap();
+        assert_eq!(ver.revision, Some(vec![2]));
+        assert_eq!(ver.to_string(), "1.0-rc1-2");
+    }
+
     #[test]
     fn pkg_ver_eq() {
         let source = vec![("1.1+git2021", "1.1+git2021")];
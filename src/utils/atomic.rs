@@ -0,0 +1,61 @@
+use anyhow::{format_err, Context, Result};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Replace the content of a file atomically
+/// Content is written to a temporary file in the same directory, then renamed over the original,
+///   so an interrupted write never leaves a partially written file behind.
+/// Permissions of the original file are preserved.
+pub fn atomic_write(path: &Path, content: &[u8]) -> Result<()> {
+    let tmp_path = tmp_path(path)?;
+    let res = write_and_rename(path, &tmp_path, content);
+    if res.is_err() {
+        // Don't leave the temporary file around
+        let _ = fs::remove_file(&tmp_path);
+    }
+    res.context(format!("Failed to write to {}.", path.display()))
+}
+
+fn write_and_rename(path: &Path, tmp_path: &Path, content: &[u8]) -> Result<()> {
+    let mut f = File::create(tmp_path)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        f.set_permissions(metadata.permissions())?;
+    }
+    f.write_all(content)?;
+    // Make sure content hits the disk before the rename does
+    f.sync_all()?;
+    fs::rename(tmp_path, path)?;
+    Ok(())
+}
+
+fn tmp_path(path: &Path) -> Result<PathBuf> {
+    let filename = path
+        .file_name()
+        .ok_or_else(|| format_err!("{} is not a file.", path.display()))?
+        .to_string_lossy();
+    Ok(path.with_file_name(format!(".{filename}.sasm-tmp")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test::TempDir;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn write() {
+        let dir = TempDir::new();
+        let path = dir.join("user.blueprint");
+        fs::write(&path, "old content, which is longer than the new one\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        atomic_write(&path, b"firefox\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "firefox\n");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        // Only the target file is left
+        assert_eq!(dir.read_dir().unwrap().count(), 1);
+    }
+}
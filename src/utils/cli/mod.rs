@@ -1,5 +1,5 @@
 mod prompt;
-pub use prompt::{ask_confirm, SasmTheme};
+pub use prompt::{ask_confirm, ask_confirm_explicit, ask_input, SasmTheme};
 
 use anyhow::{Context, Result};
 use console::Term;
@@ -20,13 +20,39 @@ pub fn gen_prefix(prefix: &str) -> String {
     real_prefix
 }
 
+/// Enable or disable colors of all styled output
+/// Colors are only used on terminals, and never with --no-color or NO_COLOR set
+pub fn init_colors(no_color: bool) {
+    let no_color_env = std::env::var_os("NO_COLOR").map(|v| !v.is_empty()).unwrap_or(false);
+    console::set_colors_enabled(colors_enabled(no_color, no_color_env, Term::stdout().is_term()));
+    console::set_colors_enabled_stderr(colors_enabled(
+        no_color,
+        no_color_env,
+        Term::stderr().is_term(),
+    ));
+}
+
+fn colors_enabled(no_color: bool, no_color_env: bool, is_term: bool) -> bool {
+    !no_color && !no_color_env && is_term
+}
+
+/// Print a value as one pretty JSON document on stdout, for --json
+pub fn print_json(value: &impl serde::Serialize) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
 pub struct Writer {
     term: Term,
 }
 
 impl Writer {
     pub fn new() -> Self {
-        Writer { term: Term::stdout() }
+        // Keep stdout clean for the JSON document in JSON mode
+        let term = if crate::json() { Term::stderr() } else { Term::stdout() };
+        Writer { term }
     }
 
     pub fn show_cursor(&self) -> Result<()> {
@@ -152,3 +178,16 @@ macro_rules! due_to {
         $crate::WRITER.writeln(&console::style("DUE TO").yellow().bold().to_string(), &format!($($arg)+)).ok();
     };
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        assert!(colors_enabled(false, false, true));
+        assert!(!colors_enabled(true, false, true));
+        assert!(!colors_enabled(false, true, true));
+        assert!(!colors_enabled(false, false, false));
+    }
+}
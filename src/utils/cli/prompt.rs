@@ -2,7 +2,7 @@ use crate::config::Opts;
 
 use anyhow::Result;
 use console::style;
-use dialoguer::{theme::Theme, Confirm};
+use dialoguer::{theme::Theme, Confirm, Input};
 use std::fmt;
 
 pub fn ask_confirm(opts: &Opts, msg: &str) -> Result<bool> {
@@ -10,12 +10,25 @@ pub fn ask_confirm(opts: &Opts, msg: &str) -> Result<bool> {
         return Ok(true);
     }
 
+    ask_confirm_explicit(msg)
+}
+
+/// Always ask, even if the user says yes to every prompt
+pub fn ask_confirm_explicit(msg: &str) -> Result<bool> {
     let prefix = super::gen_prefix("");
     let msg = format!("{prefix}{msg}");
     let res = Confirm::new().with_prompt(msg).interact()?;
     Ok(res)
 }
 
+/// Ask for a line of text, even if the user says yes to every prompt
+pub fn ask_input(msg: &str) -> Result<String> {
+    let prefix = super::gen_prefix("");
+    let msg = format!("{prefix}{msg}");
+    let res = Input::new().with_prompt(msg).allow_empty(true).interact_text()?;
+    Ok(res)
+}
+
 /// Theme for dialoguer
 #[derive(Default)]
 pub struct SasmTheme;
@@ -1,27 +1,43 @@
 use crate::{msg, types::Checksum};
 
-use anyhow::{bail, format_err, Result};
-use async_compression::tokio::write::{GzipDecoder, XzDecoder};
+use anyhow::{bail, format_err, Context, Result};
+use async_compression::tokio::write::{GzipDecoder, XzDecoder, ZstdDecoder};
+use bytes::{Bytes, BytesMut};
 use console::style;
 use futures_util::future::select_all;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use reqwest::Client;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use reqwest::{
+    header::{ACCEPT_RANGES, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, RANGE},
+    Client, Response, StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
+    io::{SeekFrom, Write},
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
-    fs::OpenOptions,
-    io::{AsyncWrite, AsyncWriteExt},
+    fs::{File, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
 };
 
 #[derive(Clone)]
 pub struct DownloadJob {
     pub url: String,
+    // Alternative urls to try in order when `url` keeps failing
+    pub fallback_urls: Vec<String>,
+    // The fallback url being tried instead of `url`, set by the downloader
+    // Results are still keyed by `url`
+    pub current_url: Option<String>,
     pub description: Option<String>,
     pub filename: Option<String>,
     pub size: Option<u64>,
     pub compression: Compression,
+    // Skip the download if the server says the file hasn't changed since it was last fetched
+    // Requires `filename`
+    pub conditional: bool,
 }
 
 #[allow(dead_code)]
@@ -29,43 +45,216 @@ pub struct DownloadJob {
 pub enum Compression {
     Gzip((Option<Checksum>, Option<Checksum>)),
     Xz((Option<Checksum>, Option<Checksum>)),
+    Zstd((Option<Checksum>, Option<Checksum>)),
     None(Option<Checksum>),
 }
 
+impl DownloadJob {
+    /// Where the file is actually fetched from
+    fn current_url(&self) -> &str {
+        self.current_url.as_deref().unwrap_or(&self.url)
+    }
+}
+
 impl Compression {
     pub fn get_extracted_checksum(&self) -> Option<Checksum> {
         match self {
-            Compression::Gzip((_, c)) | Compression::Xz((_, c)) | Compression::None(c) => c,
+            Compression::Gzip((_, c))
+            | Compression::Xz((_, c))
+            | Compression::Zstd((_, c))
+            | Compression::None(c) => c,
         }
         .clone()
     }
 
     pub fn get_download_checksum(&self) -> Option<Checksum> {
         match self {
-            Compression::Gzip((c, _)) | Compression::Xz((c, _)) | Compression::None(c) => c,
+            Compression::Gzip((c, _))
+            | Compression::Xz((c, _))
+            | Compression::Zstd((c, _))
+            | Compression::None(c) => c,
         }
         .clone()
     }
 }
 
+/// Progress events for frontends, written as newline-delimited JSON
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum ProgressEvent {
+    Start { file: String, size: u64 },
+    Progress { file: String, downloaded: u64 },
+    Done { file: String },
+    Error { file: String, error: String },
+}
+
+/// Where progress events go
+#[derive(Clone)]
+pub struct ProgressEvents(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl ProgressEvents {
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        ProgressEvents(Arc::new(Mutex::new(Box::new(out))))
+    }
+
+    pub fn stderr() -> Self {
+        Self::new(std::io::stderr())
+    }
+
+    fn emit(&self, event: ProgressEvent) {
+        let mut out = self.0.lock().unwrap();
+        // Progress reporting should never fail a download
+        if serde_json::to_writer(&mut *out, &event).is_ok() {
+            let _ = writeln!(out);
+            let _ = out.flush();
+        }
+    }
+}
+
+/// Limits total download rate, shared by all running downloads
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    // When the bytes handed out so far are paid off
+    next_free: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        RateLimiter { bytes_per_sec: bytes_per_sec.max(1), next_free: Mutex::new(Instant::now()) }
+    }
+
+    /// Take `len` bytes from the budget
+    /// Returns how long the caller should wait before using them
+    fn reserve(&self, len: u64) -> Duration {
+        let now = Instant::now();
+        let mut next_free = self.next_free.lock().unwrap();
+        // Unused budget doesn't accumulate, so idle time doesn't allow bursts
+        let start = (*next_free).max(now);
+        *next_free = start + Duration::from_secs_f64(len as f64 / self.bytes_per_sec as f64);
+        *next_free - now
+    }
+
+    async fn consume(&self, len: u64) {
+        let delay = self.reserve(len);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Things shared by all downloads of a fetch
+#[derive(Clone)]
+struct JobContext {
+    client: Client,
+    events: Option<ProgressEvents>,
+    rate_limit: Option<Arc<RateLimiter>>,
+}
+
+/// Minimal interval between two progress events of the same file
+const PROGRESS_EVENT_INTERVAL: Duration = Duration::from_millis(200);
+
 pub struct Downloader {
     client: Client,
     max_concurrent: usize,
+    // Maximum number of concurrent downloads from the same host
+    max_per_host: usize,
     max_retry: usize,
+    // Emit progress events instead of showing progress bars
+    events: Option<ProgressEvents>,
+    rate_limit: Option<Arc<RateLimiter>>,
+    // Delay before the first retry, doubled for every further retry
+    retry_backoff: Duration,
+}
+
+/// Maximum delay between two retries
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Delay before the `retry`th retry of a job, with up to 25% jitter
+fn retry_backoff(base: Duration, retry: usize) -> Duration {
+    let exp = base.saturating_mul(1 << retry.saturating_sub(1).min(16)).min(MAX_RETRY_BACKOFF);
+    // Jitter keeps failed jobs from retrying all at once
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+    let jitter = exp.mul_f64(f64::from(nanos % 1000) / 4000.0);
+    (exp + jitter).min(MAX_RETRY_BACKOFF)
 }
 
 impl Downloader {
     pub fn new() -> Self {
-        Downloader { client: Client::new(), max_concurrent: 5, max_retry: 3 }
+        Downloader {
+            client: Client::new(),
+            max_concurrent: 10,
+            max_per_host: 5,
+            max_retry: 3,
+            events: None,
+            rate_limit: None,
+            retry_backoff: Duration::from_millis(500),
+        }
+    }
+
+    /// Set delay before the first retry. `None` keeps the default
+    pub fn with_retry_backoff(mut self, base: Option<Duration>) -> Self {
+        if let Some(base) = base {
+            self.retry_backoff = base;
+        }
+        self
+    }
+
+    /// Limit total download rate, in bytes per second
+    pub fn with_rate_limit(mut self, bytes_per_sec: Option<u64>) -> Self {
+        self.rate_limit = bytes_per_sec.map(|rate| Arc::new(RateLimiter::new(rate)));
+        self
+    }
+
+    /// Report progress as events, and hide progress bars
+    pub fn with_progress_events(mut self, events: ProgressEvents) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Override concurrency limits. `None` keeps the default
+    pub fn with_limits(
+        mut self,
+        max_concurrent: Option<usize>,
+        max_per_host: Option<usize>,
+    ) -> Self {
+        if let Some(n) = max_concurrent {
+            self.max_concurrent = n.max(1);
+        }
+        if let Some(n) = max_per_host {
+            self.max_per_host = n.max(1);
+        }
+        self
     }
 
     /// Download all required stuff in an async manner and show a progress bar
     pub async fn fetch(
         &self,
-        mut to_download: Vec<DownloadJob>,
+        to_download: Vec<DownloadJob>,
         download_path: &Path,
         global_progess: bool,
     ) -> Result<HashMap<String, PathBuf>> {
+        let (res, _) = self.fetch_jobs(to_download, download_path, global_progess, true).await?;
+        Ok(res)
+    }
+
+    /// Like `fetch`, but keep downloading other files when one fails
+    /// Returns downloaded files, and jobs that failed on every mirror with their last error
+    pub async fn fetch_each(
+        &self,
+        to_download: Vec<DownloadJob>,
+        download_path: &Path,
+        global_progess: bool,
+    ) -> Result<(HashMap<String, PathBuf>, Vec<(DownloadJob, anyhow::Error)>)> {
+        self.fetch_jobs(to_download, download_path, global_progess, false).await
+    }
+
+    async fn fetch_jobs(
+        &self,
+        to_download: Vec<DownloadJob>,
+        download_path: &Path,
+        global_progess: bool,
+        fail_fast: bool,
+    ) -> Result<(HashMap<String, PathBuf>, Vec<(DownloadJob, anyhow::Error)>)> {
         // Create download dir
         if !download_path.is_dir() {
             tokio::fs::create_dir_all(download_path).await?;
@@ -75,12 +264,18 @@ impl Downloader {
         let total_size: u64 = to_download.iter().map(|job| job.size.unwrap_or(0)).sum();
 
         let mut res = HashMap::new();
+        let mut failed = Vec::new();
         // Handles for download processes
         let mut handles = Vec::with_capacity(self.max_concurrent);
+        let mut hosts = HostLimiter::new(self.max_per_host);
 
         // Show download info
         msg!("Downloading {} files...", to_download.len());
-        let multibar = MultiProgress::new();
+        let multibar = if self.events.is_some() {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        } else {
+            MultiProgress::new()
+        };
         let bar_template = {
             let max_len = crate::WRITER.get_max_len();
             if max_len < 90 {
@@ -94,83 +289,129 @@ impl Downloader {
         let total = to_download.len();
         let total_str_len = total.to_string().len();
         let mut finished = 0;
-        let global_bar = if total_size > 0 && global_progess {
-            let bar = multibar.insert(0, ProgressBar::new(total_size));
-            bar.set_style(barsty.clone());
-            Some(bar)
-        } else {
-            None
-        };
+        let global_bar =
+            if total_size > 0 && global_progess && !crate::json() && self.events.is_none() {
+                let bar = multibar.insert(0, ProgressBar::new(total_size));
+                bar.set_style(barsty.clone());
+                Some(bar)
+            } else {
+                None
+            };
 
+        // Jobs waiting to be started, with their retry count, progress bar if started before,
+        // and how long to wait before starting
+        let mut pending: Vec<(DownloadJob, usize, Option<ProgressBar>, Duration)> =
+            to_download.into_iter().map(|job| (job, 0, None, Duration::ZERO)).collect();
         // Down them all!
-        while !to_download.is_empty() {
-            while handles.len() < self.max_concurrent && !to_download.is_empty() {
-                let job = to_download.pop().unwrap();
-                let client = self.client.clone();
+        while !pending.is_empty() || !handles.is_empty() {
+            // Start as many jobs as the limits allow
+            while handles.len() < self.max_concurrent {
+                let i = match hosts.next_job(pending.iter().map(|(job, _, _, _)| job.current_url()))
+                {
+                    Some(i) => i,
+                    None => break,
+                };
+                let (job, retry, bar, delay) = pending.remove(i);
+                hosts.start(job.current_url());
+                let ctx = JobContext {
+                    client: self.client.clone(),
+                    events: self.events.clone(),
+                    rate_limit: self.rate_limit.clone(),
+                };
                 let path = download_path.to_owned();
-                let bar = multibar.insert(0, ProgressBar::new(job.size.unwrap_or(0)));
+                let bar = bar.unwrap_or_else(|| {
+                    let bar = multibar.insert(0, ProgressBar::new(job.size.unwrap_or(0)));
+                    bar.set_style(barsty.clone());
+                    bar
+                });
                 let global_bar = global_bar.clone();
-                bar.set_style(barsty.clone());
                 let handle = tokio::spawn(async move {
-                    try_download_file(client, path, job, 0, bar, global_bar).await
+                    if !delay.is_zero() {
+                        tokio::time::sleep(delay).await;
+                    }
+                    try_download_file(ctx, path, job, retry, bar, global_bar).await
                 });
                 handles.push(handle);
             }
             // Wait for any of them to stop
             let (download_res, _, remaining) = select_all(handles).await;
             handles = remaining;
-            // Remove the handle from the list
             match download_res.unwrap() {
-                Ok((name, path)) => {
-                    res.insert(name, path);
+                Ok((job, path)) => {
+                    hosts.finish(job.current_url());
+                    res.insert(job.url, path);
                     finished += 1;
                     update_global_bar(&global_bar, total, finished, total_str_len);
                 }
-                Err(e) => {
-                    // Handling download errors
-                    // If have remaining reties, do it
-                    if e.retry < self.max_retry {
-                        let c = self.client.clone();
-                        let path = download_path.to_owned();
-                        let handle = tokio::spawn(async move {
-                            try_download_file(c, path, e.job, e.retry + 1, e.bar, e.global_bar)
-                                .await
+                Err(mut e) => {
+                    hosts.finish(e.job.current_url());
+                    if let Some(events) = &self.events {
+                        events.emit(ProgressEvent::Error {
+                            file: job_name(&e.job),
+                            error: format!("{:#}", e.error),
                         });
-                        handles.push(handle);
-                    } else {
-                        return Err(e.error);
                     }
-                }
-            }
-        }
-        // Wait for the remaining to finish
-        while !handles.is_empty() {
-            let (download_res, _, remaining) = select_all(handles).await;
-            handles = remaining;
-            match download_res.unwrap() {
-                Ok((url, path)) => {
-                    res.insert(url, path);
-                    finished += 1;
-                    update_global_bar(&global_bar, total, finished, total_str_len);
-                }
-                Err(e) => {
                     // Handling download errors
-                    // If have remaining reties, do it
-                    if e.retry < self.max_retry {
-                        let c = self.client.clone();
-                        let path = download_path.to_owned();
-                        let handle = tokio::spawn(async move {
-                            try_download_file(c, path, e.job, e.retry + 1, e.bar, e.global_bar)
-                                .await
-                        });
-                        handles.push(handle);
-                    } else {
+                    // If have remaining reties, do it after a while. Otherwise try the next mirror now
+                    let delay = if e.retry < self.max_retry {
+                        retry_backoff(self.retry_backoff, e.retry)
+                    } else if switch_mirror(&mut e) {
+                        Duration::ZERO
+                    } else if fail_fast {
                         return Err(e.error);
-                    }
+                    } else {
+                        e.bar.abandon();
+                        failed.push((e.job, e.error));
+                        continue;
+                    };
+                    // Retry first
+                    pending.insert(0, (e.job, e.retry, Some(e.bar), delay));
                 }
             }
         }
-        Ok(res)
+        Ok((res, failed))
+    }
+}
+
+/// Keeps track of running downloads of every host
+struct HostLimiter {
+    active: HashMap<String, usize>,
+    max_per_host: usize,
+}
+
+impl HostLimiter {
+    fn new(max_per_host: usize) -> Self {
+        HostLimiter { active: HashMap::new(), max_per_host }
+    }
+
+    /// Get the host part of a url. Local files are all counted as one host
+    fn host(url: &str) -> String {
+        Url::parse(url)
+            .ok()
+            .and_then(|url| {
+                url.host_str().map(|host| match url.port() {
+                    Some(port) => format!("{host}:{port}"),
+                    None => host.to_owned(),
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    /// Index of the first url whose host is under the limit
+    fn next_job<'a>(&self, mut urls: impl Iterator<Item = &'a str>) -> Option<usize> {
+        urls.position(|url| {
+            self.active.get(&Self::host(url)).copied().unwrap_or(0) < self.max_per_host
+        })
+    }
+
+    fn start(&mut self, url: &str) {
+        *self.active.entry(Self::host(url)).or_default() += 1;
+    }
+
+    fn finish(&mut self, url: &str) {
+        if let Some(count) = self.active.get_mut(&Self::host(url)) {
+            *count = count.saturating_sub(1);
+        }
     }
 }
 
@@ -182,16 +423,188 @@ struct DownloadError {
     global_bar: Option<ProgressBar>,
 }
 
+/// Move a failed job to its next fallback url, resetting the retry counter
+/// Returns false if there are no more mirrors to try
+fn switch_mirror(e: &mut DownloadError) -> bool {
+    if e.job.fallback_urls.is_empty() {
+        return false;
+    }
+
+    let next = e.job.fallback_urls.remove(0);
+    e.bar.println(format!(
+        "{}{} failed, switching to {}",
+        crate::utils::cli::gen_prefix(&console::style("MIRROR").yellow().to_string()),
+        e.job.current_url(),
+        &next
+    ));
+    e.job.current_url = Some(next);
+    e.retry = 0;
+    true
+}
+
+/// HTTP cache validators of a downloaded file, saved next to it
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+struct CacheValidators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl CacheValidators {
+    fn path(file_path: &Path) -> PathBuf {
+        let mut name = file_path.file_name().unwrap_or_default().to_owned();
+        name.push(".validators");
+        file_path.with_file_name(name)
+    }
+
+    /// Validators saved for `file_path`, if the file and its validators both exist
+    async fn load(file_path: &Path) -> Option<Self> {
+        if !file_path.is_file() {
+            return None;
+        }
+        let content = tokio::fs::read(Self::path(file_path)).await.ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    async fn save(&self, file_path: &Path) -> Result<()> {
+        let path = Self::path(file_path);
+        if self == &CacheValidators::default() {
+            // Nothing to validate against next time
+            if path.is_file() {
+                tokio::fs::remove_file(path).await?;
+            }
+            return Ok(());
+        }
+        tokio::fs::write(path, serde_json::to_vec(self)?).await?;
+        Ok(())
+    }
+}
+
+/// Where the content of a download comes from
+enum Source {
+    Http(Response),
+    // For `file://` urls, e.g. mirrors on a local or network filesystem
+    File(File),
+}
+
+impl Source {
+    /// Returns None if `cached` is given and the server says the file is not modified
+    async fn open(
+        client: &Client,
+        url: &str,
+        cached: Option<&CacheValidators>,
+    ) -> Result<Option<Self>> {
+        if url.starts_with("file://") {
+            let path = Url::parse(url)?
+                .to_file_path()
+                .map_err(|_| format_err!("{} is not a valid local path.", url))?;
+            let f = File::open(&path)
+                .await
+                .with_context(|| format!("Failed to open {}.", path.display()))?;
+            Ok(Some(Source::File(f)))
+        } else {
+            let mut req = client.get(url);
+            if let Some(cached) = cached {
+                if let Some(etag) = &cached.etag {
+                    req = req.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = &cached.last_modified {
+                    req = req.header(IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+            let resp = req.send().await?;
+            if cached.is_some() && resp.status() == StatusCode::NOT_MODIFIED {
+                return Ok(None);
+            }
+            resp.error_for_status_ref()?;
+            Ok(Some(Source::Http(resp)))
+        }
+    }
+
+    /// Cache validators sent by the server
+    fn validators(&self) -> CacheValidators {
+        match self {
+            Source::Http(resp) => {
+                let header = |name| {
+                    resp.headers().get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_owned())
+                };
+                CacheValidators { etag: header(ETAG), last_modified: header(LAST_MODIFIED) }
+            }
+            Source::File(_) => CacheValidators::default(),
+        }
+    }
+
+    /// Get the last segment of the url path
+    fn filename(&self, url: &str) -> Option<String> {
+        let url = match self {
+            Source::Http(resp) => resp.url().clone(),
+            Source::File(_) => Url::parse(url).ok()?,
+        };
+        url.path_segments().and_then(|segments| segments.last()).and_then(|name| {
+            if name.is_empty() {
+                None
+            } else {
+                Some(name.to_string())
+            }
+        })
+    }
+
+    async fn content_length(&self) -> Option<u64> {
+        match self {
+            Source::Http(resp) => resp.content_length(),
+            Source::File(f) => f.metadata().await.ok().map(|m| m.len()),
+        }
+    }
+
+    /// Skip the first `pos` bytes of content
+    /// Returns false if the source can't do so
+    async fn resume_from(&mut self, client: &Client, url: &str, pos: u64) -> Result<bool> {
+        match self {
+            Source::Http(resp) => {
+                if resp.headers().get(ACCEPT_RANGES).map(|v| v == "bytes") != Some(true) {
+                    return Ok(false);
+                }
+                let range_resp =
+                    client.get(url).header(RANGE, format!("bytes={pos}-")).send().await?;
+                // Server may ignore the Range request. If so, download the whole file again
+                if range_resp.status() == StatusCode::PARTIAL_CONTENT {
+                    *resp = range_resp;
+                    Ok(true)
+                } else {
+                    Ok(false)
+                }
+            }
+            Source::File(f) => {
+                f.seek(SeekFrom::Start(pos)).await?;
+                Ok(true)
+            }
+        }
+    }
+
+    async fn chunk(&mut self) -> Result<Option<Bytes>> {
+        match self {
+            Source::Http(resp) => Ok(resp.chunk().await?),
+            Source::File(f) => {
+                let mut buf = BytesMut::with_capacity(64 * 1024);
+                if f.read_buf(&mut buf).await? == 0 {
+                    Ok(None)
+                } else {
+                    Ok(Some(buf.freeze()))
+                }
+            }
+        }
+    }
+}
+
 async fn try_download_file(
-    client: Client,
+    ctx: JobContext,
     path: PathBuf,
     job: DownloadJob,
     retry: usize,
     bar: ProgressBar,
     global_bar: Option<ProgressBar>,
-) -> Result<(String, PathBuf), DownloadError> {
-    match download_file(&client, &path, job.clone(), bar.clone(), global_bar.clone()).await {
-        Ok(res) => Ok(res),
+) -> Result<(DownloadJob, PathBuf), DownloadError> {
+    match download_file(&ctx, &path, job.clone(), bar.clone(), global_bar.clone()).await {
+        Ok(file_path) => Ok((job, file_path)),
         Err(error) => Err({
             bar.reset();
             DownloadError { error, job, retry: retry + 1, bar, global_bar }
@@ -200,63 +613,93 @@ async fn try_download_file(
 }
 
 async fn download_file(
-    client: &Client,
+    ctx: &JobContext,
     path: &Path,
     job: DownloadJob,
     bar: ProgressBar,
     global_bar: Option<ProgressBar>,
-) -> Result<(String, PathBuf)> {
-    let mut resp = client.get(&job.url).send().await?;
-    resp.error_for_status_ref()?;
+) -> Result<PathBuf> {
+    let client = &ctx.client;
+    let url = job.current_url().to_owned();
+    let events = &ctx.events;
+    let event_name = job_name(&job);
+    let cached = match (&job.filename, job.conditional) {
+        (Some(filename), true) => CacheValidators::load(&path.join(filename)).await,
+        _ => None,
+    };
+    let mut source = match Source::open(client, &url, cached.as_ref()).await? {
+        Some(source) => source,
+        None => {
+            // Only conditional jobs with a filename get here
+            let filename = job.filename.unwrap();
+            let msg = job.description.as_ref().unwrap_or(&filename);
+            skip_download(ctx, &bar, &global_bar, event_name, job.size, msg);
+            return Ok(path.join(filename));
+        }
+    };
+    let validators = source.validators();
     let filename = match job.filename {
         Some(n) => n,
-        None => resp
-            .url()
-            .path_segments()
-            .and_then(|segments| segments.last())
-            .and_then(|name| if name.is_empty() { None } else { Some(name) })
-            .ok_or_else(|| format_err!("{} doesn't contain filename.", &job.url))?
-            .to_string(),
+        None => source
+            .filename(&url)
+            .ok_or_else(|| format_err!("{} doesn't contain filename.", &url))?,
     };
     let len = match job.size {
         Some(len) => len,
-        None => {
-            resp.content_length().ok_or_else(|| format_err!("Cannot determine content length."))?
-        }
+        None => source
+            .content_length()
+            .await
+            .ok_or_else(|| format_err!("Cannot determine content length."))?,
     };
     let msg = job.description.as_ref().unwrap_or(&filename);
 
     let file_path = path.join(&filename);
+    // Length of the partial file we are resuming from, if any
+    let mut resumed = 0;
     let mut f = {
         if file_path.is_file() {
             if let Some(checksum) = job.compression.get_extracted_checksum() {
+                // Show progress while verifying, large files can take a while
+                bar.set_message(format!("Verifying {}", msg));
+                bar.set_length(tokio::fs::metadata(&file_path).await?.len());
+                bar.set_position(0);
                 let p = file_path.clone();
-                let res = tokio::task::spawn_blocking(move || checksum.cmp_file(&p)).await?;
+                let b = bar.clone();
+                let res =
+                    tokio::task::spawn_blocking(move || checksum.cmp_file_with_progress(&p, &b))
+                        .await?;
                 if res.is_ok() && res.unwrap() {
                     // Checksum matched.
-                    bar.finish_and_clear();
-                    // Reduce global bar length, since we don't need to download this file
-                    if let Some(ref global_bar) = global_bar {
-                        global_bar.set_length(global_bar.length().unwrap() - len);
-                    }
-
-                    if crate::verbose() || global_bar.is_some() {
-                        bar.println(format!(
-                            "{}{} (not modified)",
-                            crate::utils::cli::gen_prefix(
-                                &console::style("SKIP").dim().to_string()
-                            ),
-                            &msg
-                        ));
-                    }
-                    return Ok((job.url, file_path));
+                    skip_download(ctx, &bar, &global_bar, event_name, Some(len), msg);
+                    return Ok(file_path);
                 }
             }
-            // If checksum DNE/mismatch, purge current content
-            let f =
-                OpenOptions::new().read(true).write(true).truncate(true).open(&file_path).await?;
-            f.set_len(0).await?;
-            f
+            // If checksum DNE/mismatch, try to resume from where we left off
+            let existing = tokio::fs::metadata(&file_path).await?.len();
+            // Compressed files are stored extracted, so they can't be resumed
+            // A changed file of a conditional job can't be resumed either
+            if matches!(job.compression, Compression::None(_))
+                && !job.conditional
+                && existing > 0
+                && existing < len
+                && source.resume_from(client, &url, existing).await?
+            {
+                resumed = existing;
+            }
+
+            if resumed > 0 {
+                OpenOptions::new().append(true).open(&file_path).await?
+            } else {
+                // Purge current content
+                let f = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .truncate(true)
+                    .open(&file_path)
+                    .await?;
+                f.set_len(0).await?;
+                f
+            }
         } else {
             OpenOptions::new().read(true).write(true).create(true).open(&file_path).await?
         }
@@ -271,17 +714,39 @@ async fn download_file(
     bar.set_length(len);
     bar.set_position(0);
     bar.reset();
+    if resumed > 0 {
+        bar.set_position(resumed);
+        if let Some(ref global_bar) = global_bar {
+            global_bar.inc(resumed);
+        }
+    }
+
+    if let Some(events) = events {
+        events.emit(ProgressEvent::Start { file: event_name.clone(), size: len });
+    }
+    let mut last_event = Instant::now();
 
     // Download!
     {
         let mut validator =
             job.compression.get_download_checksum().as_ref().map(|c| c.get_validator());
+        if resumed > 0 {
+            if let Some(ref mut validator) = validator {
+                // Feed the part we already have, so the whole file gets validated
+                let existing = tokio::fs::read(&file_path).await?;
+                validator.update(&existing);
+            }
+        }
         let mut writer: Box<dyn AsyncWrite + Unpin + Send> = match job.compression {
             Compression::Gzip(_) => Box::new(GzipDecoder::new(&mut f)),
             Compression::Xz(_) => Box::new(XzDecoder::new(&mut f)),
+            Compression::Zstd(_) => Box::new(ZstdDecoder::new(&mut f)),
             Compression::None(_) => Box::new(&mut f),
         };
-        while let Some(chunk) = resp.chunk().await? {
+        while let Some(chunk) = source.chunk().await? {
+            if let Some(rate_limit) = &ctx.rate_limit {
+                rate_limit.consume(chunk.len() as u64).await;
+            }
             writer.write_all(&chunk).await?;
             let len = chunk.len().try_into().unwrap();
             bar.inc(len);
@@ -292,6 +757,13 @@ async fn download_file(
             if let Some(ref mut validator) = validator {
                 validator.update(&chunk);
             }
+            if let Some(events) = events {
+                if last_event.elapsed() >= PROGRESS_EVENT_INTERVAL {
+                    last_event = Instant::now();
+                    let downloaded = bar.position();
+                    events.emit(ProgressEvent::Progress { file: event_name.clone(), downloaded });
+                }
+            }
         }
         writer.shutdown().await?;
 
@@ -299,7 +771,7 @@ async fn download_file(
             if bar.length().unwrap() != len {
                 bail!(
                     "Bad file size when downloading {}: mirrors may be synchronizing, try again later.",
-                    job.url
+                    url
                 );
             }
         }
@@ -312,13 +784,57 @@ async fn download_file(
         }
     }
 
+    if job.conditional {
+        validators.save(&file_path).await?;
+    }
+
     bar.finish_and_clear();
     bar.println(format!(
         "{}{}",
         crate::utils::cli::gen_prefix(&console::style("DONE").dim().to_string()),
         &msg
     ));
-    Ok((job.url, file_path))
+    if let Some(events) = events {
+        events.emit(ProgressEvent::Done { file: event_name });
+    }
+    Ok(file_path)
+}
+
+/// Report a file that doesn't need to be downloaded again
+/// `len` is the download size that was expected for this file, if known
+fn skip_download(
+    ctx: &JobContext,
+    bar: &ProgressBar,
+    global_bar: &Option<ProgressBar>,
+    event_name: String,
+    len: Option<u64>,
+    msg: &str,
+) {
+    bar.finish_and_clear();
+    // Reduce global bar length, since we don't need to download this file
+    if let (Some(global_bar), Some(len)) = (global_bar, len) {
+        global_bar.set_length(global_bar.length().unwrap() - len);
+    }
+
+    if let Some(events) = &ctx.events {
+        events.emit(ProgressEvent::Start { file: event_name.clone(), size: len.unwrap_or(0) });
+        events.emit(ProgressEvent::Done { file: event_name });
+    }
+    if crate::verbose() || global_bar.is_some() {
+        bar.println(format!(
+            "{}{} (not modified)",
+            crate::utils::cli::gen_prefix(&console::style("SKIP").dim().to_string()),
+            msg
+        ));
+    }
+}
+
+/// Name of a download job in progress events
+fn job_name(job: &DownloadJob) -> String {
+    match &job.filename {
+        Some(name) => name.clone(),
+        None => job.url.rsplit('/').next().unwrap_or(&job.url).to_owned(),
+    }
 }
 
 #[inline]
@@ -343,3 +859,453 @@ fn gen_global_bar_message(total: usize, finished: usize, total_text_len: usize)
     let finished_str = finished.to_string();
     format!("Total Progress: [{: >width$}/{}]", finished_str, total, width = total_text_len)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test::TempDir;
+    use sha2::{Digest, Sha256};
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    /// A tiny HTTP server that serves `content` under `/good/` and 404s everything else
+    fn serve(content: Vec<u8>) -> String {
+        serve_flaky(content, 0)
+    }
+
+    /// Serve `content` under `/good/` with an ETag, answering 304 if the client already has it
+    /// Returns the base url and a counter of full responses
+    fn serve_etag(content: Vec<u8>, etag: &'static str) -> (String, Arc<Mutex<usize>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let full_responses = Arc::new(Mutex::new(0));
+        let counter = full_responses.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut not_modified = false;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    let lower = line.to_ascii_lowercase();
+                    if lower.starts_with("if-none-match:") && line.contains(etag) {
+                        not_modified = true;
+                    }
+                }
+                if not_modified {
+                    write!(stream, "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n")
+                        .unwrap();
+                } else {
+                    *counter.lock().unwrap() += 1;
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nConnection: close\r\nETag: {etag}\r\nContent-Length: {}\r\n\r\n",
+                        content.len()
+                    )
+                    .unwrap();
+                    stream.write_all(&content).unwrap();
+                }
+            }
+        });
+        (format!("http://{addr}"), full_responses)
+    }
+
+    /// Like serve, but the first `failures` requests under `/good/` get a 503
+    fn serve_flaky(content: Vec<u8>, mut failures: usize) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                reader.read_line(&mut request_line).unwrap();
+                // Drain headers
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                }
+                if request_line.starts_with("GET /good/") && failures > 0 {
+                    failures -= 1;
+                    write!(
+                        stream,
+                        "HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+                    )
+                    .unwrap();
+                } else if request_line.starts_with("GET /good/") {
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: {}\r\n\r\n",
+                        content.len()
+                    )
+                    .unwrap();
+                    stream.write_all(&content).unwrap();
+                } else {
+                    write!(
+                        stream,
+                        "HTTP/1.1 404 Not Found\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"
+                    )
+                    .unwrap();
+                }
+            }
+        });
+        format!("http://{addr}")
+    }
+
+    /// Serve `content` under `/good/`, honoring `Range: bytes=N-` requests
+    /// Returns the base url and the Range header of every request, if any
+    fn serve_ranges(content: Vec<u8>) -> (String, Arc<Mutex<Vec<Option<String>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let ranges = Arc::new(Mutex::new(Vec::new()));
+        let requests = ranges.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut range = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                    if line.to_ascii_lowercase().starts_with("range:") {
+                        range = Some(line[6..].trim().to_string());
+                    }
+                }
+                let start = range
+                    .as_ref()
+                    .and_then(|r| r.strip_prefix("bytes="))
+                    .and_then(|r| r.strip_suffix('-'))
+                    .and_then(|pos| pos.parse::<usize>().ok());
+                requests.lock().unwrap().push(range);
+                match start {
+                    Some(start) => {
+                        write!(
+                            stream,
+                            "HTTP/1.1 206 Partial Content\r\nConnection: close\r\nAccept-Ranges: bytes\r\nContent-Range: bytes {start}-{}/{}\r\nContent-Length: {}\r\n\r\n",
+                            content.len() - 1,
+                            content.len(),
+                            content.len() - start
+                        )
+                        .unwrap();
+                        stream.write_all(&content[start..]).unwrap();
+                    }
+                    None => {
+                        write!(
+                            stream,
+                            "HTTP/1.1 200 OK\r\nConnection: close\r\nAccept-Ranges: bytes\r\nContent-Length: {}\r\n\r\n",
+                            content.len()
+                        )
+                        .unwrap();
+                        stream.write_all(&content).unwrap();
+                    }
+                }
+            }
+        });
+        (format!("http://{addr}"), ranges)
+    }
+
+    #[test]
+    fn per_host_limit() {
+        let mut hosts = HostLimiter::new(2);
+        let mut pending: Vec<String> = (0..4)
+            .flat_map(|i| {
+                [format!("https://a.example.org/{i}"), format!("https://b.example.org:8080/{i}")]
+            })
+            .collect();
+        // Start as many as a global limit of 5 allows
+        let mut running = Vec::new();
+        while running.len() < 5 {
+            match hosts.next_job(pending.iter().map(|url| url.as_str())) {
+                Some(i) => {
+                    let url = pending.remove(i);
+                    hosts.start(&url);
+                    running.push(url);
+                }
+                None => break,
+            }
+        }
+        // Two hosts with a cap of 2 each
+        assert_eq!(running.len(), 4);
+        for host in ["a.example.org", "b.example.org:8080"] {
+            assert_eq!(hosts.active[host], 2);
+        }
+
+        // Finishing one frees up a slot for the same host only
+        hosts.finish(&running.remove(0));
+        let i = hosts.next_job(pending.iter().map(|url| url.as_str())).unwrap();
+        assert_eq!(HostLimiter::host(&pending[i]), "a.example.org");
+    }
+
+    /// Collects written bytes for inspection
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn backoff_schedule() {
+        let base = Duration::from_millis(100);
+        for (retry, expected) in [(1, 100), (2, 200), (3, 400)] {
+            let delay = retry_backoff(base, retry);
+            let expected = Duration::from_millis(expected);
+            assert!(delay >= expected && delay <= expected.mul_f64(1.25));
+        }
+        assert_eq!(retry_backoff(base, 100), MAX_RETRY_BACKOFF);
+    }
+
+    #[tokio::test]
+    async fn retry_with_backoff() {
+        let content = b"sasm retry backoff test";
+        let base = serve_flaky(content.to_vec(), 2);
+        let job = DownloadJob {
+            url: format!("{base}/good/test.file"),
+            fallback_urls: Vec::new(),
+            current_url: None,
+            description: None,
+            filename: Some("test.file".to_string()),
+            size: None,
+            compression: Compression::None(None),
+            conditional: false,
+        };
+
+        let download_path = TempDir::new();
+        let start = Instant::now();
+        Downloader::new()
+            .with_retry_backoff(Some(Duration::from_millis(100)))
+            .fetch(vec![job], &download_path, false)
+            .await
+            .unwrap();
+        // Two failures: 100ms, then 200ms
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn not_modified() {
+        let content = b"sasm conditional download test";
+        let (base, full_responses) = serve_etag(content.to_vec(), "\"v1\"");
+        let job = DownloadJob {
+            url: format!("{base}/good/test.db"),
+            fallback_urls: Vec::new(),
+            current_url: None,
+            description: None,
+            filename: Some("test.db".to_string()),
+            size: None,
+            compression: Compression::None(None),
+            conditional: true,
+        };
+
+        let download_path = TempDir::new();
+        let file_path = download_path.join("test.db");
+        let downloader = Downloader::new();
+        downloader.fetch(vec![job.clone()], &download_path, false).await.unwrap();
+        assert_eq!(std::fs::read(&file_path).unwrap(), content);
+        assert_eq!(
+            CacheValidators::load(&file_path).await.unwrap().etag.as_deref(),
+            Some("\"v1\"")
+        );
+
+        // The server answers 304, so the cached file is kept as is
+        downloader.fetch(vec![job], &download_path, false).await.unwrap();
+        assert_eq!(*full_responses.lock().unwrap(), 1);
+        assert_eq!(std::fs::read(&file_path).unwrap(), content);
+    }
+
+    #[test]
+    fn rate_limit() {
+        let limiter = RateLimiter::new(1000);
+        let tolerance = Duration::from_millis(50);
+        // Budget is handed out in order, so the waits add up
+        let first = limiter.reserve(500);
+        assert!(
+            first <= Duration::from_millis(500) && first + tolerance > Duration::from_millis(500)
+        );
+        let second = limiter.reserve(1000);
+        assert!(
+            second <= Duration::from_millis(1500)
+                && second + tolerance > Duration::from_millis(1500)
+        );
+    }
+
+    #[tokio::test]
+    async fn progress_events() {
+        let content = b"sasm progress event test";
+        let base = serve(content.to_vec());
+        let jobs = ["a.file", "b.file"]
+            .into_iter()
+            .map(|name| DownloadJob {
+                url: format!("{base}/good/{name}"),
+                fallback_urls: Vec::new(),
+                current_url: None,
+                description: None,
+                filename: Some(name.to_string()),
+                size: None,
+                compression: Compression::None(None),
+                conditional: false,
+            })
+            .collect();
+
+        let buf = SharedBuf::default();
+        let download_path = TempDir::new();
+        Downloader::new()
+            .with_progress_events(ProgressEvents::new(buf.clone()))
+            .fetch(jobs, &download_path, false)
+            .await
+            .unwrap();
+
+        let out = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let events: Vec<serde_json::Value> =
+            out.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        for name in ["a.file", "b.file"] {
+            let of_file: Vec<&str> = events
+                .iter()
+                .filter(|e| e["file"] == name)
+                .map(|e| e["event"].as_str().unwrap())
+                .filter(|e| *e != "progress")
+                .collect();
+            assert_eq!(of_file, vec!["start", "done"]);
+        }
+        let start = events.iter().find(|e| e["event"] == "start").unwrap();
+        assert_eq!(start["size"], content.len());
+    }
+
+    #[tokio::test]
+    async fn fallback_mirror() {
+        let content = b"sasm mirror failover test";
+        let base = serve(content.to_vec());
+        let checksum = Checksum::from_sha256_str(&hex::encode(Sha256::digest(content))).unwrap();
+
+        let job = DownloadJob {
+            url: format!("{base}/bad/test.file"),
+            fallback_urls: vec![format!("{base}/good/test.file")],
+            current_url: None,
+            description: None,
+            filename: Some("test.file".to_string()),
+            size: Some(content.len() as u64),
+            compression: Compression::None(Some(checksum.clone())),
+            conditional: false,
+        };
+
+        let download_path = TempDir::new();
+        let res = Downloader::new().fetch(vec![job], &download_path, false).await.unwrap();
+        // Results are keyed by the url asked for, not the mirror it came from
+        assert!(!res.contains_key(&format!("{base}/good/test.file")));
+        let path = &res[&format!("{base}/bad/test.file")];
+        assert!(checksum.cmp_file(path).unwrap());
+    }
+
+    #[tokio::test]
+    async fn local_file() {
+        let content = b"sasm local mirror test";
+        let mut encoder = async_compression::tokio::write::GzipEncoder::new(Vec::new());
+        encoder.write_all(content).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        let compressed = encoder.into_inner();
+
+        let mirror_path = TempDir::new();
+        std::fs::write(mirror_path.join("test.gz"), &compressed).unwrap();
+        let url = Url::from_file_path(mirror_path.join("test.gz")).unwrap().to_string();
+
+        let download_checksum =
+            Checksum::from_sha256_str(&hex::encode(Sha256::digest(&compressed))).unwrap();
+        let extracted_checksum =
+            Checksum::from_sha256_str(&hex::encode(Sha256::digest(content))).unwrap();
+        let job = DownloadJob {
+            url: url.clone(),
+            fallback_urls: Vec::new(),
+            current_url: None,
+            description: None,
+            filename: Some("test".to_string()),
+            size: None,
+            compression: Compression::Gzip((
+                Some(download_checksum),
+                Some(extracted_checksum.clone()),
+            )),
+            conditional: false,
+        };
+
+        let download_path = TempDir::new();
+        let res = Downloader::new().fetch(vec![job], &download_path, false).await.unwrap();
+        assert!(extracted_checksum.cmp_file(&res[&url]).unwrap());
+    }
+
+    #[tokio::test]
+    async fn zstd_download() {
+        let content = b"sasm zstd decompression test";
+        let mut encoder = async_compression::tokio::write::ZstdEncoder::new(Vec::new());
+        encoder.write_all(content).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        let compressed = encoder.into_inner();
+
+        let download_checksum =
+            Checksum::from_sha256_str(&hex::encode(Sha256::digest(&compressed))).unwrap();
+        let extracted_checksum =
+            Checksum::from_sha256_str(&hex::encode(Sha256::digest(content))).unwrap();
+        let base = serve(compressed);
+        let job = DownloadJob {
+            url: format!("{base}/good/test.zst"),
+            fallback_urls: Vec::new(),
+            current_url: None,
+            description: None,
+            filename: Some("test".to_string()),
+            size: None,
+            compression: Compression::Zstd((
+                Some(download_checksum),
+                Some(extracted_checksum.clone()),
+            )),
+            conditional: false,
+        };
+
+        let download_path = TempDir::new();
+        let res = Downloader::new().fetch(vec![job], &download_path, false).await.unwrap();
+        let path = &res[&format!("{base}/good/test.zst")];
+        assert!(extracted_checksum.cmp_file(path).unwrap());
+    }
+
+    #[tokio::test]
+    async fn resume_partial() {
+        let content = b"sasm interrupted download resume test";
+        let (base, ranges) = serve_ranges(content.to_vec());
+        let checksum = Checksum::from_sha256_str(&hex::encode(Sha256::digest(content))).unwrap();
+        let job = DownloadJob {
+            url: format!("{base}/good/test.file"),
+            fallback_urls: Vec::new(),
+            current_url: None,
+            description: None,
+            filename: Some("test.file".to_string()),
+            size: None,
+            compression: Compression::None(Some(checksum.clone())),
+            conditional: false,
+        };
+
+        // Left behind by an interrupted download
+        let download_path = TempDir::new();
+        let file_path = download_path.join("test.file");
+        std::fs::write(&file_path, &content[..10]).unwrap();
+
+        Downloader::new().fetch(vec![job], &download_path, false).await.unwrap();
+        assert_eq!(std::fs::read(&file_path).unwrap(), content);
+        assert!(checksum.cmp_file(&file_path).unwrap());
+        // Only the missing part is asked for after the first response
+        assert_eq!(*ranges.lock().unwrap(), vec![None, Some("bytes=10-".to_string())]);
+    }
+}
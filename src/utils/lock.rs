@@ -1,8 +1,18 @@
-use crate::{debug, LOCK_PATH};
+use crate::{debug, info, warn, LOCK_PATH};
 use anyhow::{bail, Context, Result};
-use nix::unistd::Uid;
+use nix::{
+    errno::Errno,
+    sys::signal::kill,
+    unistd::{Pid, Uid},
+};
 use serde::{Deserialize, Serialize};
-use std::{fs, io::prelude::*, path::Path, sync::atomic::Ordering};
+use std::{
+    fs,
+    io::{prelude::*, ErrorKind},
+    path::Path,
+    sync::atomic::Ordering,
+    time::Duration,
+};
 
 /// Make sure only one instance of sasm can run at one time
 
@@ -19,44 +29,112 @@ pub fn ensure_unlocked(root: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Get the PID of the running instance holding the lock, if any
+/// This never modifies the lock, stale locks are only removed when acquiring it
 pub fn check(root: &Path) -> Result<Option<u32>> {
-    let lock_path = root.join(LOCK_PATH);
-    if lock_path.is_file() {
-        let lock_content =
-            std::fs::read_to_string(lock_path).context("Failed to read lock file.")?;
-        let lock_info: LockInfo =
-            toml::from_str(&lock_content).context("Failed to parse lock file.")?;
-        Ok(Some(lock_info.pid))
-    } else {
-        Ok(None)
+    let pid = read_lock(&root.join(LOCK_PATH))?;
+    Ok(pid.filter(|pid| is_running(*pid)))
+}
+
+/// Get the PID recorded in a lock file, or None if there is no lock
+fn read_lock(path: &Path) -> Result<Option<u32>> {
+    let lock_content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("Failed to read lock file."),
+    };
+    let lock_info: LockInfo =
+        toml::from_str(&lock_content).context("Failed to parse lock file.")?;
+    Ok(Some(lock_info.pid))
+}
+
+fn is_running(pid: u32) -> bool {
+    // Signal 0 only checks if the process exists
+    !matches!(kill(Pid::from_raw(pid as i32), None), Err(Errno::ESRCH))
+}
+
+/// Remove a lock left by the no longer running instance at `pid`
+/// Another instance may have replaced the lock since it was read, so the lock is moved aside
+/// first and only deleted if it still belongs to `pid`
+fn remove_stale(path: &Path, pid: u32) -> Result<()> {
+    let aside = path.with_extension(format!("stale.{}", std::process::id()));
+    match fs::rename(path, &aside) {
+        Ok(()) => (),
+        // Someone else got to it first
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).context("Failed to move stale lock file aside."),
+    }
+
+    if read_lock(&aside)? == Some(pid) {
+        warn!(
+            "Removing stale lock left by sasm instance at PID {}, which is no longer running.",
+            pid
+        );
+    } else if let Err(e) = fs::hard_link(&aside, path) {
+        // Put back the lock of the instance that replaced it. Linking never overwrites,
+        // so if yet another instance has locked in the meantime, its lock is kept instead
+        if e.kind() != ErrorKind::AlreadyExists {
+            return Err(e).context("Failed to restore lock file.");
+        }
     }
+    fs::remove_file(&aside).context("Failed to delete stale lock file.")?;
+    Ok(())
 }
 
-pub fn lock(root: &Path) -> Result<()> {
+/// Lock this instance. If `wait` is set, block until other instances release the lock
+pub async fn lock(root: &Path, wait: bool) -> Result<()> {
     // Make sure we are running as root
     if !Uid::effective().is_root() {
         bail!("You must be root to perform this operation.");
     }
 
-    let lock_path = root.join(LOCK_PATH);
-    if lock_path.is_file() {
-        bail!("Failed to create an instance lock because the lock file already exists.");
-    }
-
+    acquire(root, wait).await?;
     // Set global lock parameter
     crate::LOCKED.store(true, Ordering::Relaxed);
+    Ok(())
+}
 
+/// Create the lock file, which fails if another instance already has it
+async fn acquire(root: &Path, wait: bool) -> Result<()> {
+    let lock_path = root.join(LOCK_PATH);
     // Create directory if not created yet
     let prefix = lock_path.parent().unwrap();
     if !prefix.is_dir() {
         fs::create_dir_all(prefix).context("Failed to create directory for lock file.")?;
     }
-    let lock_info = LockInfo { pid: std::process::id() };
-    let lock_content = toml::to_string(&lock_info)?;
-    let mut file = fs::File::create(&lock_path).context("Failed to create lock file.")?;
-    file.write(lock_content.as_bytes())
-        .context("Failed to write instance information to lock file.")?;
-    Ok(())
+
+    let mut waiting = false;
+    loop {
+        match fs::OpenOptions::new().write(true).create_new(true).open(&lock_path) {
+            Ok(mut file) => {
+                let lock_info = LockInfo { pid: std::process::id() };
+                let lock_content = toml::to_string(&lock_info)?;
+                file.write_all(lock_content.as_bytes())
+                    .context("Failed to write instance information to lock file.")?;
+                return Ok(());
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => (),
+            Err(e) => return Err(e).context("Failed to create lock file."),
+        }
+
+        let pid = match read_lock(&lock_path)? {
+            Some(pid) if is_running(pid) => pid,
+            Some(pid) => {
+                remove_stale(&lock_path, pid)?;
+                continue;
+            }
+            // Released. Try again
+            None => continue,
+        };
+        if !wait {
+            bail!("Another instance of sasm is currently running at PID {}.", pid);
+        }
+        if !waiting {
+            info!("Waiting for another instance of sasm at PID {} to finish...", pid);
+            waiting = true;
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
 }
 
 pub fn unlock(root: &Path) -> Result<()> {
@@ -68,3 +146,69 @@ pub fn unlock(root: &Path) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::test::TempDir;
+
+    #[tokio::test]
+    async fn acquire_and_release() {
+        let root = TempDir::new();
+        assert_eq!(check(&root).unwrap(), None);
+
+        acquire(&root, false).await.unwrap();
+        assert_eq!(check(&root).unwrap(), Some(std::process::id()));
+        assert!(ensure_unlocked(&root).is_err());
+        // Someone else has the lock now
+        assert!(acquire(&root, false).await.is_err());
+
+        unlock(&root).unwrap();
+        assert_eq!(check(&root).unwrap(), None);
+        acquire(&root, false).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stale() {
+        let root = TempDir::new();
+        fs::create_dir_all(root.join(LOCK_PATH).parent().unwrap()).unwrap();
+        // No process can have this PID, as it is above the kernel's limit
+        fs::write(root.join(LOCK_PATH), "pid = 2147483647\n").unwrap();
+
+        // Checking leaves the lock alone
+        assert_eq!(check(&root).unwrap(), None);
+        assert!(ensure_unlocked(&root).is_ok());
+        assert!(root.join(LOCK_PATH).is_file());
+
+        acquire(&root, false).await.unwrap();
+        assert_eq!(check(&root).unwrap(), Some(std::process::id()));
+    }
+
+    #[test]
+    fn stale_replaced() {
+        let root = TempDir::new();
+        let path = root.join(LOCK_PATH);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        // Another instance has taken over the stale lock after it was read
+        fs::write(&path, format!("pid = {}\n", std::process::id())).unwrap();
+
+        remove_stale(&path, 2147483647).unwrap();
+        assert_eq!(check(&root).unwrap(), Some(std::process::id()));
+        let files = fs::read_dir(path.parent().unwrap()).unwrap().count();
+        assert_eq!(files, 1);
+    }
+
+    #[tokio::test]
+    async fn wait() {
+        let root = TempDir::new();
+        acquire(&root, false).await.unwrap();
+
+        let path = root.to_path_buf();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            unlock(&path).unwrap();
+        });
+        acquire(&root, true).await.unwrap();
+        assert_eq!(check(&root).unwrap(), Some(std::process::id()));
+    }
+}
@@ -1,5 +1,8 @@
+pub mod atomic;
 pub mod cli;
 pub mod downloader;
 pub mod lock;
 pub mod pacparse;
 pub mod pager;
+#[cfg(test)]
+pub mod test;
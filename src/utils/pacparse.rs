@@ -85,7 +85,7 @@ fn is_package_name_char(c: char) -> bool {
 }
 
 fn is_version_requirement(i: &str) -> bool {
-    i.starts_with(">") || i.starts_with("<") || i.starts_with("=")
+    i.starts_with(">") || i.starts_with("<") || i.starts_with("=") || i.starts_with("!=")
 }
 
 pub fn parse_package_requirement_line(
@@ -125,6 +125,7 @@ fn parse_requirement_description(i: &str) -> IResult<&str, String> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::types::PkgVersion;
 
     #[test]
     fn try_parse() {
@@ -153,4 +154,19 @@ paragraph.
             .unwrap()
         );
     }
+
+    #[test]
+    fn try_parse_requirement_line() {
+        let (_, (name, ver_req, desc)) = parse_package_requirement_line("libfoo>>1:2.3-1").unwrap();
+        assert_eq!(name, "libfoo");
+        assert_eq!(ver_req.lower_bond, Some((PkgVersion::try_from("1:2.3-1").unwrap(), false)));
+        assert_eq!(ver_req.upper_bond, None);
+        assert_eq!(desc, None);
+
+        let (_, (name, ver_req, desc)) =
+            parse_package_requirement_line("libbar<<2.0: for bar support").unwrap();
+        assert_eq!(name, "libbar");
+        assert_eq!(ver_req.upper_bond, Some((PkgVersion::try_from("2.0").unwrap(), false)));
+        assert_eq!(desc, Some("for bar support".to_string()));
+    }
 }